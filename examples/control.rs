@@ -0,0 +1,63 @@
+//! Drives a stapler started with `--control` through its control API, the way an
+//! end to end test would:
+//!
+//! ```text
+//! stapler.exe --control
+//! cargo run --example control -- C:\Windows
+//! ```
+//!
+//! Goes to the path given, or `C:\Windows`, waits for stapler to say it got there,
+//! and prints the columns it's showing.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+/// One pipe connection, answering one request at a time
+struct Client {
+    writer: fs::File,
+    reader: BufReader<fs::File>,
+    next_id: u64,
+}
+
+impl Client {
+    /// Connects to the pipe named in `%LOCALAPPDATA%\stapler\control_pipe.txt`
+    fn connect() -> Result<Client> {
+        let local_app_data = std::env::var_os("LOCALAPPDATA").context("LOCALAPPDATA is not set")?;
+        let name_file = std::path::Path::new(&local_app_data).join("stapler").join("control_pipe.txt");
+        let name = fs::read_to_string(&name_file).with_context(|| format!("reading {}; is stapler running with --control?", name_file.display()))?;
+        let pipe = fs::OpenOptions::new().read(true).write(true).open(name.trim()).with_context(|| format!("connecting to {}", name.trim()))?;
+        Ok(Client { reader: BufReader::new(pipe.try_clone()?), writer: pipe, next_id: 1 })
+    }
+    fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        writeln!(self.writer, "{}", json!({"id": id, "method": method, "params": params}))?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            bail!("stapler hung up");
+        }
+        let mut response: Value = serde_json::from_str(&line).with_context(|| format!("not JSON: {line}"))?;
+        if let Some(error) = response.get("error") {
+            bail!("{method} failed: {error}");
+        }
+        Ok(response["result"].take())
+    }
+}
+
+fn main() -> Result<()> {
+    let path = std::env::args().nth(1).unwrap_or_else(|| r"C:\Windows".to_owned());
+    let mut client = Client::connect()?;
+    client.call("subscribe", json!({}))?;
+    client.call("navigate", json!({"path": path}))?;
+    let event = client.call("next_event", json!({"timeout_ms": 5000}))?;
+    if event.is_null() {
+        bail!("stapler didn't say where it went");
+    }
+    println!("navigated: {event}");
+    let columns = client.call("columns", json!({}))?;
+    println!("{}", serde_json::to_string_pretty(&columns)?);
+    Ok(())
+}
@@ -0,0 +1,197 @@
+//! Files on the clipboard, in the formats Explorer reads and writes, and
+//! text or images from the clipboard written out as new files.
+
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::mem::ManuallyDrop;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use windows::Win32::Foundation::{HANDLE, HWND, TRUE};
+use windows::Win32::System::Com as win32com;
+use windows::Win32::System::DataExchange::{self as win32dataexchange, RegisterClipboardFormatW};
+use windows::Win32::System::Memory as win32memory;
+use windows::Win32::System::Ole as win32ole;
+use windows::Win32::UI::Shell as win32shell;
+
+use crate::journal::Step;
+use crate::{file_operations, ItemId, ShellError};
+
+/// What a paste would put in a folder
+pub enum Contents {
+    /// Files to copy, or to move if they were cut
+    Files {
+        items: win32shell::IShellItemArray,
+        cut: bool,
+    },
+    Text(String),
+    /// A `CF_DIB`, made into a .bmp file
+    Image(Vec<u8>),
+    Nothing,
+}
+
+/// Puts `items` on the clipboard as `CF_HDROP` and a shell ID list, marked
+/// to be moved rather than copied when pasted if `cut` is set
+pub fn put_files(items: &[Rc<ItemId>], cut: bool) -> Result<(), ShellError> {
+    let action = if cut { "cutting" } else { "copying" };
+    unsafe {
+        let data = file_operations::data_object(items).map_err(|e| ShellError::new(action, e.code()))?;
+        let effect = if cut { win32ole::DROPEFFECT_MOVE } else { win32ole::DROPEFFECT_COPY };
+        set_drop_effect(&data, effect).map_err(|e| ShellError::new(action, e.code()))?;
+        win32ole::OleSetClipboard(&data).map_err(|e| ShellError::new(action, e.code()))?;
+        // Renders everything now, so the files stay on the clipboard after we exit
+        win32ole::OleFlushClipboard().map_err(|e| ShellError::new(action, e.code()))?;
+    }
+    log::info!("{} {} items", if cut { "cut" } else { "copied" }, items.len());
+    Ok(())
+}
+
+/// Replaces the clipboard with `text`
+pub fn put_text(owner: HWND, text: &str) -> Result<(), ShellError> {
+    let action = "copying text";
+    let wide: Vec<u16> = text.encode_utf16().chain(Some(0)).collect();
+    unsafe {
+        win32dataexchange::OpenClipboard(owner).map_err(|e| ShellError::new(action, e.code()))?;
+        let result = (|| {
+            win32dataexchange::EmptyClipboard()?;
+            let global = win32memory::GlobalAlloc(win32memory::GMEM_MOVEABLE, wide.len() * 2)?;
+            let pointer = win32memory::GlobalLock(global) as *mut u16;
+            std::ptr::copy_nonoverlapping(wide.as_ptr(), pointer, wide.len());
+            let _ = win32memory::GlobalUnlock(global);
+            // The clipboard owns `global` once this succeeds
+            win32dataexchange::SetClipboardData(win32ole::CF_UNICODETEXT.0 as u32, HANDLE(global.0))?;
+            Ok(())
+        })();
+        let _ = win32dataexchange::CloseClipboard();
+        result.map_err(|e: windows::core::Error| ShellError::new(action, e.code()))
+    }
+}
+
+/// Whatever is on the clipboard that a paste could use, files first
+pub fn contents() -> Result<Contents, ShellError> {
+    unsafe {
+        let data = win32ole::OleGetClipboard().map_err(|e| ShellError::new("reading the clipboard", e.code()))?;
+        if let Ok(items) = win32shell::SHCreateShellItemArrayFromDataObject::<_, win32shell::IShellItemArray>(&data) {
+            let cut = drop_effect(&data) == win32ole::DROPEFFECT_MOVE;
+            return Ok(Contents::Files { items, cut });
+        }
+        if let Some(bytes) = global_bytes(&data, win32ole::CF_UNICODETEXT.0) {
+            let wide: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).take_while(|c| *c != 0).collect();
+            return Ok(Contents::Text(String::from_utf16_lossy(&wide)));
+        }
+        if let Some(dib) = global_bytes(&data, win32ole::CF_DIB.0) {
+            if let Some(bmp) = bmp_from_dib(&dib) {
+                return Ok(Contents::Image(bmp));
+            }
+        }
+        Ok(Contents::Nothing)
+    }
+}
+
+/// Copies or moves the files from `Contents::Files` into `folder`, adding what was done to `done`
+pub fn paste_files(owner: HWND, items: &win32shell::IShellItemArray, folder: &win32shell::IShellItem, cut: bool, done: &mut Vec<Step>) -> windows::core::Result<()> {
+    file_operations::transfer(owner, items, folder, cut, done)?;
+    if cut {
+        // Cut files can only be pasted once
+        let _ = unsafe { win32ole::OleSetClipboard(None) };
+    }
+    Ok(())
+}
+
+/// Writes `bytes` to `stem.extension` in `folder`, or `stem (2).extension`
+/// and so on if that's taken
+pub fn write_new_file(folder: &Path, stem: &str, extension: &str, bytes: &[u8]) -> std::io::Result<PathBuf> {
+    for n in 1.. {
+        let name = if n == 1 { format!("{stem}.{extension}") } else { format!("{stem} ({n}).{extension}") };
+        let path = folder.join(name);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(bytes)?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+fn preferred_drop_effect_format() -> win32com::FORMATETC {
+    win32com::FORMATETC {
+        cfFormat: unsafe { RegisterClipboardFormatW(win32shell::CFSTR_PREFERREDDROPEFFECT) } as u16,
+        ptd: std::ptr::null_mut(),
+        dwAspect: win32com::DVASPECT_CONTENT.0,
+        lindex: -1,
+        tymed: win32com::TYMED_HGLOBAL.0 as u32,
+    }
+}
+
+unsafe fn set_drop_effect(data: &win32com::IDataObject, effect: win32ole::DROPEFFECT) -> windows::core::Result<()> {
+    let global = win32memory::GlobalAlloc(win32memory::GMEM_MOVEABLE, std::mem::size_of::<u32>())?;
+    *(win32memory::GlobalLock(global) as *mut u32) = effect.0;
+    let _ = win32memory::GlobalUnlock(global);
+    let medium = win32com::STGMEDIUM {
+        tymed: win32com::TYMED_HGLOBAL.0 as u32,
+        u: win32com::STGMEDIUM_0 { hGlobal: global },
+        pUnkForRelease: ManuallyDrop::new(None),
+    };
+    // The data object owns `global` from here on
+    data.SetData(&preferred_drop_effect_format(), &medium, TRUE)
+}
+
+/// Whether the files on the clipboard were cut or copied
+unsafe fn drop_effect(data: &win32com::IDataObject) -> win32ole::DROPEFFECT {
+    let Ok(mut medium) = data.GetData(&preferred_drop_effect_format()) else {
+        return win32ole::DROPEFFECT_COPY;
+    };
+    let pointer = win32memory::GlobalLock(medium.u.hGlobal) as *const u32;
+    let effect = if pointer.is_null() { win32ole::DROPEFFECT_COPY } else { win32ole::DROPEFFECT(*pointer) };
+    let _ = win32memory::GlobalUnlock(medium.u.hGlobal);
+    win32ole::ReleaseStgMedium(&mut medium);
+    effect
+}
+
+/// A copy of the clipboard's `HGLOBAL` in `format`, if it has one
+unsafe fn global_bytes(data: &win32com::IDataObject, format: u16) -> Option<Vec<u8>> {
+    let format = win32com::FORMATETC {
+        cfFormat: format,
+        ptd: std::ptr::null_mut(),
+        dwAspect: win32com::DVASPECT_CONTENT.0,
+        lindex: -1,
+        tymed: win32com::TYMED_HGLOBAL.0 as u32,
+    };
+    let mut medium = data.GetData(&format).ok()?;
+    let size = win32memory::GlobalSize(medium.u.hGlobal);
+    let pointer = win32memory::GlobalLock(medium.u.hGlobal) as *const u8;
+    let bytes = (!pointer.is_null()).then(|| std::slice::from_raw_parts(pointer, size).to_vec());
+    let _ = win32memory::GlobalUnlock(medium.u.hGlobal);
+    win32ole::ReleaseStgMedium(&mut medium);
+    bytes
+}
+
+/// Puts a `BITMAPFILEHEADER` in front of a packed DIB
+fn bmp_from_dib(dib: &[u8]) -> Option<Vec<u8>> {
+    let u32_at = |offset: usize| Some(u32::from_le_bytes(dib.get(offset..offset + 4)?.try_into().ok()?));
+    let header_size = u32_at(0)?;
+    let bit_count = u16::from_le_bytes(dib.get(14..16)?.try_into().ok()?);
+    let compression = u32_at(16)?;
+    let colors_used = u32_at(32)?;
+    let colors = if colors_used > 0 {
+        colors_used
+    } else if bit_count <= 8 {
+        1 << bit_count
+    } else {
+        0
+    };
+    // BI_BITFIELDS masks follow a plain BITMAPINFOHEADER
+    let masks = if compression == 3 && header_size == 40 { 12 } else { 0 };
+    let bits_offset = 14 + header_size + masks + colors * 4;
+    let file_size = 14 + TryInto::<u32>::try_into(dib.len()).ok()?;
+    let mut bmp = Vec::with_capacity(file_size as usize);
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&file_size.to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]);
+    bmp.extend_from_slice(&bits_offset.to_le_bytes());
+    bmp.extend_from_slice(dib);
+    Some(bmp)
+}
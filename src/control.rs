@@ -0,0 +1,196 @@
+//! A JSON API on a named pipe, started by `--control`, for scripts and end to
+//! end tests to drive stapler with.
+//!
+//! Clients write one request per line, `{"id": 1, "method": "navigate", "params": {"path": "C:\\Windows"}}`,
+//! and get one line back for each, `{"id": 1, "result": ...}` or `{"id": 1, "error": "..."}`.
+//! Everything but `subscribe` and `next_event` is answered by `StaplerApp::on_control_notice`.
+//! The pipe's name is in `%LOCALAPPDATA%\stapler\control_pipe.txt` while it's served.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Duration;
+
+use serde_json::{Value, json};
+use windows::Win32::Foundation::{ERROR_PIPE_CONNECTED, HANDLE};
+use windows::Win32::Storage::FileSystem as win32fs;
+use windows::Win32::System::Pipes as win32pipes;
+use windows::core::HSTRING;
+
+use crate::user_pipes;
+
+const PIPE_PREFIX: &str = "stapler-control";
+
+/// A request for the UI thread, waiting for its answer
+pub struct Call {
+    pub method: String,
+    pub params: Value,
+    reply: mpsc::Sender<Result<Value, String>>,
+}
+
+impl Call {
+    pub fn reply(self, result: Result<Value, String>) {
+        // The client may have hung up in the meantime
+        let _ = self.reply.send(result);
+    }
+}
+
+pub struct Server {
+    calls: Arc<Mutex<Vec<Call>>>,
+    /// One for each client that asked for events
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<Value>>>>,
+}
+
+impl Server {
+    /// Listens on the pipe, firing `notice` whenever there are calls to take
+    pub fn start(notice: nwg::NoticeSender) -> Server {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
+        let (calls_, subscribers_) = (calls.clone(), subscribers.clone());
+        let spawned = std::thread::Builder::new().name("control".into()).spawn(move || {
+            let mut first = true;
+            loop {
+                let pipe = match create_pipe(first) {
+                    Ok(pipe) => pipe,
+                    Err(e) => {
+                        log::error!("stopped serving the control API: {}", e.message().trim());
+                        return;
+                    }
+                };
+                if first {
+                    publish_pipe_name();
+                    first = false;
+                }
+                if let Err(e) = unsafe { win32pipes::ConnectNamedPipe(HANDLE(pipe.as_raw_handle()), None) } {
+                    if e.code() != ERROR_PIPE_CONNECTED.to_hresult() {
+                        log::warn!("a control client could not connect: {}", e.message().trim());
+                        continue;
+                    }
+                }
+                let (calls, subscribers) = (calls_.clone(), subscribers_.clone());
+                let spawned = std::thread::Builder::new()
+                    .name("control client".into())
+                    .spawn(move || serve(fs::File::from(pipe), &calls, &subscribers, notice));
+                if let Err(e) = spawned {
+                    log::error!("could not serve a control client: {e}");
+                }
+            }
+        });
+        if let Err(e) = spawned {
+            log::error!("could not start the control API: {e}");
+        }
+        Server { calls, subscribers }
+    }
+    pub fn take_calls(&self) -> Vec<Call> {
+        std::mem::take(&mut *self.calls.lock().unwrap())
+    }
+    /// Sends `event` to every client that subscribed, forgetting those that have gone
+    pub fn publish(&self, event: Value) {
+        self.subscribers.lock().unwrap().retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+/// Answers one client until it hangs up. Only one thing is ever read or written at a
+/// time, as synchronous reads and writes on the same pipe would wait for each other.
+fn serve(pipe: fs::File, calls: &Mutex<Vec<Call>>, subscribers: &Mutex<Vec<mpsc::Sender<Value>>>, notice: nwg::NoticeSender) {
+    let mut writer = match pipe.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            log::warn!("could not answer a control client: {e}");
+            return;
+        }
+    };
+    let mut events = None;
+    for line in BufReader::new(pipe).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                if writeln!(writer, "{}", json!({"id": null, "error": format!("not JSON: {e}")})).is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = request.get("method").and_then(Value::as_str).unwrap_or_default().to_owned();
+        let params = request.get("params").cloned().unwrap_or_else(|| json!({}));
+        log::debug!("control call {method} {params}");
+        let result = match method.as_str() {
+            "subscribe" => {
+                let (sender, receiver) = mpsc::channel();
+                subscribers.lock().unwrap().push(sender);
+                events = Some(receiver);
+                Ok(Value::Bool(true))
+            }
+            "next_event" => match &events {
+                None => Err("subscribe first".to_owned()),
+                // Null if nothing happened in time
+                Some(events) => Ok(match params.get("timeout_ms").and_then(Value::as_u64) {
+                    Some(timeout) => events.recv_timeout(Duration::from_millis(timeout)).unwrap_or(Value::Null),
+                    None => events.recv().unwrap_or(Value::Null),
+                }),
+            },
+            _ => {
+                let (reply, replied) = mpsc::channel();
+                calls.lock().unwrap().push(Call { method, params, reply });
+                notice.notice();
+                replied.recv().unwrap_or_else(|_| Err("stapler is closing".to_owned()))
+            }
+        };
+        let response = match result {
+            Ok(result) => json!({"id": id, "result": result}),
+            Err(error) => json!({"id": id, "error": error}),
+        };
+        if writeln!(writer, "{response}").is_err() {
+            break;
+        }
+    }
+}
+
+/// An instance of the pipe for the next client. Only the first may make the pipe,
+/// so two staplers don't end up answering the same clients.
+fn create_pipe(first: bool) -> windows::core::Result<OwnedHandle> {
+    let security = user_pipes::Security::new()?;
+    let attributes = security.attributes();
+    let mut open_mode = win32fs::PIPE_ACCESS_DUPLEX;
+    if first {
+        open_mode |= win32fs::FILE_FLAG_FIRST_PIPE_INSTANCE;
+    }
+    let pipe = unsafe {
+        win32pipes::CreateNamedPipeW(
+            &HSTRING::from(user_pipes::name(PIPE_PREFIX)?),
+            open_mode,
+            win32pipes::PIPE_TYPE_BYTE | win32pipes::PIPE_READMODE_BYTE | win32pipes::PIPE_WAIT | win32pipes::PIPE_REJECT_REMOTE_CLIENTS,
+            win32pipes::PIPE_UNLIMITED_INSTANCES,
+            4096,
+            4096,
+            0,
+            Some(&attributes),
+        )
+    };
+    if pipe.is_invalid() {
+        return Err(windows::core::Error::from_win32());
+    }
+    Ok(unsafe { OwnedHandle::from_raw_handle(pipe.0) })
+}
+
+/// Writes where the pipe is to `%LOCALAPPDATA%\stapler\control_pipe.txt`, as it has the
+/// user's SID and logon session in its name, which clients can't easily work out
+fn publish_pipe_name() {
+    let written = user_pipes::name(PIPE_PREFIX)
+        .map_err(anyhow::Error::from)
+        .and_then(|name| {
+            log::info!("serving the control API on {name}");
+            Ok(fs::write(crate::app_data_dir()?.join("control_pipe.txt"), name)?)
+        });
+    if let Err(e) = written {
+        log::warn!("could not tell clients where the control API is: {e:#}");
+    }
+}
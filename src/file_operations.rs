@@ -0,0 +1,251 @@
+//! Copying, moving, renaming, zipping and dragging sets of items, through the shell
+//! where it has a way to do it, so conflicts and progress look like Explorer's.
+//! What the shell reports doing is kept as `journal::Step`s, for undoing.
+
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::os::windows::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+use std::rc::Rc;
+
+use windows::core::{implement, w, HRESULT, HSTRING, PCWSTR};
+use windows::Win32::Foundation::{ERROR_CANCELLED, ERROR_FILE_NOT_FOUND, HANDLE, HWND};
+use windows::Win32::System::Com::{self as win32com, CoTaskMemFree};
+use windows::Win32::System::Ole as win32ole;
+use windows::Win32::UI::Shell as win32shell;
+use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+use crate::journal::Step;
+use crate::ItemId;
+
+/// Keeps `tar.exe` from flashing up a console
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+pub fn shell_item_array(items: &[Rc<ItemId>]) -> windows::core::Result<win32shell::IShellItemArray> {
+    let pidls: Vec<_> = items.iter().map(|itemid| itemid.0).collect();
+    unsafe { win32shell::SHCreateShellItemArrayFromIDLists(&pidls) }
+}
+
+/// The items as `CF_HDROP` and a shell ID list, for the clipboard or a drag
+pub fn data_object(items: &[Rc<ItemId>]) -> windows::core::Result<win32com::IDataObject> {
+    unsafe { shell_item_array(items)?.BindToHandler(None, &win32shell::BHID_DataObject) }
+}
+
+/// Copies or moves `items` into `folder`, asking about conflicts the way Explorer does.
+/// What was actually done is added to `done`, even if it stopped part way.
+pub fn transfer(owner: HWND, items: &win32shell::IShellItemArray, folder: &win32shell::IShellItem, move_items: bool, done: &mut Vec<Step>) -> windows::core::Result<()> {
+    perform(owner, win32shell::FOF_ALLOWUNDO | win32shell::FOF_NOCONFIRMMKDIR, done, |operation| unsafe {
+        if move_items {
+            operation.MoveItems(items, folder)
+        } else {
+            operation.CopyItems(items, folder)
+        }
+    })
+}
+
+/// Moves one item into `folder` as `name`, for putting things back
+pub fn move_item(owner: HWND, item: &win32shell::IShellItem, folder: &win32shell::IShellItem, name: &str, done: &mut Vec<Step>) -> windows::core::Result<()> {
+    // Quietly, as the user asked for it to go back where it was
+    perform(owner, win32shell::FOF_ALLOWUNDO | win32shell::FOF_NOCONFIRMMKDIR | win32shell::FOF_NOCONFIRMATION, done, |operation| unsafe {
+        operation.MoveItem(item, folder, &HSTRING::from(name), None)
+    })
+}
+
+/// Sends `items` to the Recycle Bin, confirming first the way Explorer does
+pub fn delete(owner: HWND, items: &win32shell::IShellItemArray, done: &mut Vec<Step>) -> windows::core::Result<()> {
+    perform(owner, win32shell::FOF_ALLOWUNDO, done, |operation| unsafe { operation.DeleteItems(items) })
+}
+
+/// Sends one item to the Recycle Bin without asking, for undoing its creation
+pub fn recycle(owner: HWND, item: &win32shell::IShellItem, done: &mut Vec<Step>) -> windows::core::Result<()> {
+    perform(owner, win32shell::FOF_ALLOWUNDO | win32shell::FOF_NOCONFIRMATION, done, |operation| unsafe { operation.DeleteItem(item, None) })
+}
+
+/// Puts an item back from the Recycle Bin where it was deleted from, with the bin's own
+/// Restore, so the bin forgets about it too. `recycled` is its file in the bin.
+pub fn restore(owner: HWND, recycled: &str) -> windows::core::Result<()> {
+    unsafe {
+        let bin: win32shell::IShellItem = win32shell::SHGetKnownFolderItem(&win32shell::FOLDERID_RecycleBinFolder, win32shell::KF_FLAG_DEFAULT, HANDLE::default())?;
+        let items: win32shell::IEnumShellItems = bin.BindToHandler(None, &win32shell::BHID_EnumItems)?;
+        let mut item = [None];
+        let mut fetched = 0;
+        loop {
+            items.Next(&mut item, Some(&mut fetched))?;
+            let Some(found) = item[0].take().filter(|_| fetched == 1) else {
+                return Err(ERROR_FILE_NOT_FOUND.to_hresult().into());
+            };
+            if !parsing_name(&found).is_some_and(|name| name.eq_ignore_ascii_case(recycled)) {
+                continue;
+            }
+            // Through the bin's id list, as its items' context menus are what offer `undelete`
+            let pidl = win32shell::SHGetIDListFromObject(&found)?;
+            let mut info = win32shell::SHELLEXECUTEINFOW {
+                cbSize: std::mem::size_of::<win32shell::SHELLEXECUTEINFOW>() as u32,
+                fMask: win32shell::SEE_MASK_INVOKEIDLIST | win32shell::SEE_MASK_FLAG_NO_UI,
+                hwnd: owner,
+                lpVerb: w!("undelete"),
+                lpIDList: pidl as *mut c_void,
+                nShow: SW_SHOWNORMAL.0,
+                ..Default::default()
+            };
+            let result = win32shell::ShellExecuteExW(&mut info);
+            CoTaskMemFree(Some(pidl as *const c_void));
+            return result;
+        }
+    }
+}
+
+/// Renames `item` in its folder
+pub fn rename(owner: HWND, item: &win32shell::IShellItem, name: &str, done: &mut Vec<Step>) -> windows::core::Result<()> {
+    perform(owner, win32shell::FOF_ALLOWUNDO, done, |operation| unsafe { operation.RenameItem(item, &HSTRING::from(name), None) })
+}
+
+/// Runs the operations `queue` sets up, recording each item's fate in `done`.
+/// `ERROR_CANCELLED` if the user stopped it part way.
+fn perform(
+    owner: HWND,
+    flags: win32shell::FILEOPERATION_FLAGS,
+    done: &mut Vec<Step>,
+    queue: impl FnOnce(&win32shell::IFileOperation) -> windows::core::Result<()>,
+) -> windows::core::Result<()> {
+    let steps = Rc::new(RefCell::new(Vec::new()));
+    let result = unsafe {
+        (|| {
+            let operation: win32shell::IFileOperation = win32com::CoCreateInstance(&win32shell::FileOperation, None, win32com::CLSCTX_ALL)?;
+            operation.SetOwnerWindow(owner)?;
+            operation.SetOperationFlags(flags)?;
+            let sink: win32shell::IFileOperationProgressSink = Recorder { steps: steps.clone() }.into();
+            let cookie = operation.Advise(&sink)?;
+            queue(&operation)?;
+            let result = operation.PerformOperations();
+            let _ = operation.Unadvise(cookie);
+            result?;
+            // Cancelling in the progress dialog still succeeds, with whatever was done by then
+            if operation.GetAnyOperationsAborted()?.as_bool() {
+                return Err(ERROR_CANCELLED.to_hresult().into());
+            }
+            Ok(())
+        })()
+    };
+    done.append(&mut steps.borrow_mut());
+    result
+}
+
+/// Hears from `IFileOperation` about each item it's done with
+#[implement(win32shell::IFileOperationProgressSink)]
+struct Recorder {
+    steps: Rc<RefCell<Vec<Step>>>,
+}
+
+impl Recorder_Impl {
+    fn moved(&self, item: Option<&win32shell::IShellItem>, result: HRESULT, created: Option<&win32shell::IShellItem>) {
+        if let (true, Some(from), Some(to)) = (result.is_ok(), item.and_then(parsing_name), created.and_then(parsing_name)) {
+            self.steps.borrow_mut().push(Step::Moved { from, to });
+        }
+    }
+    fn created(&self, result: HRESULT, created: Option<&win32shell::IShellItem>) {
+        if let (true, Some(path)) = (result.is_ok(), created.and_then(parsing_name)) {
+            self.steps.borrow_mut().push(Step::Created { path });
+        }
+    }
+}
+
+impl win32shell::IFileOperationProgressSink_Impl for Recorder_Impl {
+    fn StartOperations(&self) -> windows::core::Result<()> {
+        Ok(())
+    }
+    fn FinishOperations(&self, _result: HRESULT) -> windows::core::Result<()> {
+        Ok(())
+    }
+    fn PreRenameItem(&self, _flags: u32, _item: Option<&win32shell::IShellItem>, _new_name: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+    fn PostRenameItem(&self, _flags: u32, item: Option<&win32shell::IShellItem>, _new_name: &PCWSTR, result: HRESULT, created: Option<&win32shell::IShellItem>) -> windows::core::Result<()> {
+        self.moved(item, result, created);
+        Ok(())
+    }
+    fn PreMoveItem(&self, _flags: u32, _item: Option<&win32shell::IShellItem>, _folder: Option<&win32shell::IShellItem>, _new_name: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+    fn PostMoveItem(&self, _flags: u32, item: Option<&win32shell::IShellItem>, _folder: Option<&win32shell::IShellItem>, _new_name: &PCWSTR, result: HRESULT, created: Option<&win32shell::IShellItem>) -> windows::core::Result<()> {
+        self.moved(item, result, created);
+        Ok(())
+    }
+    fn PreCopyItem(&self, _flags: u32, _item: Option<&win32shell::IShellItem>, _folder: Option<&win32shell::IShellItem>, _new_name: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+    fn PostCopyItem(&self, _flags: u32, _item: Option<&win32shell::IShellItem>, _folder: Option<&win32shell::IShellItem>, _new_name: &PCWSTR, result: HRESULT, created: Option<&win32shell::IShellItem>) -> windows::core::Result<()> {
+        self.created(result, created);
+        Ok(())
+    }
+    fn PreDeleteItem(&self, _flags: u32, _item: Option<&win32shell::IShellItem>) -> windows::core::Result<()> {
+        Ok(())
+    }
+    fn PostDeleteItem(&self, _flags: u32, item: Option<&win32shell::IShellItem>, result: HRESULT, recycled: Option<&win32shell::IShellItem>) -> windows::core::Result<()> {
+        let from = item.and_then(parsing_name);
+        match (result.is_ok(), from, recycled.and_then(parsing_name)) {
+            (true, Some(from), Some(recycled)) => self.steps.borrow_mut().push(Step::Recycled { from, recycled }),
+            // Too big for the Recycle Bin, or on a drive without one
+            (true, Some(from), None) => log::warn!("{from} was deleted for good, so it can't be undone"),
+            _ => {}
+        }
+        Ok(())
+    }
+    fn PreNewItem(&self, _flags: u32, _folder: Option<&win32shell::IShellItem>, _new_name: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+    fn PostNewItem(&self, _flags: u32, _folder: Option<&win32shell::IShellItem>, _new_name: &PCWSTR, _template: &PCWSTR, _attributes: u32, result: HRESULT, created: Option<&win32shell::IShellItem>) -> windows::core::Result<()> {
+        self.created(result, created);
+        Ok(())
+    }
+    fn UpdateProgress(&self, _total: u32, _so_far: u32) -> windows::core::Result<()> {
+        Ok(())
+    }
+    fn ResetTimer(&self) -> windows::core::Result<()> {
+        Ok(())
+    }
+    fn PauseTimer(&self) -> windows::core::Result<()> {
+        Ok(())
+    }
+    fn ResumeTimer(&self) -> windows::core::Result<()> {
+        Ok(())
+    }
+}
+
+fn parsing_name(item: &win32shell::IShellItem) -> Option<String> {
+    unsafe {
+        let name = item.GetDisplayName(win32shell::SIGDN_DESKTOPABSOLUTEPARSING).ok()?;
+        let result = name.to_string().ok();
+        CoTaskMemFree(Some(name.0 as *const c_void));
+        result
+    }
+}
+
+/// Lets the user drag `items` out to Explorer or anywhere else that takes files.
+/// Returns once they've been dropped, or the drag was cancelled.
+pub fn drag(owner: HWND, items: &[Rc<ItemId>]) -> windows::core::Result<win32ole::DROPEFFECT> {
+    let data = data_object(items)?;
+    let effects = win32ole::DROPEFFECT_COPY | win32ole::DROPEFFECT_MOVE | win32ole::DROPEFFECT_LINK;
+    // No drop source of our own, so the shell's default one is used
+    unsafe { win32shell::SHDoDragDrop(owner, &data, None, effects) }
+}
+
+/// Zips file system `paths` into `zip` with the `tar.exe` that comes with
+/// Windows, keeping each item's name but not the folder it came from
+pub fn zip(paths: &[String], zip: &Path) -> anyhow::Result<()> {
+    let mut command = Command::new("tar.exe");
+    command.arg("-a").arg("-c").arg("-f").arg(zip);
+    for path in paths {
+        let path = Path::new(path);
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            anyhow::bail!("{} is not a file system path", path.display());
+        };
+        command.arg("-C").arg(parent).arg(name);
+    }
+    let output = command.creation_flags(CREATE_NO_WINDOW).output()?;
+    if !output.status.success() {
+        anyhow::bail!("tar failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
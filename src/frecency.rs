@@ -0,0 +1,176 @@
+//! How often and how lately each folder was opened, remembered across runs in
+//! `%LOCALAPPDATA%\stapler\frecency.tsv`, so the jump dialog, `--jump` and
+//! Recent folders can offer the likeliest ones first.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Once the ranks add up to more than this, they're all scaled down, so old favourites fade
+const MAX_TOTAL_RANK: f64 = 10_000.0;
+
+/// The least time between saves while switching folders; the rest waits for `Frecency::flush`
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Visits {
+    /// One for each visit, less after aging
+    rank: f64,
+    /// Seconds since the Unix epoch
+    last: u64,
+}
+
+impl Visits {
+    fn score(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last);
+        let recency = match age {
+            0..3_600 => 4.0,
+            3_600..86_400 => 2.0,
+            86_400..604_800 => 0.5,
+            _ => 0.25,
+        };
+        self.rank * recency
+    }
+}
+
+#[derive(Default)]
+pub struct Frecency {
+    /// By `SIGDN_DESKTOPABSOLUTEPARSING`
+    folders: HashMap<String, Visits>,
+    /// Whether there are visits that aren't saved yet
+    dirty: bool,
+    last_saved: Option<Instant>,
+}
+
+impl Frecency {
+    /// Reads the saved visits, if there are any
+    pub fn load() -> Frecency {
+        let mut frecency = Frecency::default();
+        let text = match path().and_then(|path| Ok(fs::read_to_string(path)?)) {
+            Ok(text) => text,
+            Err(e) => {
+                log::debug!("no saved folder visits: {e:#}");
+                return frecency;
+            }
+        };
+        for line in text.lines() {
+            let mut fields = line.splitn(3, '\t');
+            match (fields.next().map(str::parse::<f64>), fields.next().map(str::parse::<u64>), fields.next()) {
+                (Some(Ok(rank)), Some(Ok(last)), Some(folder)) => {
+                    frecency.folders.insert(folder.to_owned(), Visits { rank, last });
+                }
+                _ => log::warn!("skipping bad line in the folder visits: {line:?}"),
+            }
+        }
+        frecency
+    }
+    /// Counts a visit to `folder`
+    pub fn visit(&mut self, folder: &str) {
+        let now = now();
+        let visits = self.folders.entry(folder.to_owned()).or_insert(Visits { rank: 0.0, last: now });
+        visits.rank += 1.0;
+        visits.last = now;
+        let total: f64 = self.folders.values().map(|visits| visits.rank).sum();
+        if total > MAX_TOTAL_RANK {
+            let scale = 0.9 * MAX_TOTAL_RANK / total;
+            for visits in self.folders.values_mut() {
+                visits.rank *= scale;
+            }
+            self.folders.retain(|_, visits| visits.rank >= 1.0);
+        }
+        self.changed();
+    }
+    /// Stops offering `folder`, after it turned out to be gone
+    pub fn forget(&mut self, folder: &str) {
+        if self.folders.remove(folder).is_some() {
+            self.changed();
+        }
+    }
+    /// Saves whatever hasn't been saved yet, before exiting
+    pub fn flush(&mut self) {
+        if self.dirty {
+            self.save();
+        }
+    }
+    /// Every folder visited, likeliest first
+    pub fn ranked(&self) -> Vec<String> {
+        self.find("")
+    }
+    /// The folders matching `query`, likeliest first. Like zoxide, each word of
+    /// the query has to be in the path, in order, and the last in the folder's own name.
+    pub fn find(&self, query: &str) -> Vec<String> {
+        let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        let now = now();
+        let mut found: Vec<(f64, &String)> = self
+            .folders
+            .iter()
+            .filter(|(folder, _)| matches(&words, folder))
+            .map(|(folder, visits)| (visits.score(now), folder))
+            .collect();
+        found.sort_by(|a, b| b.0.total_cmp(&a.0));
+        found.into_iter().map(|(_, folder)| folder.clone()).collect()
+    }
+    /// Saves now and then, rather than on every switch
+    fn changed(&mut self) {
+        self.dirty = true;
+        if self.last_saved.is_none_or(|saved| saved.elapsed() >= SAVE_INTERVAL) {
+            self.save();
+        }
+    }
+    fn save(&mut self) {
+        self.dirty = false;
+        self.last_saved = Some(Instant::now());
+        let text: String = self.folders.iter().map(|(folder, visits)| format!("{}\t{}\t{folder}\r\n", visits.rank, visits.last)).collect();
+        if let Err(e) = path().and_then(|path| Ok(fs::write(path, text)?)) {
+            log::warn!("could not save the folder visits: {e:#}");
+        }
+    }
+}
+
+fn matches(words: &[String], folder: &str) -> bool {
+    let Some((last, leading)) = words.split_last() else {
+        return true;
+    };
+    let folder = folder.to_lowercase();
+    let mut rest = folder.as_str();
+    for word in leading {
+        match rest.find(word.as_str()) {
+            Some(at) => rest = &rest[at + word.len()..],
+            None => return false,
+        }
+    }
+    // The last word's latest place, which mustn't have another folder after it
+    rest.rfind(last.as_str()).is_some_and(|at| !rest[at + last.len()..].contains('\\'))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+fn path() -> anyhow::Result<PathBuf> {
+    Ok(crate::app_data_dir()?.join("frecency.tsv"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(query: &str) -> Vec<String> {
+        query.split_whitespace().map(str::to_lowercase).collect()
+    }
+
+    #[test]
+    fn words_in_order() {
+        assert!(matches(&words("proj stap"), r"C:\Projects\stapler"));
+        assert!(matches(&words("PROJ"), r"C:\Projects"));
+        assert!(!matches(&words("stap proj"), r"C:\Projects\stapler"));
+        assert!(matches(&words(""), r"C:\anything"));
+    }
+
+    #[test]
+    fn last_word_in_the_name() {
+        assert!(!matches(&words("proj"), r"C:\Projects\stapler"));
+        assert!(matches(&words("src"), r"C:\src\stapler\src"));
+        assert!(!matches(&words("c: src"), r"C:\src\stapler"));
+    }
+}
@@ -0,0 +1,194 @@
+//! System image list lookups, done on a worker thread so that slow icon
+//! handlers and network shares don't hold up listing a folder.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+use windows::core::{HSTRING, PCWSTR};
+use windows::Win32::Storage::FileSystem::{FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_NORMAL, FILE_FLAGS_AND_ATTRIBUTES};
+use windows::Win32::System::Com::{CoInitializeEx, COINIT_APARTMENTTHREADED, COINIT_DISABLE_OLE1DDE};
+use windows::Win32::System::SystemServices::{SFGAO_FILESYSTEM, SFGAO_FOLDER, SFGAO_STREAM};
+use windows::Win32::UI::Shell as win32shell;
+
+use crate::ItemId;
+
+/// Extensions whose files can each have their own icon, so they can't share a cache entry
+const PER_FILE_EXTENSIONS: &[&str] = &["exe", "ico", "lnk", "url", "cur", "ani", "msc", "scr", "cpl", "appref-ms", "website"];
+
+/// One row waiting for its icon
+struct Job {
+    /// The list view the row is in, as an `HWND`
+    list_view: usize,
+    /// The `IconCache::begin` this row was requested under
+    generation: u64,
+    row: usize,
+    /// If `Some`, the row's icon only depends on this
+    extension: Option<String>,
+    /// `ItemId::as_bytes`, used when `extension` is `None`
+    itemid: Vec<u8>,
+}
+
+/// An icon that has come back from the worker
+pub struct Resolved {
+    pub list_view: usize,
+    pub generation: u64,
+    pub row: usize,
+    pub icon: i32,
+}
+
+pub struct IconCache {
+    /// Icons for files that look the same as every other file with their extension
+    by_extension: RefCell<HashMap<String, i32>>,
+    /// Shown until the real icon is known
+    generic_file: i32,
+    generic_folder: i32,
+    jobs: mpsc::Sender<Job>,
+    /// Filled by the worker, drained by `take_resolved`
+    resolved: Arc<Mutex<Vec<(Resolved, Option<String>)>>>,
+    /// The live generation of each list view. Jobs from older ones get skipped.
+    generations: Arc<Mutex<HashMap<usize, u64>>>,
+}
+
+impl IconCache {
+    /// Starts the worker. `notice` fires whenever `take_resolved` has something.
+    pub fn new(notice: nwg::NoticeSender) -> IconCache {
+        let (jobs, receiver) = mpsc::channel();
+        let resolved = Arc::new(Mutex::new(Vec::new()));
+        let generations = Arc::new(Mutex::new(HashMap::new()));
+        let resolved_ = resolved.clone();
+        let generations_ = generations.clone();
+        std::thread::Builder::new()
+            .name("icons".into())
+            .spawn(move || work(receiver, resolved_, generations_, notice))
+            .expect("failed to start the icon thread");
+        IconCache {
+            by_extension: RefCell::new(HashMap::new()),
+            generic_file: by_attributes(&HSTRING::from("file"), FILE_ATTRIBUTE_NORMAL).unwrap_or(0),
+            generic_folder: by_attributes(&HSTRING::from("folder"), FILE_ATTRIBUTE_DIRECTORY).unwrap_or(3),
+            jobs,
+            resolved,
+            generations,
+        }
+    }
+    /// Forgets every row queued for `list_view`, and returns the generation to queue new ones under
+    pub fn begin(&self, list_view: usize) -> u64 {
+        let mut generations = self.generations.lock().unwrap();
+        let generation = generations.get(&list_view).map_or(0, |generation| generation + 1);
+        generations.insert(list_view, generation);
+        generation
+    }
+    /// The icon to show for a row right now. If it's only a placeholder,
+    /// the real one will come back through `take_resolved`.
+    pub fn lookup(&self, list_view: usize, generation: u64, row: usize, item: &win32shell::IShellItem, itemid: &ItemId, for_parsing: &HSTRING) -> i32 {
+        let attributes = unsafe { item.GetAttributes(SFGAO_FOLDER | SFGAO_STREAM | SFGAO_FILESYSTEM) }.unwrap_or_default();
+        let is_folder = attributes.contains(SFGAO_FOLDER) && !attributes.contains(SFGAO_STREAM);
+        let extension = Some(for_parsing.to_string_lossy())
+            .filter(|_| attributes.contains(SFGAO_FILESYSTEM) && !is_folder)
+            .and_then(|path| extension_of(&path))
+            .filter(|extension| !PER_FILE_EXTENSIONS.contains(&extension.as_str()));
+        if let Some(icon) = extension.as_ref().and_then(|extension| self.by_extension.borrow().get(extension).copied()) {
+            return icon;
+        }
+        let _ = self.jobs.send(Job {
+            list_view,
+            generation,
+            row,
+            extension,
+            itemid: itemid.as_bytes().to_vec(),
+        });
+        if is_folder {
+            self.generic_folder
+        } else {
+            self.generic_file
+        }
+    }
+    /// Everything the worker has finished since the last call
+    pub fn take_resolved(&self) -> Vec<Resolved> {
+        let resolved = std::mem::take(&mut *self.resolved.lock().unwrap());
+        let mut by_extension = self.by_extension.borrow_mut();
+        resolved
+            .into_iter()
+            .map(|(resolved, extension)| {
+                if let Some(extension) = extension {
+                    by_extension.insert(extension, resolved.icon);
+                }
+                resolved
+            })
+            .collect()
+    }
+}
+
+fn work(
+    jobs: mpsc::Receiver<Job>,
+    resolved: Arc<Mutex<Vec<(Resolved, Option<String>)>>>,
+    generations: Arc<Mutex<HashMap<usize, u64>>>,
+    notice: nwg::NoticeSender,
+) {
+    if let Err(e) = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED | COINIT_DISABLE_OLE1DDE) }.ok() {
+        log::error!("icon thread could not start COM: {}", e.message().trim());
+        return;
+    }
+    // The worker's own copy, so a folder of a thousand .txt files costs one lookup
+    let mut by_extension = HashMap::new();
+    for job in jobs {
+        if generations.lock().unwrap().get(&job.list_view) != Some(&job.generation) {
+            continue;
+        }
+        let icon = match &job.extension {
+            Some(extension) => *by_extension
+                .entry(extension.clone())
+                .or_insert_with(|| by_attributes(&HSTRING::from(format!("file.{extension}")), FILE_ATTRIBUTE_NORMAL)),
+            None => by_itemid(job.itemid.as_ptr() as *const _),
+        };
+        let Some(icon) = icon else {
+            continue;
+        };
+        let mut resolved = resolved.lock().unwrap();
+        // One notice per batch, rather than one per row
+        if resolved.is_empty() {
+            notice.notice();
+        }
+        resolved.push((
+            Resolved {
+                list_view: job.list_view,
+                generation: job.generation,
+                row: job.row,
+                icon,
+            },
+            job.extension,
+        ));
+    }
+}
+
+/// The extension of the last component of a path, lowercased
+fn extension_of(path: &str) -> Option<String> {
+    let name = path.rsplit(['\\', '/']).next()?;
+    let (_, extension) = name.rsplit_once('.')?;
+    Some(extension.to_ascii_lowercase()).filter(|extension| !extension.is_empty())
+}
+
+/// Asks the shell for an item's own icon. This is the one that might touch the disk.
+pub fn by_itemid(pidl: *const win32shell::Common::ITEMIDLIST) -> Option<i32> {
+    shell_file_info(PCWSTR::from_raw(pidl as *const u16), FILE_FLAGS_AND_ATTRIBUTES(0), win32shell::SHGFI_PIDL)
+}
+
+/// Asks the shell for the icon a file would have, without looking for it
+fn by_attributes(name: &HSTRING, attributes: FILE_FLAGS_AND_ATTRIBUTES) -> Option<i32> {
+    shell_file_info(PCWSTR::from_raw(name.as_ptr()), attributes, win32shell::SHGFI_USEFILEATTRIBUTES)
+}
+
+fn shell_file_info(path: PCWSTR, attributes: FILE_FLAGS_AND_ATTRIBUTES, flags: win32shell::SHGFI_FLAGS) -> Option<i32> {
+    unsafe {
+        let mut info = win32shell::SHFILEINFOW::default();
+        let found = win32shell::SHGetFileInfoW(
+            path,
+            attributes,
+            Some(&mut info),
+            std::mem::size_of::<win32shell::SHFILEINFOW>() as u32,
+            flags | win32shell::SHGFI_SYSICONINDEX,
+        );
+        Some(info.iIcon).filter(|_| found != 0)
+    }
+}
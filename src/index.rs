@@ -0,0 +1,324 @@
+//! The names of everything under the folders listed in
+//! `%LOCALAPPDATA%\stapler\index_roots.txt`, walked in the background and kept
+//! up to date from change notifications, for Go to anything to search.
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use windows::Win32::Foundation::{BOOL, HANDLE};
+use windows::Win32::Storage::FileSystem as win32fs;
+use windows::Win32::System::IO::CancelSynchronousIo;
+
+use crate::matching;
+
+/// Room for the changes that pile up between two reads before the watcher has to walk again
+const CHANGES_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Everything the walkers and watchers have found so far
+#[derive(Default)]
+struct Names {
+    /// The names of what's in each folder, by the folder's full path. A name is
+    /// a folder if its own path is in here too, which keeps files down to a name each.
+    folders: HashMap<Box<str>, Vec<Box<str>>>,
+}
+
+impl Names {
+    fn len(&self) -> usize {
+        self.folders.values().map(Vec::len).sum()
+    }
+    /// Lists `folder` and everything under it, replacing whatever was known about them
+    fn walk(&mut self, folder: &Path, stopped: &AtomicBool) {
+        let mut pending = vec![folder.to_owned()];
+        while let Some(folder) = pending.pop() {
+            if stopped.load(Ordering::Acquire) {
+                return;
+            }
+            let Ok(entries) = fs::read_dir(&folder) else {
+                continue;
+            };
+            let mut names = Vec::new();
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                names.push(entry.file_name().to_string_lossy().into());
+                // Junctions and symlinks could lead round in circles
+                if entry.file_type().is_ok_and(|file_type| file_type.is_dir() && !file_type.is_symlink()) {
+                    pending.push(entry.path());
+                }
+            }
+            self.folders.insert(folder.to_string_lossy().into(), names);
+        }
+    }
+    /// Something new at `path`, with `walked` holding what's under it if it's a folder
+    fn add(&mut self, path: &Path, walked: Names) {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return;
+        };
+        // Not walked yet, so it'll be picked up when it is
+        let Some(names) = self.folders.get_mut(parent.to_string_lossy().as_ref()) else {
+            return;
+        };
+        let name = name.to_string_lossy();
+        if !names.iter().any(|existing| **existing == *name) {
+            names.push(name.into());
+        }
+        self.folders.extend(walked.folders);
+    }
+    /// Forgets `path`, and everything under it if it was a folder
+    fn remove(&mut self, path: &Path) {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return;
+        };
+        if let Some(names) = self.folders.get_mut(parent.to_string_lossy().as_ref()) {
+            let name = name.to_string_lossy();
+            names.retain(|existing| **existing != *name);
+        }
+        let path = path.to_string_lossy();
+        let under = join(&path, "");
+        self.folders.retain(|folder, _| **folder != *path && !folder.starts_with(&under));
+    }
+    /// The paths of the `limit` best matches for `query`, best first
+    fn find(&self, query: &str, limit: usize) -> Vec<String> {
+        let terms: Vec<Vec<char>> = query.split_whitespace().map(|term| term.to_lowercase().chars().collect()).collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+        let mut hits: Vec<(i32, &str, &str)> = Vec::new();
+        for (folder, children) in &self.folders {
+            for name in children {
+                let score = terms.iter().try_fold(0, |total, term| Some(total + matching::fuzzy_score(term, folder, name)?));
+                if let Some(score) = score {
+                    hits.push((score, folder, name));
+                }
+            }
+            // Don't let a one letter query pile up everything
+            if hits.len() > limit * 8 {
+                hits.sort_unstable_by_key(|&(score, ..)| -score);
+                hits.truncate(limit);
+            }
+        }
+        hits.sort_unstable_by_key(|&(score, folder, name)| (-score, folder.len() + name.len()));
+        hits.truncate(limit);
+        hits.into_iter().map(|(_, folder, name)| join(folder, name)).collect()
+    }
+}
+
+/// The answer to the newest query the finder has got through
+pub struct Found {
+    /// Paths, best first
+    pub hits: Vec<String>,
+    /// How many names there were to look through
+    pub indexed: usize,
+}
+
+pub struct Index {
+    names: Arc<Mutex<Names>>,
+    /// How many roots are still being walked for the first time
+    walking: Arc<AtomicUsize>,
+    stopped: Arc<AtomicBool>,
+    watchers: Vec<JoinHandle<()>>,
+    /// Queries and how many hits to give, for the finder thread
+    queries: mpsc::Sender<(String, usize)>,
+    found: Arc<Mutex<Option<Found>>>,
+}
+
+impl Index {
+    /// Starts walking and watching `roots`. `notice` fires whenever there's something `found`.
+    pub fn start(roots: Vec<PathBuf>, notice: nwg::NoticeSender) -> Index {
+        let names = Arc::new(Mutex::new(Names::default()));
+        let walking = Arc::new(AtomicUsize::new(roots.len()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let mut watchers = Vec::new();
+        for root in roots {
+            // Watching starts before the walk, so nothing made during it is missed
+            let watcher = Watcher::open(&root);
+            let (names_, walking_, stopped_) = (names.clone(), walking.clone(), stopped.clone());
+            let spawned = std::thread::Builder::new().name("index".into()).spawn(move || {
+                let started = Instant::now();
+                // Walked into its own copy, so searches aren't held up for the whole walk
+                let mut walked = Names::default();
+                walked.walk(&root, &stopped_);
+                let count = walked.len();
+                names_.lock().unwrap().folders.extend(walked.folders);
+                walking_.fetch_sub(1, Ordering::AcqRel);
+                log::info!("indexed {count} items under {} in {:?}", root.display(), started.elapsed());
+                match watcher {
+                    Ok(watcher) => watcher.run(&root, &names_, &stopped_),
+                    Err(e) => log::warn!("not watching {} for changes: {e}", root.display()),
+                }
+            });
+            match spawned {
+                Ok(watcher) => watchers.push(watcher),
+                Err(e) => log::error!("could not start indexing: {e}"),
+            }
+        }
+        let (queries, queued) = mpsc::channel::<(String, usize)>();
+        let found = Arc::new(Mutex::new(None));
+        let (names_, found_) = (names.clone(), found.clone());
+        // Stops by itself once the index is dropped and there's nothing left to ask
+        let spawned = std::thread::Builder::new().name("index find".into()).spawn(move || {
+            while let Ok(mut query) = queued.recv() {
+                // Whatever was typed in the meantime makes the older queries moot
+                while let Ok(newer) = queued.try_recv() {
+                    query = newer;
+                }
+                let names = names_.lock().unwrap();
+                let found = Found { hits: names.find(&query.0, query.1), indexed: names.len() };
+                std::mem::drop(names);
+                *found_.lock().unwrap() = Some(found);
+                notice.notice();
+            }
+        });
+        if let Err(e) = spawned {
+            log::error!("could not start looking things up in the index: {e}");
+        }
+        Index { names, walking, stopped, watchers, queries, found }
+    }
+    /// Whether any roots are still being walked for the first time
+    pub fn walking(&self) -> bool {
+        self.walking.load(Ordering::Acquire) > 0
+    }
+    /// Looks for the paths of the `limit` best matches for `query` on the finder thread
+    pub fn find(&self, query: &str, limit: usize) {
+        let _ = self.queries.send((query.to_owned(), limit));
+    }
+    /// What the finder came up with last, if it hasn't been taken already
+    pub fn found(&self) -> Option<Found> {
+        self.found.lock().unwrap().take()
+    }
+}
+
+// The watchers are blocked waiting for changes, so they have to be woken to see `stopped`
+impl Drop for Index {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        for watcher in self.watchers.drain(..) {
+            let _ = unsafe { CancelSynchronousIo(HANDLE(watcher.as_raw_handle())) };
+        }
+    }
+}
+
+/// A root opened for `ReadDirectoryChangesW`
+struct Watcher {
+    directory: fs::File,
+}
+
+impl Watcher {
+    fn open(root: &Path) -> std::io::Result<Watcher> {
+        let directory = fs::OpenOptions::new()
+            .access_mode(win32fs::FILE_LIST_DIRECTORY.0)
+            .share_mode((win32fs::FILE_SHARE_READ | win32fs::FILE_SHARE_WRITE | win32fs::FILE_SHARE_DELETE).0)
+            .custom_flags(win32fs::FILE_FLAG_BACKUP_SEMANTICS.0)
+            .open(root)?;
+        Ok(Watcher { directory })
+    }
+    /// Applies changes under `root` to `names` until it's stopped, or the root goes away
+    fn run(&self, root: &Path, names: &Mutex<Names>, stopped: &AtomicBool) {
+        // `FILE_NOTIFY_INFORMATION` wants to be aligned to a u32
+        let mut buffer = vec![0u32; CHANGES_BUFFER_SIZE / 4];
+        while !stopped.load(Ordering::Acquire) {
+            let mut returned = 0;
+            let result = unsafe {
+                win32fs::ReadDirectoryChangesW(
+                    HANDLE(self.directory.as_raw_handle()),
+                    buffer.as_mut_ptr() as *mut _,
+                    TryInto::<u32>::try_into(CHANGES_BUFFER_SIZE).unwrap(),
+                    BOOL::from(true),
+                    win32fs::FILE_NOTIFY_CHANGE_FILE_NAME | win32fs::FILE_NOTIFY_CHANGE_DIR_NAME,
+                    Some(&mut returned),
+                    None,
+                    None,
+                )
+            };
+            if let Err(e) = result {
+                if !stopped.load(Ordering::Acquire) {
+                    log::warn!("stopped watching {}: {}", root.display(), e.message().trim());
+                }
+                return;
+            }
+            // Too much changed to say what, so look again
+            if returned == 0 {
+                log::debug!("{} changed too much to follow, walking it again", root.display());
+                let mut walked = Names::default();
+                walked.walk(root, stopped);
+                let mut names = names.lock().unwrap();
+                names.remove(root);
+                names.folders.extend(walked.folders);
+                continue;
+            }
+            let mut changes = Vec::new();
+            let mut offset = 0;
+            loop {
+                let info = unsafe { &*((buffer.as_ptr() as *const u8).add(offset) as *const win32fs::FILE_NOTIFY_INFORMATION) };
+                let name = unsafe { std::slice::from_raw_parts(info.FileName.as_ptr(), info.FileNameLength as usize / 2) };
+                let path = root.join(String::from_utf16_lossy(name));
+                changes.push((info.Action, path));
+                if info.NextEntryOffset == 0 {
+                    break;
+                }
+                offset += info.NextEntryOffset as usize;
+            }
+            for (action, path) in changes {
+                match action {
+                    win32fs::FILE_ACTION_ADDED | win32fs::FILE_ACTION_RENAMED_NEW_NAME => {
+                        // A folder moved in can be big, so it's walked without holding up searches
+                        let mut walked = Names::default();
+                        if path.is_dir() {
+                            walked.walk(&path, stopped);
+                        }
+                        names.lock().unwrap().add(&path, walked);
+                    }
+                    win32fs::FILE_ACTION_REMOVED | win32fs::FILE_ACTION_RENAMED_OLD_NAME => names.lock().unwrap().remove(&path),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// The folders to index, one per line. Made with the user's profile folder in it the first time.
+pub fn roots_file() -> anyhow::Result<PathBuf> {
+    let path = crate::app_data_dir()?.join("index_roots.txt");
+    if !path.exists() {
+        let profile = std::env::var("USERPROFILE").unwrap_or_default();
+        fs::write(&path, format!("{profile}\r\n"))?;
+    }
+    Ok(path)
+}
+
+/// The folders in `roots_file` that are there
+pub fn roots() -> Vec<PathBuf> {
+    let text = match roots_file().and_then(|path| Ok(fs::read_to_string(path)?)) {
+        Ok(text) => text,
+        Err(e) => {
+            log::warn!("could not read the folders to index: {e:#}");
+            return Vec::new();
+        }
+    };
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            // The same form `fs::read_dir` gives for folders, so `C:\` but `D:\Projects`
+            let line = line.trim_end_matches('\\');
+            PathBuf::from(if line.ends_with(':') { format!("{line}\\") } else { line.to_owned() })
+        })
+        .filter(|root| {
+            let exists = root.is_dir();
+            if !exists {
+                log::warn!("not indexing {}, which isn't a folder", root.display());
+            }
+            exists
+        })
+        .collect()
+}
+
+/// `name` in `folder`, which only ends in a slash if it's a drive
+fn join(folder: &str, name: &str) -> String {
+    if folder.ends_with('\\') { format!("{folder}{name}") } else { format!("{folder}\\{name}") }
+}
@@ -0,0 +1,175 @@
+//! Keeping to one stapler per user. The first to start listens on a named pipe,
+//! and later launches hand what they were asked to do to it there, then quit.
+
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use windows::core::HSTRING;
+use windows::Win32::Foundation::{ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, HANDLE};
+use windows::Win32::Storage::FileSystem as win32fs;
+use windows::Win32::System::Pipes as win32pipes;
+use windows::Win32::UI::WindowsAndMessaging::AllowSetForegroundWindow;
+
+use crate::user_pipes;
+
+/// One pipe per user and sign-in, so people sharing a machine each have their own stapler
+const PIPE_PREFIX: &str = "stapler";
+
+/// How many times a launch tries the pipe while the running instance is busy with another
+const BUSY_RETRIES: usize = 40;
+const BUSY_WAIT: Duration = Duration::from_millis(50);
+
+/// What a later launch asks the running instance to do
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Request {
+    /// Just come to the front
+    Show,
+    /// Open a tab revealing this path
+    Reveal(String),
+    /// Open a tab on the likeliest folder for these words, as `--jump` does
+    Jump(String),
+}
+
+impl Request {
+    fn to_line(&self) -> String {
+        match self {
+            Request::Show => "show\r\n".to_owned(),
+            Request::Reveal(path) => format!("reveal\t{path}\r\n"),
+            Request::Jump(query) => format!("jump\t{query}\r\n"),
+        }
+    }
+    fn parse(line: &str) -> Option<Request> {
+        match line.split_once('\t') {
+            None if line == "show" => Some(Request::Show),
+            Some(("reveal", path)) => Some(Request::Reveal(path.to_owned())),
+            Some(("jump", query)) => Some(Request::Jump(query.to_owned())),
+            _ => None,
+        }
+    }
+}
+
+/// The pipe, made before the window so that a second launch right behind this one finds it
+pub struct Claim {
+    pipe: OwnedHandle,
+}
+
+/// Becomes the running instance, or `None` if there already is one
+pub fn claim() -> Option<Claim> {
+    let (name, security) = match user_pipes::name(PIPE_PREFIX).and_then(|name| Ok((name, user_pipes::Security::new()?))) {
+        Ok(pipe) => pipe,
+        Err(e) => {
+            log::warn!("could not make a pipe for other launches: {}", e.message().trim());
+            return None;
+        }
+    };
+    let attributes = security.attributes();
+    let pipe = unsafe {
+        win32pipes::CreateNamedPipeW(
+            &HSTRING::from(name),
+            win32fs::PIPE_ACCESS_INBOUND | win32fs::FILE_FLAG_FIRST_PIPE_INSTANCE,
+            win32pipes::PIPE_TYPE_BYTE | win32pipes::PIPE_READMODE_BYTE | win32pipes::PIPE_WAIT | win32pipes::PIPE_REJECT_REMOTE_CLIENTS,
+            1,
+            0,
+            4096,
+            0,
+            Some(&attributes),
+        )
+    };
+    if pipe.is_invalid() {
+        log::debug!("another instance has the pipe: {}", windows::core::Error::from_win32().message().trim());
+        return None;
+    }
+    Some(Claim { pipe: unsafe { OwnedHandle::from_raw_handle(pipe.0) } })
+}
+
+/// Hands `requests` to the running instance. `false` if there isn't one to take them.
+pub fn send(requests: &[Request]) -> bool {
+    let name = match user_pipes::name(PIPE_PREFIX) {
+        Ok(name) => name,
+        Err(e) => {
+            log::warn!("could not find the running instance's pipe: {}", e.message().trim());
+            return false;
+        }
+    };
+    let mut pipe = None;
+    for _ in 0..BUSY_RETRIES {
+        match fs::OpenOptions::new().write(true).open(&name) {
+            Ok(opened) => {
+                pipe = Some(opened);
+                break;
+            }
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) => std::thread::sleep(BUSY_WAIT),
+            Err(e) if e.kind() == ErrorKind::NotFound => return false,
+            Err(e) => {
+                log::warn!("could not reach the running instance: {e}");
+                return false;
+            }
+        }
+    }
+    let Some(mut pipe) = pipe else {
+        log::warn!("the running instance stayed busy");
+        return false;
+    };
+    // So it's allowed to come to the front, which Windows only lets the foreground process give away
+    let mut server = 0;
+    if unsafe { win32pipes::GetNamedPipeServerProcessId(HANDLE(pipe.as_raw_handle()), &mut server) }.is_ok() {
+        let _ = unsafe { AllowSetForegroundWindow(server) };
+    }
+    let text: String = requests.iter().map(Request::to_line).collect();
+    match pipe.write_all(text.as_bytes()) {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!("could not pass {requests:?} on: {e}");
+            false
+        }
+    }
+}
+
+pub struct Listener {
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Claim {
+    /// Takes requests from later launches on a thread of its own, firing `notice` for each batch
+    pub fn listen(self, notice: nwg::NoticeSender) -> Listener {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_ = requests.clone();
+        let mut pipe = fs::File::from(self.pipe);
+        let spawned = std::thread::Builder::new().name("instance".into()).spawn(move || {
+            loop {
+                let handle = HANDLE(pipe.as_raw_handle());
+                // Already connected is fine too: the launch got in before the wait started
+                if let Err(e) = unsafe { win32pipes::ConnectNamedPipe(handle, None) } {
+                    if e.code() != ERROR_PIPE_CONNECTED.to_hresult() {
+                        log::error!("stopped listening for other launches: {}", e.message().trim());
+                        return;
+                    }
+                }
+                let mut text = String::new();
+                if let Err(e) = pipe.read_to_string(&mut text) {
+                    log::warn!("could not read what another launch sent: {e}");
+                }
+                let _ = unsafe { win32pipes::DisconnectNamedPipe(handle) };
+                let received: Vec<Request> = text.lines().filter_map(Request::parse).collect();
+                if !received.is_empty() {
+                    log::info!("another launch asked for {received:?}");
+                    requests_.lock().unwrap().extend(received);
+                    notice.notice();
+                }
+            }
+        });
+        if let Err(e) = spawned {
+            log::error!("could not start listening for other launches: {e}");
+        }
+        Listener { requests }
+    }
+}
+
+impl Listener {
+    pub fn take_requests(&self) -> Vec<Request> {
+        std::mem::take(&mut *self.requests.lock().unwrap())
+    }
+}
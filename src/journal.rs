@@ -0,0 +1,190 @@
+//! The undo and redo stacks for what stapler has done to files. Each entry keeps
+//! the steps that were actually taken, as the shell reported them, so undoing
+//! one is a matter of taking the opposite steps, and only if nothing has
+//! changed underneath them since.
+
+use windows::Win32::Foundation::HWND;
+
+use crate::{file_operations, ItemId};
+
+/// How many entries each stack keeps
+const MAX_ENTRIES: usize = 100;
+
+/// One thing that happened to one item, by `SIGDN_DESKTOPABSOLUTEPARSING` names
+#[derive(Clone, Debug)]
+pub enum Step {
+    /// Moved or renamed
+    Moved { from: String, to: String },
+    /// Made by copying, pasting or from a template
+    Created { path: String },
+    /// Sent to the Recycle Bin, where it's now `recycled`
+    Recycled { from: String, recycled: String },
+}
+
+impl Step {
+    /// Why taking the opposite step would go wrong now, if it would
+    fn divergence(&self) -> Option<String> {
+        match self {
+            Step::Moved { to, .. } if !exists(to) => Some(format!("{to} isn't there any more")),
+            // A rename that only changed the case finds the item itself at `from`
+            Step::Moved { from, to } if !same_path(from, to) && exists(from) => Some(format!("something else is at {from} now")),
+            Step::Created { path } if !exists(path) => Some(format!("{path} isn't there any more")),
+            Step::Recycled { recycled, from } if !exists(recycled) => Some(format!("{from} isn't in the Recycle Bin any more")),
+            Step::Recycled { from, .. } if exists(from) => Some(format!("something else is at {from} now")),
+            _ => None,
+        }
+    }
+    /// Takes the opposite step, adding what that did to `done`
+    fn reverse(&self, owner: HWND, done: &mut Vec<Step>) -> windows::core::Result<()> {
+        match self {
+            Step::Moved { from, to } => move_back(owner, to, from, done),
+            Step::Created { path } => {
+                let item = ItemId::from_parsing_name(path)?.shell_item()?;
+                file_operations::recycle(owner, &item, done)
+            }
+            Step::Recycled { from, recycled } => {
+                file_operations::restore(owner, recycled)?;
+                // So it's recycled again if this is reversed
+                done.push(Step::Created { path: from.clone() });
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A command's worth of steps, undone or redone together
+pub struct Entry {
+    /// Like "moving 3 items to D:\Photos"
+    pub description: String,
+    pub steps: Vec<Step>,
+}
+
+impl Entry {
+    /// Takes the opposite steps, newest first, unless things have changed since.
+    /// The shell pumps messages while it works, so this is done with the entry
+    /// taken out of the journal, and the journal free to be looked at.
+    pub fn reverse(self, owner: HWND) -> (Reversal, Result<(), Refusal>) {
+        let Entry { description, steps: mut remaining } = self;
+        let diverged: Vec<String> = remaining.iter().filter_map(Step::divergence).collect();
+        if !diverged.is_empty() {
+            return (Reversal { description, done: Vec::new(), remaining }, Err(Refusal::Diverged(diverged)));
+        }
+        let mut done = Vec::new();
+        let mut result = Ok(());
+        while let Some(step) = remaining.pop() {
+            if let Err(e) = step.reverse(owner, &mut done) {
+                remaining.push(step);
+                result = Err(Refusal::Failed(e));
+                break;
+            }
+        }
+        (Reversal { description, done, remaining }, result)
+    }
+}
+
+/// What came of reversing an entry, for `Journal::put_back`
+pub struct Reversal {
+    description: String,
+    /// The steps taken, which can now be reversed the other way
+    done: Vec<Step>,
+    /// The steps not taken, as it was refused or stopped part way
+    remaining: Vec<Step>,
+}
+
+pub enum Refusal {
+    /// Things have changed since, so the steps can't be reversed as they were
+    Diverged(Vec<String>),
+    /// It started, but the shell stopped part way
+    Failed(windows::core::Error),
+}
+
+#[derive(Default)]
+pub struct Journal {
+    undo: Vec<Entry>,
+    redo: Vec<Entry>,
+}
+
+impl Journal {
+    /// Keeps `steps` to be undone, unless nothing was actually done. A new entry
+    /// means what was undone before can't be redone any more.
+    pub fn record(&mut self, description: impl Into<String>, steps: Vec<Step>) {
+        if steps.is_empty() {
+            return;
+        }
+        let description = description.into();
+        log::info!("journal: {description}, {} steps", steps.len());
+        push(&mut self.undo, Entry { description, steps });
+        self.redo.clear();
+    }
+    /// Newest first
+    pub fn undo_entries(&self) -> impl Iterator<Item = &Entry> {
+        self.undo.iter().rev()
+    }
+    /// Newest first
+    pub fn redo_entries(&self) -> impl Iterator<Item = &Entry> {
+        self.redo.iter().rev()
+    }
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+    /// Takes the newest entry that can be undone, to `Entry::reverse` it
+    pub fn take_undo(&mut self) -> Option<Entry> {
+        self.undo.pop()
+    }
+    pub fn take_redo(&mut self) -> Option<Entry> {
+        self.redo.pop()
+    }
+    /// Puts back what came of reversing an entry from `take_undo`, or from `take_redo`
+    /// if not `undone`. Whatever did get reversed can be put back, and what didn't
+    /// can be tried again.
+    pub fn put_back(&mut self, undone: bool, reversal: Reversal) {
+        let (from, to) = if undone { (&mut self.undo, &mut self.redo) } else { (&mut self.redo, &mut self.undo) };
+        let Reversal { description, done, remaining } = reversal;
+        if !remaining.is_empty() {
+            from.push(Entry { description: description.clone(), steps: remaining });
+        }
+        if !done.is_empty() {
+            push(to, Entry { description, steps: done });
+        }
+    }
+    /// Drops the newest entry that can be undone, after it was refused
+    pub fn forget_undo(&mut self) {
+        self.undo.pop();
+    }
+    pub fn forget_redo(&mut self) {
+        self.redo.pop();
+    }
+}
+
+fn push(stack: &mut Vec<Entry>, entry: Entry) {
+    stack.push(entry);
+    if stack.len() > MAX_ENTRIES {
+        stack.remove(0);
+    }
+}
+
+/// Moves the item at `current` to where `original` says, under the name it had there
+fn move_back(owner: HWND, current: &str, original: &str, done: &mut Vec<Step>) -> windows::core::Result<()> {
+    let (folder, name) = original.rsplit_once('\\').ok_or_else(|| windows::core::Error::from(windows::Win32::Foundation::E_INVALIDARG))?;
+    let item = ItemId::from_parsing_name(current)?.shell_item()?;
+    // Still in the same folder, so it was renamed, maybe only changing the case, which a move can't undo
+    if current.rsplit_once('\\').is_some_and(|(current_folder, _)| same_path(current_folder, folder)) {
+        return file_operations::rename(owner, &item, name, done);
+    }
+    // A drive root like `C:` needs its slash to parse
+    let folder = if folder.ends_with(':') { format!("{folder}\\") } else { folder.to_owned() };
+    let folder = ItemId::from_parsing_name(&folder)?.shell_item()?;
+    file_operations::move_item(owner, &item, &folder, name, done)
+}
+
+/// Whether two parsing names are the same place, as the file system ignores case
+fn same_path(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+fn exists(path: &str) -> bool {
+    ItemId::from_parsing_name(path).is_ok()
+}
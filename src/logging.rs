@@ -0,0 +1,173 @@
+//! Levelled logging to a rotating file under `%LOCALAPPDATA%\stapler\logs`,
+//! with the most recent entries kept in memory for the debug window.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use windows::Win32::System::SystemInformation::GetLocalTime;
+
+/// Start a new log file once the current one is this big
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// How many old log files to keep around, as `stapler.1.log` and up
+const MAX_OLD_FILES: u32 = 4;
+/// How many entries the debug window can show
+const MAX_RECENT: usize = 2000;
+
+/// One log line, before it gets formatted
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// Local time, as `YYYY-MM-DD hh:mm:ss.mmm`
+    pub time: String,
+    pub level: log::Level,
+    /// The module that logged this
+    pub target: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\t{:<5}\t{}\t{}", self.time, self.level, self.target, self.message)
+    }
+}
+
+struct State {
+    /// The log file, or `None` if it couldn't be opened
+    file: Option<File>,
+    /// Bytes in `file` so far
+    file_size: u64,
+    /// The ring buffer shown in the debug window
+    recent: VecDeque<Entry>,
+    /// Entries the debug window hasn't picked up yet
+    pending: Vec<Entry>,
+    /// Pokes the UI thread when `pending` gets something
+    notice: Option<nwg::NoticeSender>,
+}
+
+struct Logger {
+    state: Mutex<State>,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let entry = Entry {
+            time: now(),
+            level: record.level(),
+            target: record.target().to_owned(),
+            message: record.args().to_string(),
+        };
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let state = &mut *guard;
+        if state.file_size >= MAX_FILE_SIZE {
+            state.file = rotate().and_then(open).ok();
+            state.file_size = 0;
+        }
+        if let Some(file) = &mut state.file {
+            let line = format!("{entry}\r\n");
+            if file.write_all(line.as_bytes()).is_ok() {
+                state.file_size += line.len() as u64;
+            }
+        }
+        if state.recent.len() == MAX_RECENT {
+            state.recent.pop_front();
+        }
+        state.recent.push_back(entry.clone());
+        if let Some(notice) = state.notice {
+            state.pending.push(entry);
+            notice.notice();
+        }
+    }
+    fn flush(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(file) = &mut state.file {
+            let _ = file.flush();
+        }
+    }
+}
+
+static LOGGER: Logger = Logger {
+    state: Mutex::new(State {
+        file: None,
+        file_size: 0,
+        recent: VecDeque::new(),
+        pending: Vec::new(),
+        notice: None,
+    }),
+};
+
+/// Installs the logger. Logging still works in memory if the file can't be opened.
+pub fn init(level: log::LevelFilter) {
+    {
+        let mut state = LOGGER.state.lock().unwrap();
+        let path = log_path();
+        let file_size = path.as_ref().ok().and_then(|path| fs::metadata(path).ok()).map_or(0, |m| m.len());
+        let file = if file_size >= MAX_FILE_SIZE {
+            rotate().and_then(open)
+        } else {
+            path.and_then(open)
+        };
+        state.file_size = if file_size >= MAX_FILE_SIZE { 0 } else { file_size };
+        match file {
+            Ok(file) => state.file = Some(file),
+            Err(e) => eprintln!("could not open the log file: {e}"),
+        }
+    }
+    log::set_max_level(level);
+    let _ = log::set_logger(&LOGGER);
+    log::info!("stapler {} started, logging at {level}", env!("CARGO_PKG_VERSION"));
+}
+
+/// Sets the notice that fires whenever there's something for `take_pending`
+pub fn subscribe(notice: nwg::NoticeSender) {
+    LOGGER.state.lock().unwrap().notice = Some(notice);
+}
+
+/// The last `MAX_RECENT` entries, oldest first
+pub fn recent() -> Vec<Entry> {
+    LOGGER.state.lock().unwrap().recent.iter().cloned().collect()
+}
+
+/// Entries logged since the last call
+pub fn take_pending() -> Vec<Entry> {
+    std::mem::take(&mut LOGGER.state.lock().unwrap().pending)
+}
+
+/// Where the current log file lives
+pub fn log_path() -> anyhow::Result<PathBuf> {
+    let dir = crate::app_data_dir()?.join("logs");
+    fs::create_dir_all(&dir)?;
+    Ok(dir.join("stapler.log"))
+}
+
+fn open(path: PathBuf) -> anyhow::Result<File> {
+    Ok(OpenOptions::new().create(true).append(true).open(path)?)
+}
+
+/// Shifts `stapler.log` to `stapler.1.log`, `stapler.1.log` to `stapler.2.log`,
+/// and so on, dropping the oldest. Returns the path to start afresh at.
+fn rotate() -> anyhow::Result<PathBuf> {
+    let path = log_path()?;
+    let numbered = |i: u32| path.with_file_name(format!("stapler.{i}.log"));
+    let _ = fs::remove_file(numbered(MAX_OLD_FILES));
+    for i in (1..MAX_OLD_FILES).rev() {
+        let _ = fs::rename(numbered(i), numbered(i + 1));
+    }
+    let _ = fs::rename(&path, numbered(1));
+    Ok(path)
+}
+
+fn now() -> String {
+    let t = unsafe { GetLocalTime() };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        t.wYear, t.wMonth, t.wDay, t.wHour, t.wMinute, t.wSecond, t.wMilliseconds,
+    )
+}
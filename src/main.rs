@@ -4,41 +4,221 @@ extern crate native_windows_gui as nwg;
 #[macro_use]
 extern crate native_windows_derive as nwd;
 
-use anyhow::Result;
+mod clipboard;
+mod control;
+mod file_operations;
+mod frecency;
+mod icons;
+mod index;
+mod instance;
+mod journal;
+mod logging;
+mod matching;
+mod output;
+mod path_formats;
+mod search;
+mod selection_sets;
+mod session;
+mod shelf;
+mod templates;
+mod terminal;
+mod thumbnails;
+mod user_commands;
+mod user_pipes;
+mod widths;
+
+use anyhow::{bail, Context, Result};
 
 use nwg::NativeUi;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::ffi::{c_void, OsString};
-use std::os::windows::ffi::OsStringExt;
+use std::io::Write;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use windows::core::{w, Interface};
+use windows::core::{w, Interface, HRESULT, HSTRING, PWSTR};
 use windows::Win32::UI::Shell as win32shell;
 use windows::Win32::UI::Controls as win32controls;
 use windows::Win32::System::Com::CoTaskMemFree;
 use windows::Win32::UI::WindowsAndMessaging::{self as win32wam, HICON};
-use windows::Win32::Foundation::HWND;
+use windows::Win32::Foundation::{E_ACCESSDENIED, ERROR_CANCELLED, HINSTANCE, HWND, LPARAM, POINT, WPARAM};
+use windows::Win32::Graphics::Gdi as win32gdi;
+use windows::Win32::UI::Input::KeyboardAndMouse as win32input;
+use windows::Win32::System::Ole::OleInitialize;
+use windows::Win32::System::SystemServices::{SFGAO_FOLDER, SFGAO_STREAM};
+use windows::Win32::Storage::EnhancedStorage::PKEY_Size;
 
 use windows_strings::PCWSTR;
 
 #[derive(Clone)]
 enum Folder {
     Shell {
-        sysobj: win32shell::IShellFolder,
+        item: win32shell::IShellItem,
+        itemid: Rc<ItemId>,
         display: String,
         icon: Option<i32>,
-        for_parsing: Vec<u16>,
+        for_parsing: HSTRING,
     },
     Selection {
         selection: HashSet<File>,
     },
-    Error(String),
+    /// The hits of a search under the folder `for_parsing`, streamed in as they're found
+    Search {
+        itemid: Rc<ItemId>,
+        icon: Option<i32>,
+        for_parsing: HSTRING,
+        search: Rc<search::Search>,
+    },
+    /// The folders visited most, and most lately, see `frecency::Frecency`
+    Recent,
+    Error {
+        error: ShellError,
+        /// If `Some`, the retry button will redo this
+        retry: Option<Retry>,
+    },
+}
+
+/// How to repeat the operation that produced a `Folder::Error`
+#[derive(Clone)]
+enum Retry {
+    /// Bind to the file again
+    SwitchInto(File),
+    /// Enumerate the folder again
+    Switch(Box<Folder>),
+}
+
+impl Retry {
+    /// The item id and parsing name of the thing that failed, if it has them
+    fn target(&self) -> Option<(&ItemId, &HSTRING)> {
+        match self {
+            Retry::SwitchInto(File::Shell { itemid, for_parsing, .. }) => Some((itemid, for_parsing)),
+            Retry::Switch(folder) => match &**folder {
+                Folder::Shell { itemid, for_parsing, .. } => Some((itemid, for_parsing)),
+                _ => None,
+            },
+            Retry::SwitchInto(File::Error(..)) => None,
+        }
+    }
+}
+
+/// A failed shell call, kept so it can be shown to the user in place of a listing
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct ShellError {
+    /// What we were doing at the time, e.g. "opening Documents"
+    action: String,
+    code: HRESULT,
+}
+
+impl ShellError {
+    fn new(action: impl Into<String>, code: HRESULT) -> ShellError {
+        let error = ShellError {
+            action: action.into(),
+            code,
+        };
+        log::warn!("{}", error.to_string().replace("\r\n", " "));
+        error
+    }
+    fn is_access_denied(&self) -> bool {
+        self.code == E_ACCESSDENIED
+    }
+    /// The user stopped it, so there's nothing to tell them
+    fn is_cancelled(&self) -> bool {
+        self.code == ERROR_CANCELLED.to_hresult()
+    }
+}
+
+impl std::fmt::Display for ShellError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = self.code.message();
+        let message = match message.trim() {
+            "" => "Unknown error",
+            message => message,
+        };
+        write!(f, "Error {}:\r\n{message}\r\n(0x{:08X})", self.action, self.code.0 as u32)
+    }
+}
+
+/// Runs a verb on an item, logging the launch and any failure.
+/// The item goes to the shell by id, so its path can be any length.
+fn shell_execute(hwnd: HWND, verb: PCWSTR, itemid: &ItemId, for_parsing: &HSTRING) {
+    let verb_ = unsafe { verb.to_string() }.unwrap_or_default();
+    log::info!("{verb_} {for_parsing}");
+    let mut info = win32shell::SHELLEXECUTEINFOW {
+        cbSize: std::mem::size_of::<win32shell::SHELLEXECUTEINFOW>() as u32,
+        fMask: win32shell::SEE_MASK_INVOKEIDLIST,
+        hwnd,
+        lpVerb: verb,
+        lpIDList: itemid.0 as *mut c_void,
+        nShow: win32wam::SW_SHOWNORMAL.0,
+        ..Default::default()
+    };
+    let started = Instant::now();
+    match unsafe { win32shell::ShellExecuteExW(&mut info) } {
+        Ok(()) => log::debug!("{verb_} {for_parsing} took {:?}", started.elapsed()),
+        Err(e) => log::error!("{verb_} {for_parsing} failed: {}", e.message().trim()),
+    }
+}
+
+/// `%LOCALAPPDATA%\stapler`, created if needed
+fn app_data_dir() -> Result<PathBuf> {
+    let local_app_data = std::env::var_os("LOCALAPPDATA").context("LOCALAPPDATA is not set")?;
+    let dir = PathBuf::from(local_app_data).join("stapler");
+    std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    Ok(dir)
 }
 
-#[derive(Debug, Eq, Hash, PartialEq)]
+/// An absolute item id list, owned. It names an item relative to the Desktop,
+/// so it can be bound, compared and stored without knowing which folder it came from.
+#[derive(Debug)]
 struct ItemId(*const win32shell::Common::ITEMIDLIST);
+impl ItemId {
+    /// The whole id list, including its terminator
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            let size = win32shell::ILGetSize(Some(self.0));
+            std::slice::from_raw_parts(self.0 as *const u8, size as usize)
+        }
+    }
+    fn shell_item(&self) -> windows::core::Result<win32shell::IShellItem> {
+        unsafe { win32shell::SHCreateItemFromIDList(self.0) }
+    }
+    /// Reads one of the item's names, e.g. `SIGDN_DESKTOPABSOLUTEPARSING`,
+    /// at whatever length the shell gives it
+    fn name(&self, sigdn: win32shell::SIGDN) -> windows::core::Result<HSTRING> {
+        unsafe {
+            let name = win32shell::SHGetNameFromIDList(self.0, sigdn)?;
+            let result = HSTRING::from_wide(name.as_wide());
+            CoTaskMemFree(Some(name.0 as *const c_void));
+            result
+        }
+    }
+    /// Looks an item up by its `SIGDN_DESKTOPABSOLUTEPARSING` name
+    fn from_parsing_name(name: &str) -> windows::core::Result<ItemId> {
+        let mut pidl = std::ptr::null_mut();
+        unsafe { win32shell::SHParseDisplayName(&HSTRING::from(name), None, &mut pidl, 0, None)? };
+        Ok(ItemId(pidl))
+    }
+    /// The item's index in the system image list. This can be slow,
+    /// see `icons::IconCache` for looking up many at once.
+    fn icon(&self) -> Option<i32> {
+        icons::by_itemid(self.0)
+    }
+}
+impl PartialEq for ItemId {
+    fn eq(&self, other: &ItemId) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+impl Eq for ItemId {}
+impl std::hash::Hash for ItemId {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state)
+    }
+}
 impl Drop for ItemId {
     fn drop(&mut self) {
         unsafe {
@@ -47,15 +227,182 @@ impl Drop for ItemId {
     }
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug)]
 enum File {
     Shell {
         itemid: Rc<ItemId>,
         display: String,
-        for_parsing: Vec<u16>,
+        for_parsing: HSTRING,
         icon: Option<i32>,
+        /// From `File::size`, once it's been asked for
+        size: Cell<Option<Option<u64>>>,
     },
-    Error(String),
+    Error(ShellError),
+}
+
+// Files are the same if they are the same item, whatever icon they're showing
+impl PartialEq for File {
+    fn eq(&self, other: &File) -> bool {
+        match (self, other) {
+            (File::Shell { itemid, .. }, File::Shell { itemid: other, .. }) => itemid == other,
+            (File::Error(error), File::Error(other)) => error == other,
+            _ => false,
+        }
+    }
+}
+impl Eq for File {}
+impl std::hash::Hash for File {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        match self {
+            File::Shell { itemid, .. } => itemid.hash(state),
+            File::Error(error) => error.hash(state),
+        }
+    }
+}
+
+impl File {
+    /// Reads the names and icon of an absolute item id
+    fn from_itemid(itemid: ItemId) -> Result<File, ShellError> {
+        let icon = itemid.icon();
+        File::named(itemid, icon)
+    }
+    /// Reads the names of an absolute item id, leaving the icon to the caller
+    fn named(itemid: ItemId, icon: Option<i32>) -> Result<File, ShellError> {
+        let display = itemid
+            .name(win32shell::SIGDN_PARENTRELATIVE)
+            .map_err(|e| ShellError::new("reading the name of an item", e.code()))?;
+        // Only lossy for unpaired surrogates, which `for_parsing` keeps intact
+        let display = display.to_string_lossy();
+        let for_parsing = itemid
+            .name(win32shell::SIGDN_DESKTOPABSOLUTEPARSING)
+            .map_err(|e| ShellError::new(format!("reading the path of {display}"), e.code()))?;
+        Ok(File::Shell {
+            itemid: Rc::new(itemid),
+            display,
+            for_parsing,
+            icon,
+            size: Cell::new(None),
+        })
+    }
+    /// Reads the names of an item, without an icon
+    fn from_item(item: &win32shell::IShellItem) -> Result<File, ShellError> {
+        match unsafe { win32shell::SHGetIDListFromObject(item) } {
+            Ok(pidl) => File::named(ItemId(pidl), None),
+            Err(e) => Err(ShellError::new("identifying an item", e.code())),
+        }
+    }
+    /// The folder the item is in, as a parsing name
+    fn parent_path(&self) -> String {
+        match self {
+            File::Shell { for_parsing, .. } => for_parsing
+                .to_string_lossy()
+                .rsplit_once('\\')
+                .map_or(String::new(), |(parent, _)| parent.to_owned()),
+            File::Error(..) => String::new(),
+        }
+    }
+    /// How big the item is, if it's a file. The shell keeps this in the item id list,
+    /// so it's read from there rather than the disk, and only the once.
+    fn size(&self) -> Option<u64> {
+        match self {
+            File::Shell { itemid, size, .. } => size.get().unwrap_or_else(|| {
+                let read = unsafe { win32shell::SHCreateItemFromIDList::<win32shell::IShellItem2>(itemid.0) }
+                    .and_then(|item| unsafe { item.GetUInt64(&PKEY_Size) })
+                    .ok()
+                    .filter(|_| !self.is_folder());
+                size.set(Some(read));
+                read
+            }),
+            File::Error(..) => None,
+        }
+    }
+    /// Whether it's a folder as Explorer would say, so not a zip file
+    fn is_folder(&self) -> bool {
+        match self {
+            File::Shell { itemid, .. } => itemid
+                .shell_item()
+                .and_then(|item| unsafe { item.GetAttributes(SFGAO_FOLDER | SFGAO_STREAM) })
+                .is_ok_and(|attributes| attributes.contains(SFGAO_FOLDER) && !attributes.contains(SFGAO_STREAM)),
+            File::Error(..) => false,
+        }
+    }
+    /// Binds the file so its children can be listed.
+    /// Anything that can't hold children comes back as `None`.
+    fn to_folder(&self) -> Result<Option<Folder>, ShellError> {
+        match self {
+            File::Shell { itemid, display, for_parsing, icon, .. } => unsafe {
+                let item = itemid
+                    .shell_item()
+                    .map_err(|e| ShellError::new(format!("opening {display}"), e.code()))?;
+                let attributes = item
+                    .GetAttributes(SFGAO_FOLDER)
+                    .map_err(|e| ShellError::new(format!("opening {display}"), e.code()))?;
+                if !attributes.contains(SFGAO_FOLDER) {
+                    return Ok(None);
+                }
+                Ok(Some(Folder::Shell {
+                    item,
+                    itemid: itemid.clone(),
+                    display: display.clone(),
+                    icon: *icon,
+                    for_parsing: for_parsing.clone(),
+                }))
+            },
+            File::Error(error) => Err(error.clone()),
+        }
+    }
+}
+
+/// How a column shows its children
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ViewMode {
+    /// A list of names with small icons
+    Details,
+    /// A grid of `thumbnails::THUMBNAIL_SIZE` thumbnails
+    Thumbnails,
+}
+
+/// What a `Folder::Selection` column is sorted by, from clicking its headers
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum SortKey {
+    Name,
+    /// The folder each item is in
+    Folder,
+    Size,
+}
+
+/// Shows what's selected in `columns[index]` in the column after it, and empties the ones
+/// after that. Returns `false` if there was a selection but no column to show it in.
+fn cascade_selection(columns: &mut VecDeque<Column>, index: usize) -> bool {
+    let mut selection = columns.get(index).map(|column| column.selection.clone()).filter(|selection| selection.len() > 0);
+    for column in columns.iter_mut().skip(index + 1) {
+        match selection.take() {
+            Some(selection) if selection.len() == 1 => column.switch_into(selection.into_iter().next()),
+            Some(selection) => column.switch(Some(Folder::Selection { selection })),
+            None => column.switch(None),
+        }
+    }
+    selection.is_none()
+}
+
+fn search_header(found: usize, finished: bool) -> String {
+    if finished {
+        format!("{found} found")
+    } else {
+        format!("{found} found, searching...")
+    }
+}
+
+/// Writes a byte count the way Explorer does, to one decimal place
+fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["bytes", "KB", "MB", "GB"] {
+        if size < 1024.0 {
+            return if unit == "bytes" { format!("{bytes} bytes") } else { format!("{size:.1} {unit}") };
+        }
+        size /= 1024.0;
+    }
+    format!("{size:.1} TB")
 }
 
 struct Column {
@@ -75,68 +422,469 @@ struct Column {
     proxy_icon_handler: nwg::EventHandler,
     /// All the items selected in this column
     selection: HashSet<File>,
+    /// Shown instead of the list view when the folder is an error
+    error_label: nwg::Label,
+    /// Repeats whatever failed
+    retry_button: nwg::Button,
+    /// Opens the failed folder in Explorer, only shown when access was denied. Explorer
+    /// never runs elevated, even for `runas`, but it offers to grant access with an
+    /// administrator's consent.
+    admin_button: nwg::Button,
+    /// The event handler, bound to the retry button
+    retry_handler: nwg::EventHandler,
+    /// The event handler, bound to the admin button
+    admin_handler: nwg::EventHandler,
+    /// Catches middle clicks in the list view, which nwg doesn't pass on
+    middle_click_handler: Option<nwg::RawEventHandler>,
+    /// Looks up the icons of `children` in the background
+    icons: Rc<icons::IconCache>,
+    /// Tells this column's icon lookups apart from those of whatever it showed before
+    icon_generation: u64,
+    view_mode: ViewMode,
+    /// Renders thumbnails in the background, for `ViewMode::Thumbnails`
+    thumbnails: Rc<thumbnails::ThumbnailCache>,
+    /// Like `icon_generation`, for `thumbnails`
+    thumbnail_generation: u64,
+    /// The list view's normal image list, holding the thumbnails rendered so far
+    thumbnail_list: nwg::ImageList,
+    /// Whether the column is scrolled into view, and so in the layouts
+    in_view: bool,
+    /// Whether `proxy_icon` has anything to show
+    has_proxy_icon: bool,
+    width: i32,
+    /// Where the column was last placed, relative to the window
+    left: i32,
+    /// Where `width` comes from, and where the user's changes to it go
+    widths: Rc<RefCell<widths::ColumnWidths>>,
+    /// Where visits to folders are counted
+    frecency: Rc<RefCell<frecency::Frecency>>,
+    /// Whether the list view has the Folder and Size columns of a `Folder::Selection`
+    detail_columns: bool,
+    /// How many of a `Folder::Search`'s hits are in `children`, or were skipped as gone
+    hits_seen: usize,
+    sort_key: SortKey,
+    sort_descending: bool,
 }
 
 impl Column {
-    fn switch_into(&mut self, file: Option<File>, folder: Option<Folder>) {
+    fn switch_into(&mut self, file: Option<File>) {
         let file = if let Some(file) = file {
             file
         } else {
             self.switch(None);
             return;
         };
-        match (file, folder.clone()) {
-            (File::Error(err), _) | (_, Some(Folder::Error(err))) => {
-                println!("{err:?}");
+        match file.to_folder() {
+            Ok(folder) => self.switch(folder),
+            Err(error) => {
+                let retry = match file {
+                    File::Shell { .. } => Some(Retry::SwitchInto(file)),
+                    File::Error(..) => None,
+                };
+                self.switch(Some(Folder::Error { error, retry }));
+            }
+        }
+    }
+    /// Shows placeholder icons for `rows` and queues the real ones under `icon_generation`,
+    /// the same way `switch` does for a shell folder's children
+    fn look_up_icons(&mut self, rows: impl IntoIterator<Item = usize>) {
+        let handle = self.list_view.handle.hwnd().unwrap() as usize;
+        for row in rows {
+            if let Some(File::Shell { itemid, for_parsing, icon, .. }) = self.children.get_mut(row) {
+                if let Ok(item) = itemid.shell_item() {
+                    *icon = Some(self.icons.lookup(handle, self.icon_generation, row, &item, itemid, for_parsing));
+                }
+            }
+        }
+    }
+    /// Swaps a placeholder icon for the real one
+    fn set_icon(&mut self, row: usize, icon: i32) {
+        if let Some(File::Shell { display, icon: old_icon, .. }) = self.children.get_mut(row) {
+            *old_icon = Some(icon);
+            if self.view_mode != ViewMode::Details {
                 return;
             }
-            (_, Some(Folder::Selection { .. })) => {
-                return self.switch(folder);
-            }
-            (File::Shell { itemid, display, icon, for_parsing }, Some(Folder::Shell { sysobj, display: _, icon: _, for_parsing: _ })) => {
-                self.switch(Some(unsafe {
-                    match sysobj.BindToObject(itemid.0, None) {
-                        Ok(sysobj) => {
-                            Folder::Shell {
-                                display,
-                                sysobj,
-                                icon,
-                                for_parsing,
-                            }
-                        },
-                        Err(e) => Folder::Error(format!("{e:?}")),
-                    }
-                }))
+            self.list_view.update_item(row, nwg::InsertListViewItem {
+                index: Some(TryInto::<i32>::try_into(row).unwrap()),
+                column_index: 0,
+                text: Some(display.clone()),
+                image: Some(icon),
+            });
+        }
+    }
+    /// Takes ownership of a rendered thumbnail and shows it on its row
+    fn set_thumbnail(&mut self, row: usize, bitmap: usize) {
+        let index = thumbnails::add_to(&self.thumbnail_list, bitmap);
+        if let Some(File::Shell { display, .. }) = self.children.get(row) {
+            self.list_view.update_item(row, nwg::InsertListViewItem {
+                index: Some(TryInto::<i32>::try_into(row).unwrap()),
+                column_index: 0,
+                text: Some(display.clone()),
+                image: Some(index),
+            });
+        }
+    }
+    fn set_view_mode(&mut self, view_mode: ViewMode) {
+        if self.view_mode == view_mode {
+            return;
+        }
+        self.view_mode = view_mode;
+        self.list_view.set_list_style(match view_mode {
+            ViewMode::Details => nwg::ListViewStyle::Detailed,
+            ViewMode::Thumbnails => nwg::ListViewStyle::Icon,
+        });
+        self.fill_list_view();
+        for (row, child) in self.children.iter().enumerate() {
+            if self.selection.contains(child) {
+                self.list_view.select_item(row, true);
             }
-            (File::Shell { itemid, display, icon, for_parsing }, None) => {
-                self.switch(Some(unsafe {
-                    match win32shell::SHGetDesktopFolder() {
-                        Ok(sysobj) => {
-                            match sysobj.BindToObject(itemid.0, None) {
-                                Ok(sysobj) => Folder::Shell {
-                                    display,
-                                    sysobj,
-                                    icon,
-                                    for_parsing,
-                                },
-                                Err(e) => Folder::Error(format!("{e:?}")),
-                            }
-                        }
-                        Err(e) => Folder::Error(format!("{e:?}")),
-                    }
-                }))
+        }
+    }
+    /// Puts `children` in the list view, with images to suit `view_mode`
+    fn fill_list_view(&mut self) {
+        let handle = self.list_view.handle.hwnd().unwrap() as usize;
+        self.thumbnail_generation = self.thumbnails.begin(handle);
+        thumbnails::clear(&self.thumbnail_list);
+        self.list_view.set_redraw(false);
+        self.list_view.clear();
+        self.list_view.set_item_count(TryInto::<u32>::try_into(self.children.len()).unwrap());
+        let mut total_size = 0;
+        // Folders, which would take a walk of everything inside them to size
+        let mut uncounted = 0;
+        for row in 0..self.children.len() {
+            let size = self.insert_row(row);
+            total_size += size.unwrap_or(0);
+            uncounted += usize::from(size.is_none());
+        }
+        if let Some(Folder::Search { search, .. }) = &self.folder {
+            self.set_name_header(search_header(self.children.len(), search.finished()));
+        } else if let Some(Folder::Recent) = &self.folder {
+            self.set_name_header("Recent folders".to_owned());
+        } else if self.detail_columns {
+            let mut header = format!("{} items, {}", self.children.len(), format_size(total_size));
+            if uncounted > 0 {
+                header.push_str(&format!(" ({uncounted} folders not counted)"));
+            }
+            self.set_name_header(header);
+        }
+        self.list_view.set_redraw(true);
+    }
+    /// Puts `children[row]` in the list view. Returns its size if it's a file
+    /// and the detail columns are showing.
+    fn insert_row(&self, row: usize) -> Option<u64> {
+        let handle = self.list_view.handle.hwnd().unwrap() as usize;
+        let child = &self.children[row];
+        let i = TryInto::<i32>::try_into(row).unwrap();
+        let (text, image) = match child {
+            File::Shell { itemid, display, icon, .. } => match self.view_mode {
+                ViewMode::Details => (display.clone(), *icon),
+                ViewMode::Thumbnails => {
+                    self.thumbnails.request(handle, self.thumbnail_generation, row, itemid);
+                    (display.clone(), None)
+                }
+            },
+            File::Error(error) => (error.to_string().replace("\r\n", " "), None),
+        };
+        self.list_view.insert_item(nwg::InsertListViewItem {
+            text: Some(text),
+            image,
+            index: Some(i),
+            column_index: 0,
+        });
+        if !self.detail_columns {
+            return None;
+        }
+        let size = child.size();
+        let folder = match &self.folder {
+            // Where the hit is under the folder searched from
+            Some(Folder::Search { for_parsing, .. }) => {
+                let parent = child.parent_path();
+                let root = for_parsing.to_string_lossy();
+                let relative = parent.get(root.len()..).filter(|_| parent.get(..root.len()).is_some_and(|start| start.eq_ignore_ascii_case(&root)));
+                match relative.map(|relative| relative.trim_start_matches('\\')) {
+                    Some("") => ".".to_owned(),
+                    Some(relative) => relative.to_owned(),
+                    None => parent.clone(),
+                }
+            }
+            _ => child.parent_path(),
+        };
+        self.list_view.insert_item(nwg::InsertListViewItem {
+            text: Some(folder),
+            image: None,
+            index: Some(i),
+            column_index: 1,
+        });
+        self.list_view.insert_item(nwg::InsertListViewItem {
+            text: Some(size.map_or(String::new(), format_size)),
+            image: None,
+            index: Some(i),
+            column_index: 2,
+        });
+        size
+    }
+    /// The hits `search` has found since the column last looked, skipping any gone since
+    fn new_hits(&mut self, search: &search::Search) -> Vec<File> {
+        let hits = search.hits_since(self.hits_seen);
+        self.hits_seen += hits.len();
+        hits.iter()
+            .filter_map(|path| ItemId::from_parsing_name(&path.to_string_lossy()).ok())
+            .filter_map(|itemid| File::named(itemid, None).ok())
+            .collect()
+    }
+    /// Adds a `Folder::Search`'s new hits to the end of the list
+    fn add_search_hits(&mut self) {
+        let Some(Folder::Search { search, .. }) = self.folder.clone() else {
+            return;
+        };
+        let hits = self.new_hits(&search);
+        let first = self.children.len();
+        self.children.extend(hits);
+        self.look_up_icons(first..self.children.len());
+        self.list_view.set_redraw(false);
+        self.list_view.set_item_count(TryInto::<u32>::try_into(self.children.len()).unwrap());
+        for row in first..self.children.len() {
+            self.insert_row(row);
+        }
+        self.set_name_header(search_header(self.children.len(), search.finished()));
+        self.list_view.set_redraw(true);
+    }
+    fn set_name_header(&self, text: String) {
+        self.list_view.update_column(0, nwg::InsertListViewColumn {
+            index: Some(0),
+            fmt: None,
+            width: None,
+            text: Some(text),
+        });
+    }
+    /// Adds or takes away the Folder and Size columns that a `Folder::Selection` has
+    fn set_detail_columns(&mut self, detail_columns: bool) {
+        if self.detail_columns == detail_columns {
+            return;
+        }
+        if detail_columns {
+            for (index, text, fmt) in [(1, "Folder", None), (2, "Size", Some(nwg::ListViewColumnFlags::RIGHT))] {
+                self.list_view.insert_column(nwg::InsertListViewColumn {
+                    index: Some(index),
+                    fmt,
+                    width: Some(100),
+                    text: Some(text.into()),
+                });
+            }
+        } else {
+            self.list_view.remove_column(2);
+            self.list_view.remove_column(1);
+            self.set_name_header("Name".into());
+        }
+        self.detail_columns = detail_columns;
+        self.fit_list_columns();
+    }
+    /// Orders a `Folder::Selection` or `Folder::Search` column's items by `sort_key`
+    fn sort_children(&mut self) {
+        let key = self.sort_key;
+        self.children.sort_by_cached_key(|child| {
+            let name = match child {
+                File::Shell { display, .. } => display.to_lowercase(),
+                File::Error(..) => String::new(),
+            };
+            match key {
+                SortKey::Name => (0, name, String::new()),
+                SortKey::Folder => (0, child.parent_path().to_lowercase(), name),
+                SortKey::Size => (child.size().unwrap_or(0), name, String::new()),
+            }
+        });
+        if self.sort_descending {
+            self.children.reverse();
+        }
+    }
+    /// Sorts by `key`, or the other way round if it's sorted by that already
+    fn sort_by(&mut self, key: SortKey) {
+        if !self.detail_columns {
+            return;
+        }
+        self.sort_descending = self.sort_key == key && !self.sort_descending;
+        self.sort_key = key;
+        self.sort_children();
+        // Rows queued before the sort would get their icons in the wrong places
+        self.icon_generation = self.icons.begin(self.list_view.handle.hwnd().unwrap() as usize);
+        self.look_up_icons(0..self.children.len());
+        self.fill_list_view();
+        for (row, child) in self.children.iter().enumerate() {
+            if self.selection.contains(child) {
+                self.list_view.select_item(row, true);
             }
         }
     }
+    /// The selected items, in the order they're listed
+    fn selected_itemids(&self) -> Vec<Rc<ItemId>> {
+        self.children
+            .iter()
+            .filter(|child| self.selection.contains(child))
+            .filter_map(|child| match child {
+                File::Shell { itemid, .. } => Some(itemid.clone()),
+                File::Error(..) => None,
+            })
+            .collect()
+    }
+    /// The parsing names of the selected items, in the order they're listed
+    fn selected_paths(&self) -> Vec<String> {
+        self.children
+            .iter()
+            .filter(|child| self.selection.contains(child))
+            .filter_map(|child| match child {
+                File::Shell { for_parsing, .. } => Some(for_parsing.to_string_lossy()),
+                File::Error(..) => None,
+            })
+            .collect()
+    }
+    /// The parsing names of what the proxy icon stands for
+    fn folder_paths(&self) -> Vec<String> {
+        match &self.folder {
+            Some(Folder::Shell { for_parsing, .. }) | Some(Folder::Search { for_parsing, .. }) => vec![for_parsing.to_string_lossy()],
+            Some(Folder::Selection { selection }) => selection
+                .iter()
+                .filter_map(|file| match file {
+                    File::Shell { for_parsing, .. } => Some(for_parsing.to_string_lossy()),
+                    File::Error(..) => None,
+                })
+                .collect(),
+            Some(Folder::Recent) | Some(Folder::Error { .. }) | None => Vec::new(),
+        }
+    }
+    /// Scrolls the list view until `row` is in sight
+    fn scroll_to(&self, row: usize) {
+        let hwnd = HWND(self.list_view.handle.hwnd().unwrap() as *mut _);
+        unsafe { win32wam::SendMessageW(hwnd, win32controls::LVM_ENSUREVISIBLE, WPARAM(row), LPARAM(0)) };
+    }
+    /// Puts the name in `row` into an edit box, for `StaplerApp::bind_renames` to pick up
+    fn edit_name(&self, row: usize) {
+        self.list_view.set_focus();
+        let hwnd = HWND(self.list_view.handle.hwnd().unwrap() as *mut _);
+        unsafe { win32wam::SendMessageW(hwnd, win32controls::LVM_EDITLABELW, WPARAM(row), LPARAM(0)) };
+    }
+    /// Lists the folder again, keeping whatever is still there selected
+    fn refresh(&mut self) {
+        let selection = std::mem::take(&mut self.selection);
+        self.switch(self.folder.clone());
+        for (row, child) in self.children.iter().enumerate() {
+            if selection.contains(child) {
+                self.list_view.select_item(row, true);
+                self.selection.insert(child.clone());
+            }
+        }
+    }
+    /// Runs the `Retry` of an error column, if it has one
+    fn retry(&mut self) {
+        match self.folder.clone() {
+            Some(Folder::Error { retry: Some(Retry::SwitchInto(file)), .. }) => {
+                self.switch_into(Some(file));
+            }
+            Some(Folder::Error { retry: Some(Retry::Switch(folder)), .. }) => {
+                self.switch(Some(*folder));
+            }
+            _ => {}
+        }
+    }
+    /// Fills in the error controls, which `update_visibility` will show in place of the list view
+    fn show_error(&self, error: &ShellError) {
+        self.error_label.set_text(&error.to_string());
+        self.layout_error();
+    }
+    /// Shows the list view, or the error controls that replace it, if the column is in view
+    fn update_visibility(&self) {
+        let error = match &self.folder {
+            Some(Folder::Error { error, .. }) => Some(error),
+            _ => None,
+        };
+        self.list_view.set_visible(self.in_view && error.is_none());
+        self.error_label.set_visible(self.in_view && error.is_some());
+        self.retry_button.set_visible(self.in_view && error.is_some());
+        self.admin_button.set_visible(self.in_view && error.is_some_and(|error| error.is_access_denied()));
+        self.proxy_icon.set_visible(self.in_view && self.has_proxy_icon);
+    }
+    /// What the column's width is remembered under
+    fn folder_key(&self) -> Option<String> {
+        match &self.folder {
+            Some(Folder::Shell { for_parsing, .. }) => Some(for_parsing.to_string_lossy()),
+            _ => None,
+        }
+    }
+    /// Puts the column at `left`, below `top`, with its list view reaching down to `bottom`
+    fn place(&mut self, left: i32, top: i32, bottom: i32) {
+        self.left = left;
+        let width = TryInto::<u32>::try_into(self.width).unwrap();
+        self.proxy_icon.set_position(left, top);
+        self.proxy_icon.set_size(width, TryInto::<u32>::try_into(PROXY_ICON_HEIGHT).unwrap());
+        self.list_view.set_position(left, top + PROXY_ICON_HEIGHT);
+        self.list_view.set_size(width, TryInto::<u32>::try_into((bottom - top - PROXY_ICON_HEIGHT).max(0)).unwrap());
+        self.fit_list_columns();
+        if let Some(Folder::Error { .. }) = self.folder {
+            self.layout_error();
+        }
+    }
+    /// Shares the list view's width out between its columns
+    fn fit_list_columns(&self) {
+        let scroll_bar = unsafe { win32wam::GetSystemMetrics(win32wam::SM_CXVSCROLL) };
+        let available = (self.width - scroll_bar - 4).max(0);
+        if self.detail_columns {
+            let name = available * 45 / 100;
+            let folder = available * 35 / 100;
+            self.list_view.set_column_width(0, name as isize);
+            self.list_view.set_column_width(1, folder as isize);
+            self.list_view.set_column_width(2, (available - name - folder) as isize);
+        } else {
+            self.list_view.set_column_width(0, available as isize);
+        }
+    }
+    /// The width that shows the longest name in full
+    fn fitted_width(&self) -> i32 {
+        let hwnd = HWND(self.list_view.handle.hwnd().unwrap() as *mut _);
+        let longest = self
+            .children
+            .iter()
+            .map(|child| {
+                let text = match child {
+                    File::Shell { display, .. } => HSTRING::from(display.as_str()),
+                    File::Error(error) => HSTRING::from(error.to_string().replace("\r\n", " ")),
+                };
+                unsafe { win32wam::SendMessageW(hwnd, win32controls::LVM_GETSTRINGWIDTHW, WPARAM(0), LPARAM(text.as_ptr() as isize)) }.0 as i32
+            })
+            .max()
+            .unwrap_or(0);
+        let scroll_bar = unsafe { win32wam::GetSystemMetrics(win32wam::SM_CXVSCROLL) };
+        // The small icon, the padding around it and the name, the scroll bar, and the border
+        (longest + 16 + 18 + scroll_bar + 4).max(widths::MIN_COLUMN_WIDTH)
+    }
+    /// Places the error controls over the list view's spot in the layout
+    fn layout_error(&self) {
+        let (x, y) = self.list_view.position();
+        let (width, _height) = self.list_view.size();
+        self.error_label.set_position(x + 8, y + 8);
+        self.error_label.set_size(width.saturating_sub(16), 120);
+        self.retry_button.set_position(x + 8, y + 136);
+        self.retry_button.set_size(100, 28);
+        self.admin_button.set_position(x + 116, y + 136);
+        self.admin_button.set_size(180, 28);
+    }
     fn switch(&mut self, folder: Option<Folder>) {
+        let handle = self.list_view.handle.hwnd().unwrap() as usize;
+        self.icon_generation = self.icons.begin(handle);
         self.children.clear();
         self.list_view.clear();
+        // Listing the same folder again, as a refresh does, isn't another visit
+        if let Some(Folder::Shell { for_parsing, .. }) = &folder {
+            if !matches!(&self.folder, Some(Folder::Shell { for_parsing: showing, .. }) if showing == for_parsing) {
+                self.frecency.borrow_mut().visit(&for_parsing.to_string_lossy());
+            }
+        }
         self.folder = folder.clone();
+        self.width = self.widths.borrow().width_for(self.folder_key().as_deref());
         self.selection.clear();
+        self.hits_seen = 0;
+        self.set_detail_columns(matches!(self.folder, Some(Folder::Selection { .. }) | Some(Folder::Search { .. }) | Some(Folder::Recent)));
         if let Some(folder) = folder {
-            let handle = self.list_view.handle.hwnd().unwrap() as usize;
             // jump to `StaplerApp::on_load_notice` for the rest of this
-            match folder {
+            match folder.clone() {
                 Folder::Selection { selection } => unsafe {
                     let mut big = win32controls::HIMAGELIST::default();
                     win32shell::Shell_GetImageLists(Some(&mut big), None);
@@ -146,13 +894,20 @@ impl Column {
                             handle: hicon.0 as *mut _,
                             owned: false,
                         }));
-                        self.proxy_icon.set_visible(selection.len() > 0);
+                        self.has_proxy_icon = selection.len() > 0;
                     } else {
-                        self.proxy_icon.set_visible(false);
+                        self.has_proxy_icon = false;
                     };
                     std::mem::forget(image_list_big);
+                    self.children = selection.into_iter().collect();
+                    self.sort_children();
+                    // Items picked out of other columns come with their icons, saved sets don't
+                    let missing: Vec<usize> = (0..self.children.len())
+                        .filter(|&row| matches!(self.children[row], File::Shell { icon: None, .. }))
+                        .collect();
+                    self.look_up_icons(missing);
                 }
-                Folder::Shell { sysobj, display: _, icon, for_parsing: _ } => unsafe {
+                Folder::Search { icon, search, .. } => unsafe {
                     let mut big = win32controls::HIMAGELIST::default();
                     win32shell::Shell_GetImageLists(Some(&mut big), None);
                     let image_list_big = win32controls::IImageList::from_raw(big.0 as *mut _);
@@ -161,76 +916,304 @@ impl Column {
                             handle: hicon.0 as *mut _,
                             owned: false,
                         }));
-                        self.proxy_icon.set_visible(true);
+                        self.has_proxy_icon = true;
                     } else {
-                        self.proxy_icon.set_visible(false);
+                        self.has_proxy_icon = false;
                     };
                     std::mem::forget(image_list_big);
-                    let mut penumidlist = None;
-                    sysobj.EnumObjects(
-                        HWND::default(),
-                        TryInto::<u32>::try_into(win32shell::SHCONTF_FOLDERS.0 | win32shell::SHCONTF_NONFOLDERS.0).unwrap(),
-                        &mut penumidlist,
-                    );
-                    if let Some(enumidlist) = penumidlist {
-                        let mut rgelt = [std::ptr::null_mut(); 1];
-                        let mut fetched_count = 0;
-                        while enumidlist.Next(&mut rgelt[..], Some(&mut fetched_count)).is_ok() && fetched_count != 0 {
-                            for i in 0..fetched_count {
-                                let mut display_name_ret = win32shell::Common::STRRET::default();
-                                sysobj.GetDisplayNameOf(rgelt[i as usize] as *const _, win32shell::SHGDN_INFOLDER, &mut display_name_ret);
-                                let mut display_name_w = [0u16; 260];
-                                win32shell::StrRetToBufW(&mut display_name_ret, Some(rgelt[i as usize] as *const _), &mut display_name_w);
-                                let mut for_parsing_ret = win32shell::Common::STRRET::default();
-                                sysobj.GetDisplayNameOf(rgelt[i as usize] as *const _, win32shell::SHGDN_FORPARSING, &mut for_parsing_ret);
-                                let mut for_parsing = [0u16; 1024];
-                                win32shell::StrRetToBufW(&mut for_parsing_ret, Some(rgelt[i as usize] as *const _), &mut for_parsing);
-                                self.children.push(File::Shell {
-                                    itemid: Rc::new(ItemId(rgelt[i as usize])),
-                                    display: OsString::from_wide(&display_name_w).display().to_string(),
-                                    for_parsing: Vec::from(for_parsing),
-                                    icon: Some(win32shell::SHMapPIDLToSystemImageListIndex(
-                                        &sysobj,
-                                        rgelt[i as usize],
-                                        None,
-                                    )),
-                                });
+                    // In the order they were found, which is the order more will arrive in
+                    self.children = self.new_hits(&search);
+                    self.look_up_icons(0..self.children.len());
+                }
+                Folder::Recent => {
+                    self.has_proxy_icon = false;
+                    let ranked = self.frecency.borrow().ranked();
+                    for path in ranked {
+                        if self.children.len() == RECENT_FOLDERS {
+                            break;
+                        }
+                        match ItemId::from_parsing_name(&path) {
+                            Ok(itemid) => self.children.push(File::named(itemid, None).unwrap_or_else(File::Error)),
+                            // Gone since, so not worth offering again
+                            Err(_) => self.frecency.borrow_mut().forget(&path),
+                        }
+                    }
+                    self.look_up_icons(0..self.children.len());
+                }
+                Folder::Shell { item, itemid: _, display, icon, for_parsing: _ } => unsafe {
+                    let mut big = win32controls::HIMAGELIST::default();
+                    win32shell::Shell_GetImageLists(Some(&mut big), None);
+                    let image_list_big = win32controls::IImageList::from_raw(big.0 as *mut _);
+                    if let Some(hicon) = icon.and_then(|icon| image_list_big.GetIcon(icon, 0).ok()) {
+                        self.proxy_icon.set_icon(Some(&nwg::Icon {
+                            handle: hicon.0 as *mut _,
+                            owned: false,
+                        }));
+                        self.has_proxy_icon = true;
+                    } else {
+                        self.has_proxy_icon = false;
+                    };
+                    std::mem::forget(image_list_big);
+                    let started = Instant::now();
+                    let enumerator: win32shell::IEnumShellItems = match item.BindToHandler(None, &win32shell::BHID_EnumItems) {
+                        Ok(enumerator) => enumerator,
+                        Err(e) => {
+                            return self.switch(Some(Folder::Error {
+                                error: ShellError::new(format!("listing {display}"), e.code()),
+                                retry: Some(Retry::Switch(Box::new(folder))),
+                            }));
+                        }
+                    };
+                    let mut rgelt = [None];
+                    let mut fetched_count = 0;
+                    loop {
+                        if let Err(e) = enumerator.Next(&mut rgelt[..], Some(&mut fetched_count)) {
+                            self.children.push(File::Error(ShellError::new(format!("listing {display}"), e.code())));
+                            break;
+                        }
+                        if fetched_count == 0 {
+                            break;
+                        }
+                        for child in rgelt.iter_mut().take(fetched_count as usize) {
+                            if let Some(child) = child.take() {
+                                let mut file = File::from_item(&child).unwrap_or_else(File::Error);
+                                if let File::Shell { itemid, for_parsing, icon, .. } = &mut file {
+                                    *icon = Some(self.icons.lookup(handle, self.icon_generation, self.children.len(), &child, itemid, for_parsing));
+                                }
+                                self.children.push(file);
                             }
                         }
                     }
+                    log::debug!("listed {} items in {display} in {:?}", self.children.len(), started.elapsed());
                 },
-                Folder::Error(err) => {
-                    self.proxy_icon.set_visible(false);
-                    println!("{err:?}");
+                Folder::Error { error, retry: _ } => {
+                    self.has_proxy_icon = false;
+                    self.show_error(&error);
                 },
             }
-            self.list_view.set_redraw(false);
-            self.list_view.set_item_count(TryInto::<u32>::try_into(self.children.len()).unwrap());
-            let mut i = 0;
-            for child in &self.children {
-                let (text, image) = match child {
-                    File::Shell { itemid: _, display, icon, for_parsing: _ } => (display.clone(), *icon),
-                    File::Error(string) => (format!("{string:?}"), None),
-                };
-                self.list_view.insert_item(nwg::InsertListViewItem {
-                    text: Some(text),
-                    image,
-                    index: Some(i),
-                    column_index: 0,
-                });
-                i += 1;
-            }
-            self.list_view.set_redraw(true);
+            self.fill_list_view();
         } else {
-            self.proxy_icon.set_visible(false);
+            self.has_proxy_icon = false;
         }
+        self.update_visibility();
     }
 }
 
 const DEFAULT_WIDTH: i32 = 800;
 const DEFAULT_HEIGHT: i32 = 600;
-fn calculate_column_count(window_width: i32) -> i32 {
-    (window_width / 300) + 1
+const SCROLL_BAR_HEIGHT: i32 = 17;
+/// The Pick and Cancel buttons along the bottom with `--pick`
+const PICK_BAR_HEIGHT: i32 = 32;
+/// The output panel, when it's showing, and the part of it with its name and buttons
+const OUTPUT_PANEL_HEIGHT: i32 = 200;
+const OUTPUT_HEADER_HEIGHT: i32 = 30;
+const PROXY_ICON_HEIGHT: i32 = 64;
+/// The draggable gap between two columns
+const SPLITTER_WIDTH: i32 = 5;
+/// For `nwg::bind_raw_event_handler`, which wants ids above 0xFFFF
+const SPLITTER_HANDLER_ID: usize = 0x10000;
+const SHELF_DRAG_HANDLER_ID: usize = 0x10001;
+const RENAME_HANDLER_ID: usize = 0x10002;
+const MIDDLE_CLICK_HANDLER_ID: usize = 0x10003;
+const TAB_HANDLER_ID: usize = 0x10004;
+const TAB_STRIP_HEIGHT: i32 = 26;
+/// How many folders back a tab remembers
+const TAB_HISTORY: usize = 100;
+/// How many of the best matches Go to anything lists
+const GOTO_HITS: usize = 50;
+/// How many folders Recent folders and the jump dialog offer
+const RECENT_FOLDERS: usize = 50;
+
+/// Something asked for from a list view's keyboard shortcuts, to be run
+/// by `StaplerApp::on_command_notice` once the list view's handler is done
+#[derive(Clone, Copy, Debug)]
+enum Command {
+    Cut,
+    Copy,
+    Paste,
+    /// Pops up `StaplerApp::path_menu` for the focused column's selection,
+    /// or for what its proxy icon stands for
+    CopyPathAs { of_folder: bool },
+    /// Puts the focused column's selection on the shelf
+    Staple,
+    /// Shows the shelf again after staples were dragged out of it, in case they moved
+    ShelfDragged,
+    /// Takes the items selected in a `Folder::Selection` column out of it
+    RemoveFromSet,
+    SelectMatching,
+    Undo,
+    Redo,
+    NewFolder,
+    /// Makes a file from the template at this index in `StaplerApp::templates`
+    NewFile(usize),
+    /// Starts editing the name of the focused row
+    EditName,
+    /// Renames the item whose label was edited, see `StaplerApp::pending_rename`
+    Rename,
+    /// Shows the saved set at this index in `StaplerApp::set_names`
+    OpenSet(usize),
+    DeleteSet(usize),
+    GoTo,
+    Jump,
+    /// Goes back to the folder the active tab showed before
+    Back,
+    /// Goes forward again after `Command::Back`
+    Forward,
+    /// Opens a tab showing the focused column's folder
+    NewTab,
+    /// Closes the tab at this index, or the active one
+    CloseTab(Option<usize>),
+    /// Shows the tab the strip has selected
+    SwitchTab,
+    /// Drags a tab to another place in the strip
+    MoveTab { from: usize, to: usize },
+    /// Opens the folder in this row of the focused column in a new tab
+    OpenInNewTab(usize),
+    /// Picks the focused column's selection, from double-clicking it with `--pick`
+    Pick,
+    /// Runs the user's command at this index in `StaplerApp::user_commands`
+    RunUserCommand(usize),
+    /// Pops up `StaplerApp::commands_popup`, from a right click in a column
+    CommandsPopup,
+    OpenTerminal,
+    /// Shows the output panel, ready for a command to run in the focused column's folder
+    RunHere,
+}
+
+/// A chain of columns of its own, shown when its name is picked in `StaplerApp::tab_strip`
+#[derive(Default)]
+struct Tab {
+    /// Empty while the tab is active, when its chain is in `StaplerApp::columns`
+    columns: VecDeque<Column>,
+    /// `StaplerApp::first_visible_column` for the chain, from when it was last active
+    first_visible_column: usize,
+    /// The folders the tab showed before, latest last, with `None` for just the Desktop
+    back: Vec<Option<String>>,
+    /// The folders gone back from, latest last
+    forward: Vec<Option<String>>,
+    /// Where the chain was when the tab's history was last brought up to date, see `chain_folder`
+    shown: Option<String>,
+}
+
+/// What a tab showing `columns` is called in the strip: the deepest thing it has open
+fn tab_title(columns: &VecDeque<Column>) -> String {
+    columns
+        .iter()
+        .rev()
+        .find_map(|column| match &column.folder {
+            Some(Folder::Shell { display, .. }) => Some(display.clone()),
+            Some(Folder::Selection { .. }) => Some("Selection".to_owned()),
+            Some(Folder::Search { .. }) => Some("Search".to_owned()),
+            Some(Folder::Recent) => Some("Recent folders".to_owned()),
+            Some(Folder::Error { .. }) | None => None,
+        })
+        .unwrap_or_else(|| "Desktop".to_owned())
+}
+
+/// What `StaplerApp::reveal` would need to open `columns` again: the deepest folder
+/// shown, or the one thing selected in it
+fn chain_path(columns: &VecDeque<Column>) -> Option<String> {
+    let column = columns.iter().rev().find(|column| matches!(column.folder, Some(Folder::Shell { .. })))?;
+    match column.selection.iter().next() {
+        Some(File::Shell { for_parsing, .. }) if column.selection.len() == 1 => Some(for_parsing.to_string_lossy()),
+        _ => column.folder_paths().pop(),
+    }
+}
+
+/// The deepest folder below the Desktop that `columns` show, which is what a tab's
+/// Back and Forward go between
+fn chain_folder(columns: &VecDeque<Column>) -> Option<String> {
+    columns.iter().skip(1).rev().find_map(|column| match &column.folder {
+        Some(Folder::Shell { for_parsing, .. }) => Some(for_parsing.to_string_lossy()),
+        _ => None,
+    })
+}
+
+/// The rows selected in the list view `list`, in order
+fn selected_rows(list: HWND) -> Vec<usize> {
+    let mut rows = Vec::new();
+    let mut row = -1isize;
+    loop {
+        row = unsafe { win32wam::SendMessageW(list, win32controls::LVM_GETNEXTITEM, WPARAM(row as usize), LPARAM(win32controls::LVNI_SELECTED as isize)) }.0;
+        if row < 0 {
+            return rows;
+        }
+        rows.push(row as usize);
+    }
+}
+
+/// The staples at `indexes` on `shelf`, found again from their parsing names
+fn shelf_itemids(shelf: &shelf::Shelf, indexes: &[usize]) -> Result<Vec<Rc<ItemId>>, ShellError> {
+    indexes
+        .iter()
+        .filter_map(|&i| shelf.items().get(i))
+        .map(|path| {
+            ItemId::from_parsing_name(path)
+                .map(Rc::new)
+                .map_err(|e| ShellError::new(format!("finding {path}"), e.code()))
+        })
+        .collect()
+}
+
+/// How the control API describes a column
+fn column_json(column: &Column, focused: bool) -> serde_json::Value {
+    let (kind, folder) = match &column.folder {
+        Some(Folder::Shell { for_parsing, .. }) => ("folder", Some(for_parsing.to_string_lossy())),
+        Some(Folder::Selection { .. }) => ("selection", None),
+        Some(Folder::Search { for_parsing, .. }) => ("search", Some(for_parsing.to_string_lossy())),
+        Some(Folder::Recent) => ("recent", None),
+        Some(Folder::Error { .. }) => ("error", None),
+        None => ("empty", None),
+    };
+    let error = match &column.folder {
+        Some(Folder::Error { error, .. }) => Some(error.to_string()),
+        _ => None,
+    };
+    serde_json::json!({
+        "kind": kind,
+        "folder": folder,
+        "items": column.children.len(),
+        "selected": column.selected_paths(),
+        "focused": focused,
+        "error": error,
+    })
+}
+
+/// Moves the selection in a list of hits for the arrow keys, so a hit can be
+/// picked without leaving the text box above it
+fn step_hits(list: &nwg::ListBox<String>, key: u32) {
+    let count = list.len();
+    if count == 0 {
+        return;
+    }
+    let selected = list.selection();
+    match key {
+        0x26 => list.set_selection(Some(selected.map_or(0, |row| row.saturating_sub(1)))),
+        0x28 => list.set_selection(Some(selected.map_or(0, |row| (row + 1).min(count - 1)))),
+        _ => {}
+    }
+}
+
+/// The Desktop, for the first column of a chain
+fn desktop() -> Option<Folder> {
+    let desktop = unsafe { win32shell::SHGetDesktopFolder().and_then(|desktop| win32shell::SHGetIDListFromObject(&desktop)) }
+        .map_err(|e| ShellError::new("opening the Desktop", e.code()))
+        .and_then(|pidl| File::from_itemid(ItemId(pidl)))
+        .and_then(|desktop| desktop.to_folder());
+    match desktop {
+        Ok(desktop) => desktop,
+        Err(error) => Some(Folder::Error {
+            error,
+            retry: None,
+        }),
+    }
+}
+
+/// The column whose splitter, on its right, is at `x`
+fn splitter_at(columns: &VecDeque<Column>, x: i32) -> Option<usize> {
+    columns
+        .iter()
+        .position(|column| column.in_view && x >= column.left + column.width && x < column.left + column.width + SPLITTER_WIDTH)
 }
 
 #[derive(Default, NwgUi)]
@@ -245,68 +1228,799 @@ pub struct StaplerApp {
     )]
     window: nwg::Window,
 
-    #[nwg_layout(parent: window, max_row: Some(1), spacing: 3, max_size: [u32::MAX, 64])]
-    proxy_icon_grid_layout: nwg::GridLayout,
+    #[nwg_control(parent: window, text: "&File")]
+    file_menu: nwg::Menu,
 
-    #[nwg_layout(parent: window, max_row: Some(1), spacing: 3, margin: [64, 0, 0, 0])]
-    column_grid_layout: nwg::GridLayout,
+    #[nwg_control(parent: file_menu, text: "New &folder\tCtrl+Shift+N")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_new_folder])]
+    new_folder_item: nwg::MenuItem,
 
-    image_list_small: RefCell<nwg::ImageList>,
+    #[nwg_control(parent: file_menu, text: "&New")]
+    new_menu: nwg::Menu,
 
-    columns: Rc<RefCell<VecDeque<Column>>>,
-}
+    #[nwg_control(parent: file_menu, text: "New &tab\tCtrl+T")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_new_tab])]
+    new_tab_item: nwg::MenuItem,
 
-impl StaplerApp {
-    fn reconcile_columns(&self, desired_column_count: i32) {
-        let mut columns = self.columns.borrow_mut();
-        let needs_renumbered = TryInto::<i32>::try_into(columns.len()).unwrap() > desired_column_count;
-        while TryInto::<i32>::try_into(columns.len()).unwrap() > desired_column_count {
-            let destroyed = if columns.back().unwrap().folder.is_none() {
-                columns.pop_back()
-            } else {
-                columns.pop_front()
-            }.unwrap();
-            nwg::unbind_event_handler(&destroyed.list_handler);
-            nwg::unbind_event_handler(&destroyed.proxy_icon_handler);
-            self.proxy_icon_grid_layout.remove_child(destroyed.proxy_icon.handle);
-            self.column_grid_layout.remove_child(destroyed.list_view.handle);
-        }
-        if needs_renumbered {
-            let mut i = 0;
-            for column in columns.iter_mut() {
-                self.proxy_icon_grid_layout.remove_child(column.proxy_icon.handle);
-                self.column_grid_layout.remove_child(column.list_view.handle);
-                self.proxy_icon_grid_layout.add_child(i, 0, &column.proxy_icon);
-                self.column_grid_layout.add_child(i, 0, &column.list_view);
-                i += 1;
-            }
-        }
-        let column_count = TryInto::<i32>::try_into(columns.len()).unwrap();
-        std::mem::drop(columns);
-        let icon = unsafe {
-            let mut big = win32controls::HIMAGELIST::default();
-            win32shell::Shell_GetImageLists(Some(&mut big), None);
-            let image_list_big = win32controls::IImageList::from_raw(big.0 as *mut _);
-            let result = if let Ok(hicon) = image_list_big.GetIcon(0, 0) {
-                Some(nwg::Icon {
-                    handle: hicon.0 as *mut _,
-                    owned: false,
-                })
-            } else {
-                None
-            };
-            std::mem::forget(image_list_big);
-            result
-        };
-        for i in column_count .. desired_column_count {
-            let mut proxy_icon = nwg::ImageFrame::default();
-            nwg::ImageFrame::builder()
-                .parent(&self.window)
-                .size((64, 64))
-                .icon(icon.as_ref())
-                .build(&mut proxy_icon)
-                .expect("failed");
-            proxy_icon.set_visible(false);
+    #[nwg_control(parent: file_menu, text: "&Close tab\tCtrl+W")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_close_tab])]
+    close_tab_item: nwg::MenuItem,
+
+    #[nwg_control(parent: file_menu, text: "Open t&erminal here\tCtrl+Shift+T")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_open_terminal])]
+    open_terminal_item: nwg::MenuItem,
+
+    /// Which terminal Open terminal here starts, see `terminal::Terminal`
+    #[nwg_control(parent: file_menu, text: "Ter&minal")]
+    terminal_menu: nwg::Menu,
+
+    #[nwg_control(parent: terminal_menu, text: "&Windows Terminal")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_terminal_windows_terminal])]
+    terminal_windows_terminal_item: nwg::MenuItem,
+
+    #[nwg_control(parent: terminal_menu, text: "&Command Prompt")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_terminal_cmd])]
+    terminal_cmd_item: nwg::MenuItem,
+
+    #[nwg_control(parent: terminal_menu, text: "&PowerShell")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_terminal_powershell])]
+    terminal_powershell_item: nwg::MenuItem,
+
+    #[nwg_control(parent: terminal_menu, text: "W&SL")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_terminal_wsl])]
+    terminal_wsl_item: nwg::MenuItem,
+
+    #[nwg_control(parent: file_menu, text: "Run a comman&d here...\tCtrl+R")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_run_here])]
+    run_here_item: nwg::MenuItem,
+
+    #[nwg_control(parent: file_menu, text: "&Rename\tF2")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_edit_name])]
+    rename_item: nwg::MenuItem,
+
+    #[nwg_control(parent: file_menu)]
+    file_separator: nwg::MenuSeparator,
+
+    #[nwg_control(parent: file_menu, text: "Edit &templates...")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_edit_templates])]
+    edit_templates_item: nwg::MenuItem,
+
+    #[nwg_control(parent: file_menu, text: "Re&load templates")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::load_templates])]
+    reload_templates_item: nwg::MenuItem,
+
+    #[nwg_control(parent: window, text: "&Edit")]
+    edit_menu: nwg::Menu,
+
+    #[nwg_control(parent: edit_menu, text: "&Undo\tCtrl+Z", disabled: true)]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_undo])]
+    undo_item: nwg::MenuItem,
+
+    #[nwg_control(parent: edit_menu, text: "&Redo\tCtrl+Y", disabled: true)]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_redo])]
+    redo_item: nwg::MenuItem,
+
+    #[nwg_control(parent: edit_menu, text: "&History")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_history_toggle])]
+    history_item: nwg::MenuItem,
+
+    #[nwg_control(parent: edit_menu)]
+    undo_separator: nwg::MenuSeparator,
+
+    #[nwg_control(parent: edit_menu, text: "Cu&t\tCtrl+X")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_cut])]
+    cut_item: nwg::MenuItem,
+
+    #[nwg_control(parent: edit_menu, text: "&Copy\tCtrl+C")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_copy])]
+    copy_item: nwg::MenuItem,
+
+    #[nwg_control(parent: edit_menu, text: "&Paste\tCtrl+V")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_paste])]
+    paste_item: nwg::MenuItem,
+
+    #[nwg_control(parent: edit_menu, text: "Copy path &as...\tCtrl+Shift+C")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_copy_path_as])]
+    copy_path_as_item: nwg::MenuItem,
+
+    #[nwg_control(parent: edit_menu)]
+    edit_separator: nwg::MenuSeparator,
+
+    #[nwg_control(parent: edit_menu, text: "Select &matching...\tCtrl+M")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_select_matching])]
+    select_matching_item: nwg::MenuItem,
+
+    #[nwg_control(parent: edit_menu, text: "&Search subfolders...\tCtrl+F")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_select_matching])]
+    search_item: nwg::MenuItem,
+
+    #[nwg_control(parent: edit_menu, text: "&Invert selection")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_invert_selection])]
+    invert_selection_item: nwg::MenuItem,
+
+    #[nwg_control(parent: edit_menu, text: "Select all &files")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_select_files])]
+    select_files_item: nwg::MenuItem,
+
+    #[nwg_control(parent: edit_menu, text: "Select all f&olders")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_select_folders])]
+    select_folders_item: nwg::MenuItem,
+
+    #[nwg_control(parent: edit_menu)]
+    select_separator: nwg::MenuSeparator,
+
+    #[nwg_control(parent: edit_menu, text: "Paste text and images as &new files")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_paste_as_files_toggle])]
+    paste_as_files_item: nwg::MenuItem,
+
+    #[nwg_control(parent: window, popup: true)]
+    path_menu: nwg::Menu,
+
+    #[nwg_control(parent: path_menu, text: "&Windows path")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_copy_path_windows])]
+    path_windows_item: nwg::MenuItem,
+
+    #[nwg_control(parent: path_menu, text: "&Quoted")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_copy_path_quoted])]
+    path_quoted_item: nwg::MenuItem,
+
+    #[nwg_control(parent: path_menu, text: "&Forward slashes")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_copy_path_forward_slash])]
+    path_forward_slash_item: nwg::MenuItem,
+
+    #[nwg_control(parent: path_menu, text: "file:// &URI")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_copy_path_file_uri])]
+    path_file_uri_item: nwg::MenuItem,
+
+    #[nwg_control(parent: path_menu, text: "WS&L path")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_copy_path_wsl])]
+    path_wsl_item: nwg::MenuItem,
+
+    #[nwg_control(parent: path_menu, text: "&Relative to the base column")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_copy_path_relative])]
+    path_relative_item: nwg::MenuItem,
+
+    #[nwg_control(parent: path_menu)]
+    path_separator: nwg::MenuSeparator,
+
+    #[nwg_control(parent: path_menu, text: "Make this column the &base")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_set_relative_base])]
+    path_set_base_item: nwg::MenuItem,
+
+    #[nwg_control(parent: path_menu)]
+    path_join_separator: nwg::MenuSeparator,
+
+    #[nwg_control(parent: path_menu, text: "One per &line", check: true)]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_join_paths_with_newlines])]
+    path_newlines_item: nwg::MenuItem,
+
+    #[nwg_control(parent: path_menu, text: "Separated by &spaces")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_join_paths_with_spaces])]
+    path_spaces_item: nwg::MenuItem,
+
+    #[nwg_control(parent: window, text: "&View")]
+    view_menu: nwg::Menu,
+
+    #[nwg_control(parent: view_menu, text: "&Debug log")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_debug_log_toggle])]
+    debug_log_item: nwg::MenuItem,
+
+    #[nwg_control(parent: view_menu)]
+    view_separator: nwg::MenuSeparator,
+
+    #[nwg_control(parent: view_menu, text: "D&etails")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_view_details])]
+    view_details_item: nwg::MenuItem,
+
+    #[nwg_control(parent: view_menu, text: "&Thumbnails")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_view_thumbnails])]
+    view_thumbnails_item: nwg::MenuItem,
+
+    #[nwg_control(parent: view_menu, text: "Column widths per &folder", check: true)]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_widths_per_folder_toggle])]
+    view_widths_per_folder_item: nwg::MenuItem,
+
+    #[nwg_control(parent: window, text: "&Go")]
+    go_menu: nwg::Menu,
+
+    #[nwg_control(parent: go_menu, text: "&Back\tBackspace")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_back])]
+    back_item: nwg::MenuItem,
+
+    #[nwg_control(parent: go_menu, text: "&Forward\tShift+Backspace")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_forward])]
+    forward_item: nwg::MenuItem,
+
+    #[nwg_control(parent: go_menu)]
+    go_history_separator: nwg::MenuSeparator,
+
+    #[nwg_control(parent: go_menu, text: "Go to &anything...\tCtrl+P")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_goto])]
+    goto_item: nwg::MenuItem,
+
+    #[nwg_control(parent: go_menu, text: "&Jump to folder...\tCtrl+J")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_jump])]
+    jump_item: nwg::MenuItem,
+
+    #[nwg_control(parent: go_menu, text: "&Recent folders")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_recent_folders])]
+    recent_folders_item: nwg::MenuItem,
+
+    #[nwg_control(parent: go_menu)]
+    go_separator: nwg::MenuSeparator,
+
+    #[nwg_control(parent: go_menu, text: "Edit &indexed folders...")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_edit_index_roots])]
+    edit_index_roots_item: nwg::MenuItem,
+
+    #[nwg_control(parent: go_menu, text: "Re&build index")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::start_index])]
+    rebuild_index_item: nwg::MenuItem,
+
+    #[nwg_control(parent: window, text: "Se&lection")]
+    selection_menu: nwg::Menu,
+
+    #[nwg_control(parent: selection_menu, text: "&Copy to...")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_selection_copy_to])]
+    selection_copy_to_item: nwg::MenuItem,
+
+    #[nwg_control(parent: selection_menu, text: "&Move to...")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_selection_move_to])]
+    selection_move_to_item: nwg::MenuItem,
+
+    #[nwg_control(parent: selection_menu, text: "&Delete")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_selection_delete])]
+    selection_delete_item: nwg::MenuItem,
+
+    #[nwg_control(parent: selection_menu, text: "&Zip...")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_selection_zip])]
+    selection_zip_item: nwg::MenuItem,
+
+    #[nwg_control(parent: selection_menu, text: "Open &with...")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_selection_open_with])]
+    selection_open_with_item: nwg::MenuItem,
+
+    #[nwg_control(parent: selection_menu)]
+    selection_separator: nwg::MenuSeparator,
+
+    #[nwg_control(parent: selection_menu, text: "&Remove from the set\tDel")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_remove_from_set])]
+    remove_from_set_item: nwg::MenuItem,
+
+    #[nwg_control(parent: window, text: "Se&ts")]
+    sets_menu: nwg::Menu,
+
+    #[nwg_control(parent: sets_menu, text: "&Save the selection as...")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_save_set])]
+    save_set_item: nwg::MenuItem,
+
+    #[nwg_control(parent: sets_menu, text: "&Open")]
+    open_set_menu: nwg::Menu,
+
+    #[nwg_control(parent: sets_menu, text: "&Delete")]
+    delete_set_menu: nwg::Menu,
+
+    #[nwg_control(parent: sets_menu)]
+    sets_separator: nwg::MenuSeparator,
+
+    #[nwg_control(parent: sets_menu, text: "&Export the selection...")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_export_set])]
+    export_set_item: nwg::MenuItem,
+
+    #[nwg_control(parent: sets_menu, text: "&Import a set...")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_import_set])]
+    import_set_item: nwg::MenuItem,
+
+    /// The user's commands, filled in by `StaplerApp::fill_commands_menu`
+    #[nwg_control(parent: window, text: "&Commands")]
+    commands_menu: nwg::Menu,
+
+    #[nwg_control(parent: commands_menu, text: "Edit &commands...")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_edit_commands])]
+    edit_commands_item: nwg::MenuItem,
+
+    #[nwg_control(parent: commands_menu, text: "Re&load commands")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::fill_commands_menu])]
+    reload_commands_item: nwg::MenuItem,
+
+    #[nwg_control(parent: commands_menu)]
+    commands_separator: nwg::MenuSeparator,
+
+    /// The user's commands again, for right-clicking in a column
+    #[nwg_control(parent: window, popup: true)]
+    commands_popup: nwg::Menu,
+
+    #[nwg_control(parent: window, text: "&Shelf")]
+    shelf_menu: nwg::Menu,
+
+    #[nwg_control(parent: shelf_menu, text: "&Staple selection\tCtrl+S")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_staple])]
+    staple_item: nwg::MenuItem,
+
+    #[nwg_control(parent: shelf_menu, text: "Show the s&helf")]
+    #[nwg_events(OnMenuItemSelected: [StaplerApp::on_shelf_toggle])]
+    show_shelf_item: nwg::MenuItem,
+
+    #[nwg_control(parent: window, flags: "VISIBLE|HORIZONTAL")]
+    #[nwg_events(OnHorizontalScroll: [StaplerApp::on_column_scroll])]
+    column_scroll_bar: nwg::ScrollBar,
+
+    /// Says what `--pick` wants picked, under the columns and only there when picking
+    #[nwg_control(parent: window, text: "")]
+    pick_label: nwg::Label,
+
+    #[nwg_control(parent: window, text: "Pick")]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_pick])]
+    pick_button: nwg::Button,
+
+    #[nwg_control(parent: window, text: "Cancel")]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_pick_cancel])]
+    pick_cancel_button: nwg::Button,
+
+    /// What the running command is, above its output
+    #[nwg_control(parent: window, text: "")]
+    output_label: nwg::Label,
+
+    /// A command to run in the focused column's folder
+    #[nwg_control(parent: window, placeholder_text: Some("a command to run in the folder"))]
+    #[nwg_events(OnKeyPress: [StaplerApp::on_output_key(SELF, EVT_DATA)])]
+    output_input: nwg::TextInput,
+
+    #[nwg_control(parent: window, text: "Run")]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_output_run])]
+    output_run_button: nwg::Button,
+
+    #[nwg_control(parent: window, text: "Stop")]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_output_stop])]
+    output_stop_button: nwg::Button,
+
+    #[nwg_control(parent: window, text: "Close")]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_output_close])]
+    output_close_button: nwg::Button,
+
+    /// What commands run with `output = yes` print, under the columns
+    #[nwg_control(parent: window, readonly: true, flags: "VSCROLL|HSCROLL|AUTOVSCROLL")]
+    output_text: nwg::TextBox,
+
+    /// Only the strip of tab names, a bare tab control made by `StaplerApp::init_tab_strip`.
+    /// The columns are the window's, see `StaplerApp::tabs`.
+    tab_strip: Cell<nwg::ControlHandle>,
+
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_layout_notice])]
+    layout_notice: nwg::Notice,
+
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_resize_notice])]
+    resize_notice: nwg::Notice,
+
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_command_notice])]
+    command_notice: nwg::Notice,
+
+    /// Set by a list view's shortcut, just before `command_notice` fires
+    pending_command: Rc<Cell<Option<Command>>>,
+
+    /// What `path_menu` was popped up for
+    path_menu_paths: RefCell<Vec<String>>,
+
+    /// The folder `PathFormat::Relative` paths start from
+    relative_base: RefCell<Option<String>>,
+
+    join_paths_with_spaces: Cell<bool>,
+
+    #[nwg_control(size: (800, 400), position: (340, 340), title: "Stapler debug log", flags: "WINDOW|RESIZABLE")]
+    #[nwg_events(OnWindowClose: [StaplerApp::on_debug_window_close(SELF, EVT_DATA)])]
+    debug_window: nwg::Window,
+
+    #[nwg_layout(parent: debug_window, spacing: 0, margin: [0, 0, 0, 0])]
+    debug_layout: nwg::GridLayout,
+
+    #[nwg_control(parent: debug_window, readonly: true, flags: "VISIBLE|VSCROLL|HSCROLL|AUTOVSCROLL")]
+    #[nwg_layout_item(layout: debug_layout, row: 0, col: 0)]
+    debug_text: nwg::TextBox,
+
+    #[nwg_control(size: (520, 400), position: (360, 360), title: "Stapler shelf", flags: "WINDOW|RESIZABLE")]
+    #[nwg_events(OnWindowClose: [StaplerApp::on_shelf_window_close(SELF, EVT_DATA)])]
+    shelf_window: nwg::Window,
+
+    #[nwg_layout(parent: shelf_window, spacing: 3)]
+    shelf_layout: nwg::GridLayout,
+
+    #[nwg_control(parent: shelf_window, list_style: nwg::ListViewStyle::Detailed, ex_flags: nwg::ListViewExFlags::FULL_ROW_SELECT, flags: "VISIBLE|ALWAYS_SHOW_SELECTION")]
+    #[nwg_layout_item(layout: shelf_layout, row: 0, col: 0, col_span: 5, row_span: 9)]
+    #[nwg_events(OnKeyPress: [StaplerApp::on_shelf_key(SELF, EVT_DATA)])]
+    shelf_list: nwg::ListView,
+
+    #[nwg_control(parent: shelf_window, text: "Copy here")]
+    #[nwg_layout_item(layout: shelf_layout, row: 9, col: 0)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_shelf_copy_here])]
+    shelf_copy_button: nwg::Button,
+
+    #[nwg_control(parent: shelf_window, text: "Move here")]
+    #[nwg_layout_item(layout: shelf_layout, row: 9, col: 1)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_shelf_move_here])]
+    shelf_move_button: nwg::Button,
+
+    #[nwg_control(parent: shelf_window, text: "Zip...")]
+    #[nwg_layout_item(layout: shelf_layout, row: 9, col: 2)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_shelf_zip])]
+    shelf_zip_button: nwg::Button,
+
+    #[nwg_control(parent: shelf_window, text: "Remove")]
+    #[nwg_layout_item(layout: shelf_layout, row: 9, col: 3)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_shelf_remove])]
+    shelf_remove_button: nwg::Button,
+
+    #[nwg_control(parent: shelf_window, text: "Clear")]
+    #[nwg_layout_item(layout: shelf_layout, row: 9, col: 4)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_shelf_clear])]
+    shelf_clear_button: nwg::Button,
+
+    #[nwg_control(size: (520, 400), position: (400, 400), title: "Stapler history", flags: "WINDOW|RESIZABLE")]
+    #[nwg_events(OnWindowClose: [StaplerApp::on_history_window_close(SELF, EVT_DATA)])]
+    history_window: nwg::Window,
+
+    #[nwg_layout(parent: history_window, spacing: 3)]
+    history_layout: nwg::GridLayout,
+
+    #[nwg_control(parent: history_window, list_style: nwg::ListViewStyle::Detailed, ex_flags: nwg::ListViewExFlags::FULL_ROW_SELECT, flags: "VISIBLE")]
+    #[nwg_layout_item(layout: history_layout, row: 0, col: 0, col_span: 2, row_span: 9)]
+    history_list: nwg::ListView,
+
+    #[nwg_control(parent: history_window, text: "Undo")]
+    #[nwg_layout_item(layout: history_layout, row: 9, col: 0)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_undo])]
+    history_undo_button: nwg::Button,
+
+    #[nwg_control(parent: history_window, text: "Redo")]
+    #[nwg_layout_item(layout: history_layout, row: 9, col: 1)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_redo])]
+    history_redo_button: nwg::Button,
+
+    #[nwg_control(size: (380, 290), position: (380, 380), title: "Select or search", flags: "WINDOW")]
+    #[nwg_events(OnWindowClose: [StaplerApp::on_select_window_close(SELF, EVT_DATA)])]
+    select_window: nwg::Window,
+
+    #[nwg_layout(parent: select_window, spacing: 3)]
+    select_layout: nwg::GridLayout,
+
+    #[nwg_control(parent: select_window, text: "Name")]
+    #[nwg_layout_item(layout: select_layout, row: 0, col: 0)]
+    select_pattern_label: nwg::Label,
+
+    #[nwg_control(parent: select_window, placeholder_text: Some("*.jpg;*.png"))]
+    #[nwg_layout_item(layout: select_layout, row: 0, col: 1, col_span: 2)]
+    select_pattern_input: nwg::TextInput,
+
+    #[nwg_control(parent: select_window, text: "Regular expression")]
+    #[nwg_layout_item(layout: select_layout, row: 1, col: 1, col_span: 2)]
+    select_regex_check: nwg::CheckBox,
+
+    #[nwg_control(parent: select_window, text: "Type")]
+    #[nwg_layout_item(layout: select_layout, row: 2, col: 0)]
+    select_kind_label: nwg::Label,
+
+    #[nwg_control(parent: select_window, collection: vec!["Anything", "Files", "Folders"], selected_index: Some(0))]
+    #[nwg_layout_item(layout: select_layout, row: 2, col: 1, col_span: 2)]
+    select_kind_combo: nwg::ComboBox<&'static str>,
+
+    #[nwg_control(parent: select_window, text: "Size (KB)")]
+    #[nwg_layout_item(layout: select_layout, row: 3, col: 0)]
+    select_size_label: nwg::Label,
+
+    #[nwg_control(parent: select_window, placeholder_text: Some("at least"))]
+    #[nwg_layout_item(layout: select_layout, row: 3, col: 1)]
+    select_min_size_input: nwg::TextInput,
+
+    #[nwg_control(parent: select_window, placeholder_text: Some("at most"))]
+    #[nwg_layout_item(layout: select_layout, row: 3, col: 2)]
+    select_max_size_input: nwg::TextInput,
+
+    #[nwg_control(parent: select_window, text: "Modified in the last")]
+    #[nwg_layout_item(layout: select_layout, row: 4, col: 0)]
+    select_days_label: nwg::Label,
+
+    #[nwg_control(parent: select_window, placeholder_text: Some("days, for files on disk"))]
+    #[nwg_layout_item(layout: select_layout, row: 4, col: 1)]
+    select_days_input: nwg::TextInput,
+
+    #[nwg_control(parent: select_window, text: "Containing")]
+    #[nwg_layout_item(layout: select_layout, row: 5, col: 0)]
+    select_contents_label: nwg::Label,
+
+    #[nwg_control(parent: select_window, placeholder_text: Some("text, when searching; only A-Z ignore case"))]
+    #[nwg_layout_item(layout: select_layout, row: 5, col: 1, col_span: 2)]
+    select_contents_input: nwg::TextInput,
+
+    #[nwg_control(parent: select_window, text: "Add to the selection")]
+    #[nwg_layout_item(layout: select_layout, row: 6, col: 1, col_span: 2)]
+    select_add_check: nwg::CheckBox,
+
+    #[nwg_control(parent: select_window, text: "Select here")]
+    #[nwg_layout_item(layout: select_layout, row: 7, col: 0)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_select_matching_ok])]
+    select_ok_button: nwg::Button,
+
+    #[nwg_control(parent: select_window, text: "Search subfolders")]
+    #[nwg_layout_item(layout: select_layout, row: 7, col: 1)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_search_ok])]
+    search_ok_button: nwg::Button,
+
+    #[nwg_control(parent: select_window, text: "Cancel")]
+    #[nwg_layout_item(layout: select_layout, row: 7, col: 2)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_select_matching_cancel])]
+    select_cancel_button: nwg::Button,
+
+    #[nwg_control(size: (560, 360), position: (300, 200), title: "Go to anything", flags: "WINDOW")]
+    #[nwg_events(OnWindowClose: [StaplerApp::on_goto_window_close(SELF, EVT_DATA)])]
+    goto_window: nwg::Window,
+
+    #[nwg_layout(parent: goto_window, spacing: 3)]
+    goto_layout: nwg::GridLayout,
+
+    #[nwg_control(parent: goto_window, placeholder_text: Some("part of a name, or of its path"))]
+    #[nwg_layout_item(layout: goto_layout, row: 0, col: 0, col_span: 4)]
+    #[nwg_events(OnTextInput: [StaplerApp::on_goto_input], OnKeyPress: [StaplerApp::on_goto_key(SELF, EVT_DATA)])]
+    goto_input: nwg::TextInput,
+
+    #[nwg_control(parent: goto_window)]
+    #[nwg_layout_item(layout: goto_layout, row: 1, col: 0, col_span: 4, row_span: 8)]
+    #[nwg_events(OnListBoxDoubleClick: [StaplerApp::on_goto_ok], OnKeyPress: [StaplerApp::on_goto_key(SELF, EVT_DATA)])]
+    goto_list: nwg::ListBox<String>,
+
+    #[nwg_control(parent: goto_window, text: "")]
+    #[nwg_layout_item(layout: goto_layout, row: 9, col: 0, col_span: 3)]
+    goto_status: nwg::Label,
+
+    #[nwg_control(parent: goto_window, text: "Go")]
+    #[nwg_layout_item(layout: goto_layout, row: 9, col: 3)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_goto_ok])]
+    goto_ok_button: nwg::Button,
+
+    #[nwg_control(size: (480, 300), position: (320, 220), title: "Jump to folder", flags: "WINDOW")]
+    #[nwg_events(OnWindowClose: [StaplerApp::on_jump_window_close(SELF, EVT_DATA)])]
+    jump_window: nwg::Window,
+
+    #[nwg_layout(parent: jump_window, spacing: 3)]
+    jump_layout: nwg::GridLayout,
+
+    #[nwg_control(parent: jump_window, placeholder_text: Some("words from the folder's path, the last from its name"))]
+    #[nwg_layout_item(layout: jump_layout, row: 0, col: 0, col_span: 4)]
+    #[nwg_events(OnTextInput: [StaplerApp::on_jump_input], OnKeyPress: [StaplerApp::on_jump_key(SELF, EVT_DATA)])]
+    jump_input: nwg::TextInput,
+
+    #[nwg_control(parent: jump_window)]
+    #[nwg_layout_item(layout: jump_layout, row: 1, col: 0, col_span: 4, row_span: 6)]
+    #[nwg_events(OnListBoxDoubleClick: [StaplerApp::on_jump_ok], OnKeyPress: [StaplerApp::on_jump_key(SELF, EVT_DATA)])]
+    jump_list: nwg::ListBox<String>,
+
+    #[nwg_control(parent: jump_window, text: "Go")]
+    #[nwg_layout_item(layout: jump_layout, row: 7, col: 3)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_jump_ok])]
+    jump_ok_button: nwg::Button,
+
+    #[nwg_control(size: (360, 110), position: (340, 240), title: "Save the selection as", flags: "WINDOW")]
+    #[nwg_events(OnWindowClose: [StaplerApp::on_set_name_window_close(SELF, EVT_DATA)])]
+    set_name_window: nwg::Window,
+
+    #[nwg_layout(parent: set_name_window, spacing: 3)]
+    set_name_layout: nwg::GridLayout,
+
+    #[nwg_control(parent: set_name_window, placeholder_text: Some("the set's name"))]
+    #[nwg_layout_item(layout: set_name_layout, row: 0, col: 0, col_span: 4)]
+    #[nwg_events(OnKeyPress: [StaplerApp::on_set_name_key(SELF, EVT_DATA)])]
+    set_name_input: nwg::TextInput,
+
+    #[nwg_control(parent: set_name_window, text: "Save")]
+    #[nwg_layout_item(layout: set_name_layout, row: 1, col: 3)]
+    #[nwg_events(OnButtonClick: [StaplerApp::on_set_name_ok])]
+    set_name_ok_button: nwg::Button,
+
+    #[nwg_resource(title: "Zip to", action: nwg::FileDialogAction::Save, filters: "Zip(*.zip)")]
+    zip_dialog: nwg::FileDialog,
+
+    #[nwg_resource(title: "Choose a folder", action: nwg::FileDialogAction::OpenDirectory)]
+    folder_dialog: nwg::FileDialog,
+
+    #[nwg_resource(title: "Open with", action: nwg::FileDialogAction::Open, filters: "Programs(*.exe)")]
+    program_dialog: nwg::FileDialog,
+
+    #[nwg_resource(title: "Export the selection", action: nwg::FileDialogAction::Save, filters: "Selection sets(*.txt)")]
+    export_set_dialog: nwg::FileDialog,
+
+    #[nwg_resource(title: "Import a set", action: nwg::FileDialogAction::Open, filters: "Selection sets(*.txt)")]
+    import_set_dialog: nwg::FileDialog,
+
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_log_notice])]
+    log_notice: nwg::Notice,
+
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_icon_notice])]
+    icon_notice: nwg::Notice,
+
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_thumbnail_notice])]
+    thumbnail_notice: nwg::Notice,
+
+    /// Fired by the search thread when it has hits, or is done
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_search_notice])]
+    search_notice: nwg::Notice,
+
+    /// Fired by `instance::Listener` when a later launch has passed something on
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_instance_notice])]
+    instance_notice: nwg::Notice,
+
+    /// Fired by `output::Run` when a command has printed more, or finished
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_output_notice])]
+    output_notice: nwg::Notice,
+
+    /// Fired by `control::Server` when a client has made calls
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_control_notice])]
+    control_notice: nwg::Notice,
+
+    /// Fired by a zip's thread when it failed, see `StaplerApp::zip_failure`
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_zip_notice])]
+    zip_notice: nwg::Notice,
+
+    /// Fired by `templates::ShellTemplates` when it's read the shell's templates
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_templates_notice])]
+    templates_notice: nwg::Notice,
+
+    /// Fired by `index::Index` when it's found what was typed into Go to anything
+    #[nwg_control(parent: window)]
+    #[nwg_events(OnNotice: [StaplerApp::on_index_notice])]
+    index_notice: nwg::Notice,
+
+    image_list_small: RefCell<nwg::ImageList>,
+
+    /// Created on first use, see `StaplerApp::icons`
+    icons: RefCell<Option<Rc<icons::IconCache>>>,
+
+    /// Created on first use, see `StaplerApp::thumbnails`
+    thumbnails: RefCell<Option<Rc<thumbnails::ThumbnailCache>>>,
+
+    columns: Rc<RefCell<VecDeque<Column>>>,
+
+    /// The `HWND` of the list view that last had focus, which menu commands act on
+    focused_list_view: Rc<Cell<usize>>,
+
+    /// How far the chain is scrolled, counted in columns
+    first_visible_column: Cell<usize>,
+
+    widths: Rc<RefCell<widths::ColumnWidths>>,
+
+    /// Shared with every column, which counts the folders it shows
+    frecency: Rc<RefCell<frecency::Frecency>>,
+
+    /// From `--jump`, to go to once the window is up
+    startup_jump: RefCell<Option<String>>,
+    /// From the command line, to reveal once the window is up
+    startup_path: RefCell<Option<String>>,
+
+    /// The pipe claimed in `main`, until the window is up to listen on it
+    instance_claim: RefCell<Option<instance::Claim>>,
+    instance_listener: RefCell<Option<instance::Listener>>,
+
+    /// From `--pick`, which turns stapler into a file chooser until something's picked
+    pick: Cell<Option<Pick>>,
+    /// Whether `pick` is set, for the list views' double clicks
+    picking: Rc<Cell<bool>>,
+    /// What was picked, for `main` to print once the window has closed
+    picked: RefCell<Option<Vec<String>>>,
+
+    /// From `--control`, to serve the control API once the window is up
+    serve_control: Cell<bool>,
+    control: RefCell<Option<control::Server>>,
+    /// The active tab and what it showed when clients were last told, so they're only told of changes
+    control_shown: RefCell<Option<(usize, Option<String>)>>,
+
+    /// Every tab, in strip order. The active one's chain is in `columns`, not here.
+    tabs: RefCell<Vec<Tab>>,
+    active_tab: Cell<usize>,
+    /// Whether the last session has been reopened, so it isn't saved over with just the Desktop first
+    session_restored: Cell<bool>,
+    /// Switches and reorders tabs, see `StaplerApp::bind_tabs`
+    tab_handlers: RefCell<Vec<nwg::RawEventHandler>>,
+
+    /// Drags the gaps between columns, see `StaplerApp::bind_splitters`
+    splitter_handler: RefCell<Option<nwg::RawEventHandler>>,
+
+    /// Shared with `shelf_drag_handler`, which drags staples out as soon as it's asked to
+    shelf: Rc<RefCell<shelf::Shelf>>,
+    /// What `fill_shelf` queued the staples' icons under, see `icons::IconCache::begin`
+    shelf_icon_generation: Cell<u64>,
+
+    /// Starts drags out of `shelf_list`
+    shelf_drag_handler: RefCell<Option<nwg::RawEventHandler>>,
+    /// What went wrong zipping in the background, for `StaplerApp::on_zip_notice` to show
+    zip_failure: Arc<Mutex<Option<String>>>,
+
+    /// Names under the folders in `index::roots_file`, for Go to anything
+    index: RefCell<Option<index::Index>>,
+
+    /// What's in the Sets menu, in the order `selection_sets::names` gave
+    set_names: RefCell<Vec<String>>,
+    /// What `set_name_window` is naming, taken when it was opened
+    set_paths: RefCell<Vec<String>>,
+    /// What can be undone and redone, see `StaplerApp::record`
+    journal: RefCell<journal::Journal>,
+
+    /// What File > New offers, in menu order
+    templates: RefCell<Vec<templates::Template>>,
+    /// The shell's own, once `shell_templates_reader` has them
+    shell_templates: RefCell<Vec<templates::Template>>,
+    shell_templates_reader: RefCell<Option<templates::ShellTemplates>>,
+    /// Shared with the list views, for their shortcuts and double clicks
+    user_commands: Rc<RefCell<Vec<user_commands::UserCommand>>>,
+    terminal: Cell<terminal::Terminal>,
+    /// Whether the output panel is showing
+    output_shown: Cell<bool>,
+    /// What's running in the output panel, and what it's called
+    output_run: RefCell<Option<output::Run>>,
+    output_title: RefCell<String>,
+    /// Menu items built at run time, for saved sets and templates, and what each does
+    dynamic_menu_items: Rc<RefCell<Vec<(nwg::MenuItem, Command)>>>,
+    dynamic_menu_handler: RefCell<Option<nwg::EventHandler>>,
+
+    /// A label edited in a list view, as its `HWND`, row and new text, for `Command::Rename`
+    pending_rename: Rc<RefCell<Option<(usize, usize, String)>>>,
+    /// Catches label edits ending, see `StaplerApp::bind_renames`
+    rename_handler: RefCell<Option<nwg::RawEventHandler>>,
+}
+
+impl StaplerApp {
+    /// Makes the chain long enough to fill the window, plus one empty column
+    /// after the last one showing anything, then lays out the visible part
+    fn reconcile_columns(&self) {
+        let window_width = TryInto::<i32>::try_into(self.window.size().0).unwrap();
+        let new_width = self.widths.borrow().width_for(None);
+        let mut columns = self.columns.borrow_mut();
+        let mut needed = columns.iter().rposition(|column| column.folder.is_some()).map_or(1, |last| last + 2);
+        // Then enough to fill the window, with the empty ones
+        let mut filled: i32 = columns.iter().take(needed).map(|column| column.width + SPLITTER_WIDTH).sum();
+        filled += TryInto::<i32>::try_into(needed.saturating_sub(columns.len())).unwrap() * (new_width + SPLITTER_WIDTH);
+        while filled < window_width {
+            filled += columns.get(needed).map_or(new_width, |column| column.width) + SPLITTER_WIDTH;
+            needed += 1;
+        }
+        while columns.len() > needed && columns.back().unwrap().folder.is_none() {
+            let destroyed = columns.pop_back().unwrap();
+            self.destroy_column(destroyed);
+        }
+        let column_count = columns.len();
+        std::mem::drop(columns);
+        let icon = unsafe {
+            let mut big = win32controls::HIMAGELIST::default();
+            win32shell::Shell_GetImageLists(Some(&mut big), None);
+            let image_list_big = win32controls::IImageList::from_raw(big.0 as *mut _);
+            let result = if let Ok(hicon) = image_list_big.GetIcon(0, 0) {
+                Some(nwg::Icon {
+                    handle: hicon.0 as *mut _,
+                    owned: false,
+                })
+            } else {
+                None
+            };
+            std::mem::forget(image_list_big);
+            result
+        };
+        for _ in column_count .. needed {
+            let mut proxy_icon = nwg::ImageFrame::default();
+            nwg::ImageFrame::builder()
+                .parent(&self.window)
+                .size((64, 64))
+                .icon(icon.as_ref())
+                .build(&mut proxy_icon)
+                .expect("failed");
+            proxy_icon.set_visible(false);
             let mut list_view = nwg::ListView::default();
             nwg::ListView::builder()
                 .double_buffer(true)
@@ -316,19 +2030,26 @@ impl StaplerApp {
                 .build(&mut list_view)
                 .expect("failed to build list view");
             list_view.set_image_list(Some(&self.image_list_small.borrow()), nwg::ListViewImageListType::Small);
+            // nwg has no flag for this; the edits are picked up by `StaplerApp::bind_renames`
+            unsafe {
+                let hwnd = HWND(list_view.handle.hwnd().unwrap() as *mut _);
+                let style = win32wam::GetWindowLongPtrW(hwnd, win32wam::GWL_STYLE);
+                win32wam::SetWindowLongPtrW(hwnd, win32wam::GWL_STYLE, style | win32controls::LVS_EDITLABELS as isize);
+            }
+            let thumbnail_list = thumbnails::new_image_list();
+            list_view.set_image_list(Some(&thumbnail_list), nwg::ListViewImageListType::Normal);
             list_view.insert_column(nwg::InsertListViewColumn {
                 index: None,
                 fmt: None,
                 width: Some(250),
                 text: Some("Name".into()),
             });
-            self.proxy_icon_grid_layout.add_child(TryInto::<u32>::try_into(i).unwrap(), 0, &proxy_icon);
-            self.column_grid_layout.add_child(TryInto::<u32>::try_into(i).unwrap(), 0, &list_view);
             let list_view_handle = list_view.handle;
             let proxy_icon_handle = proxy_icon.handle;
             let columns_ = Rc::downgrade(&self.columns);
-            let proxy_icon_grid_layout = self.proxy_icon_grid_layout.clone();
-            let column_grid_layout = self.column_grid_layout.clone();
+            let focused_list_view = self.focused_list_view.clone();
+            let pending_command = self.pending_command.clone();
+            let command_notice = self.command_notice.sender();
             let proxy_icon_handler = nwg::bind_event_handler(&proxy_icon.handle, &self.window.handle, move |evt, _evt_data, handle| {
                 let columns = if let Some(columns) = columns_.upgrade() {
                     columns
@@ -343,45 +2064,42 @@ impl StaplerApp {
                             while let Some(column) = column_iterator.next() {
                                 if column.list_view.handle == list_view_handle {
                                     match &column.folder {
-                                        Some(Folder::Shell { for_parsing, .. }) => unsafe {
-                                            println!("{}", OsString::from_wide(&for_parsing.clone()).display());
-                                            win32shell::ShellExecuteW(
-                                                HWND(handle.hwnd().unwrap() as *mut _),
-                                                w!("open"),
-                                                PCWSTR::from_raw(for_parsing.as_ptr()),
-                                                w!(""),
-                                                w!(""),
-                                                windows::Win32::UI::WindowsAndMessaging::SHOW_WINDOW_CMD(0),
-                                            );
+                                        Some(Folder::Shell { itemid, for_parsing, .. }) | Some(Folder::Search { itemid, for_parsing, .. }) => {
+                                            shell_execute(HWND(handle.hwnd().unwrap() as *mut _), w!("open"), itemid, for_parsing);
                                         }
-                                        Some(Folder::Selection { selection }) => unsafe {
+                                        Some(Folder::Selection { selection }) => {
                                             for sel in selection {
                                                 match sel {
-                                                    File::Shell { for_parsing, itemid: _, display: _, icon: _ } => unsafe {
-                                                        println!("{}", OsString::from_wide(&for_parsing.clone()).display());
-                                                        win32shell::ShellExecuteW(
-                                                            HWND(handle.hwnd().unwrap() as *mut _),
-                                                            w!("open"),
-                                                            PCWSTR::from_raw(for_parsing.as_ptr()),
-                                                            w!(""),
-                                                            w!(""),
-                                                            windows::Win32::UI::WindowsAndMessaging::SHOW_WINDOW_CMD(0),
-                                                        );
+                                                    File::Shell { for_parsing, itemid, .. } => {
+                                                        shell_execute(HWND(handle.hwnd().unwrap() as *mut _), w!("open"), itemid, for_parsing);
                                                     }
                                                     File::Error(..) => {},
                                                 }
                                             }
                                         }
-                                        Some(Folder::Error { .. }) | None => {},
+                                        Some(Folder::Recent) | Some(Folder::Error { .. }) | None => {},
                                     }
                                 }
                             }
                         }
                     }
+                    nwg::Event::OnMousePress(nwg::MousePressEvent::MousePressRightUp) => {
+                        if handle == proxy_icon_handle {
+                            focused_list_view.set(list_view_handle.hwnd().unwrap() as usize);
+                            pending_command.set(Some(Command::CopyPathAs { of_folder: true }));
+                            command_notice.notice();
+                        }
+                    }
                     _ => {}
                 }
             });
             let columns_ = Rc::downgrade(&self.columns);
+            let focused_list_view = self.focused_list_view.clone();
+            let layout_notice = self.layout_notice.sender();
+            let pending_command = self.pending_command.clone();
+            let command_notice = self.command_notice.sender();
+            let picking = self.picking.clone();
+            let user_commands = self.user_commands.clone();
             let list_handler = nwg::bind_event_handler(&list_view.handle, &self.window.handle, move |evt, evt_data, handle| {
                 let columns = if let Some(columns) = columns_.upgrade() {
                     columns
@@ -389,24 +2107,76 @@ impl StaplerApp {
                     return;
                 };
                 match evt_data {
-                    _ if evt == nwg::Event::OnListViewDoubleClick => {
+                    _ if evt == nwg::Event::OnListViewFocus => {
+                        if handle == list_view_handle {
+                            focused_list_view.set(list_view_handle.hwnd().unwrap() as usize);
+                        }
+                    }
+                    nwg::EventData::OnKey(key) if evt == nwg::Event::OnKeyPress => {
                         if handle == list_view_handle {
+                            let control = unsafe { win32input::GetKeyState(win32input::VK_CONTROL.0 as i32) } < 0;
+                            let shift = unsafe { win32input::GetKeyState(win32input::VK_SHIFT.0 as i32) } < 0;
+                            let command = match key {
+                                0x43 if control && shift => Some(Command::CopyPathAs { of_folder: false }),
+                                0x58 if control => Some(Command::Cut),
+                                0x43 if control => Some(Command::Copy),
+                                0x56 if control => Some(Command::Paste),
+                                0x53 if control => Some(Command::Staple),
+                                0x4D if control => Some(Command::SelectMatching),
+                                0x46 if control => Some(Command::SelectMatching),
+                                0x5A if control => Some(Command::Undo),
+                                0x59 if control => Some(Command::Redo),
+                                0x4E if control && shift => Some(Command::NewFolder),
+                                0x50 if control => Some(Command::GoTo),
+                                0x4A if control => Some(Command::Jump),
+                                0x54 if control && shift => Some(Command::OpenTerminal),
+                                0x54 if control => Some(Command::NewTab),
+                                0x52 if control => Some(Command::RunHere),
+                                0x57 if control => Some(Command::CloseTab(None)),
+                                0x08 if shift => Some(Command::Forward),
+                                0x08 => Some(Command::Back),
+                                0x71 => Some(Command::EditName),
+                                0x2E if !control => Some(Command::RemoveFromSet),
+                                key => {
+                                    let pressed = user_commands::Shortcut { control, shift, key: *key };
+                                    let commands = user_commands.try_borrow();
+                                    commands.ok().and_then(|commands| commands.iter().position(|command| command.key == Some(pressed))).map(Command::RunUserCommand)
+                                }
+                            };
+                            if let Some(command) = command {
+                                focused_list_view.set(list_view_handle.hwnd().unwrap() as usize);
+                                pending_command.set(Some(command));
+                                command_notice.notice();
+                            }
+                        }
+                    }
+                    _ if evt == nwg::Event::OnListViewDoubleClick => {
+                        if handle == list_view_handle && picking.get() {
+                            focused_list_view.set(list_view_handle.hwnd().unwrap() as usize);
+                            pending_command.set(Some(Command::Pick));
+                            command_notice.notice();
+                        } else if handle == list_view_handle {
                             let mut columns = columns.borrow_mut();
                             let mut column_iterator = columns.iter_mut();
                             while let Some(column) = column_iterator.next() {
                                 if column.list_view.handle == list_view_handle {
+                                    // One of the user's commands may be set to open these instead
+                                    let opener = user_commands.try_borrow().ok().and_then(|commands| {
+                                        commands.iter().position(|command| {
+                                            !column.selection.is_empty()
+                                                && column.selection.iter().all(|file| matches!(file, File::Shell { for_parsing, .. } if command.opens(&for_parsing.to_string_lossy())))
+                                        })
+                                    });
+                                    if let Some(index) = opener {
+                                        focused_list_view.set(list_view_handle.hwnd().unwrap() as usize);
+                                        pending_command.set(Some(Command::RunUserCommand(index)));
+                                        command_notice.notice();
+                                        continue;
+                                    }
                                     for sel in &column.selection {
                                         match sel {
-                                            File::Shell { for_parsing, itemid: _, display: _, icon: _ } => unsafe {
-                                                println!("{}", OsString::from_wide(&for_parsing.clone()).display());
-                                                win32shell::ShellExecuteW(
-                                                    HWND(handle.hwnd().unwrap() as *mut _),
-                                                    w!("open"),
-                                                    PCWSTR::from_raw(for_parsing.as_ptr()),
-                                                    w!(""),
-                                                    w!(""),
-                                                    windows::Win32::UI::WindowsAndMessaging::SHOW_WINDOW_CMD(0),
-                                                );
+                                            File::Shell { for_parsing, itemid, .. } => {
+                                                shell_execute(HWND(handle.hwnd().unwrap() as *mut _), w!("open"), itemid, for_parsing);
                                             }
                                             File::Error(..) => {},
                                         }
@@ -415,109 +2185,2686 @@ impl StaplerApp {
                             }
                         }
                     }
-                    nwg::EventData::OnListViewItemChanged { row_index, column_index: _, selected } => {
+                    _ if evt == nwg::Event::OnListViewRightClick => {
                         if handle == list_view_handle {
-                            let mut columns = columns.borrow_mut();
-                            let mut column_iterator = columns.iter_mut();
-                            let mut parent_folder = None;
-                            let mut selection = None;
-                            while let Some(column) = column_iterator.next() {
-                                if column.list_view.handle == list_view_handle {
-                                    let selected_path = column.children.get(row_index).map(|x| x.to_owned());
-                                    parent_folder = column.folder.clone();
-                                    if let Some(path) = &selected_path {
-                                        if selected {
-                                            column.selection.insert(path.clone());
-                                        } else {
-                                            column.selection.remove(path);
-                                        }
-                                    }
-                                    selection = Some(column.selection.clone()).filter(|sel| sel.len() > 0);
-                                    break;
-                                }
+                            focused_list_view.set(list_view_handle.hwnd().unwrap() as usize);
+                            pending_command.set(Some(Command::CommandsPopup));
+                            command_notice.notice();
+                        }
+                    }
+                    nwg::EventData::OnListViewItemIndex { row_index: _, column_index } if evt == nwg::Event::OnListViewColumnClick => {
+                        if handle == list_view_handle {
+                            let Ok(mut columns) = columns.try_borrow_mut() else {
+                                return;
+                            };
+                            if let Some(column) = columns.iter_mut().find(|column| column.list_view.handle == list_view_handle) {
+                                column.sort_by(match column_index {
+                                    1 => SortKey::Folder,
+                                    2 => SortKey::Size,
+                                    _ => SortKey::Name,
+                                });
                             }
-                            while let Some(column) = column_iterator.next() {
-                                if let Some(selection) = selection.take() {
-                                    if selection.len() == 1 {
-                                        column.switch_into(selection.iter().next().map(|x| x.to_owned()), parent_folder.take());
-                                    } else {
-                                        column.switch(Some(Folder::Selection { selection }));
-                                    }
+                        }
+                    }
+                    nwg::EventData::OnListViewItemChanged { row_index, column_index: _, selected } => {
+                        if handle == list_view_handle {
+                            let mut columns = match columns.try_borrow_mut() {
+                                Ok(columns) => columns,
+                                // We're in the middle of updating a column, and this is just its echo
+                                Err(_) => return,
+                            };
+                            let Some(index) = columns.iter().position(|column| column.list_view.handle == list_view_handle) else {
+                                return;
+                            };
+                            let column = &mut columns[index];
+                            let selected_path = column.children.get(row_index).map(|x| x.to_owned());
+                            if let Some(path) = &selected_path {
+                                if selected {
+                                    column.selection.insert(path.clone());
                                 } else {
-                                    column.switch(None);
+                                    column.selection.remove(path);
                                 }
                             }
-                            // If the selected path hasn't been taken, it means we're at
-                            // the right-most column.
-                            if let Some(selection) = selection.take() {
-                                let mut surrogate = columns.pop_front().unwrap();
-                                if selection.len() == 1 {
-                                    surrogate.switch_into(selection.iter().next().map(|x| x.to_owned()), parent_folder.take());
-                                } else {
-                                    surrogate.switch(Some(Folder::Selection { selection }));
-                                }
-                                columns.push_back(surrogate);
-                                let mut i = 0;
-                                for column in columns.iter() {
-                                    proxy_icon_grid_layout.remove_child(&column.proxy_icon);
-                                    proxy_icon_grid_layout.add_child(i, 0, &column.proxy_icon);
-                                    column_grid_layout.remove_child(&column.list_view);
-                                    column_grid_layout.add_child(i, 0, &column.list_view);
-                                    i += 1;
-                                }
+                            // `reconcile_columns` keeps an empty column on the end of the chain to take this
+                            if !cascade_selection(&mut columns, index) {
+                                log::warn!("selection in the last column had nowhere to go");
                             }
+                            // Grow or shrink the chain to fit, once we've let go of it
+                            layout_notice.notice();
                         }
                     },
                     _ => {}
                 }
             });
-            self.columns.borrow_mut().push_back(Column {
-                proxy_icon,
-                list_view,
-                folder: None,
-                children: Vec::new(),
-                loader: None,
-                list_handler,
+            let mut error_label = nwg::Label::default();
+            nwg::Label::builder()
+                .parent(&self.window)
+                .text("")
+                .build(&mut error_label)
+                .expect("failed to build error label");
+            error_label.set_visible(false);
+            let mut retry_button = nwg::Button::default();
+            nwg::Button::builder()
+                .parent(&self.window)
+                .text("Retry")
+                .build(&mut retry_button)
+                .expect("failed to build retry button");
+            retry_button.set_visible(false);
+            let mut admin_button = nwg::Button::default();
+            nwg::Button::builder()
+                .parent(&self.window)
+                .text("Get access in Explorer")
+                .build(&mut admin_button)
+                .expect("failed to build admin button");
+            admin_button.set_visible(false);
+            let retry_button_handle = retry_button.handle;
+            let columns_ = Rc::downgrade(&self.columns);
+            let retry_handler = nwg::bind_event_handler(&retry_button.handle, &self.window.handle, move |evt, _evt_data, handle| {
+                let columns = if let Some(columns) = columns_.upgrade() {
+                    columns
+                } else {
+                    return;
+                };
+                if evt == nwg::Event::OnButtonClick && handle == retry_button_handle {
+                    let mut columns = columns.borrow_mut();
+                    let mut column_iterator = columns.iter_mut();
+                    while let Some(column) = column_iterator.next() {
+                        if column.retry_button.handle == retry_button_handle {
+                            column.retry();
+                        }
+                    }
+                }
+            });
+            let admin_button_handle = admin_button.handle;
+            let columns_ = Rc::downgrade(&self.columns);
+            let admin_handler = nwg::bind_event_handler(&admin_button.handle, &self.window.handle, move |evt, _evt_data, handle| {
+                let columns = if let Some(columns) = columns_.upgrade() {
+                    columns
+                } else {
+                    return;
+                };
+                if evt == nwg::Event::OnButtonClick && handle == admin_button_handle {
+                    let columns = columns.borrow();
+                    let mut column_iterator = columns.iter();
+                    while let Some(column) = column_iterator.next() {
+                        if column.admin_button.handle == admin_button_handle {
+                            if let Some(Folder::Error { retry: Some(retry), .. }) = &column.folder {
+                                if let Some((itemid, for_parsing)) = retry.target() {
+                                    shell_execute(HWND(handle.hwnd().unwrap() as *mut _), w!("open"), itemid, for_parsing);
+                                }
+                            }
+                        }
+                    }
+                }
+            });
+            let focused_list_view = self.focused_list_view.clone();
+            let pending_command = self.pending_command.clone();
+            let command_notice = self.command_notice.sender();
+            let middle_click_handler = nwg::bind_raw_event_handler(&list_view.handle, MIDDLE_CLICK_HANDLER_ID, move |hwnd, msg, _wparam, lparam| {
+                if msg != win32wam::WM_MBUTTONUP {
+                    return None;
+                }
+                let mut hit = win32controls::LVHITTESTINFO {
+                    pt: POINT { x: (lparam & 0xFFFF) as i16 as i32, y: ((lparam >> 16) & 0xFFFF) as i16 as i32 },
+                    ..Default::default()
+                };
+                let row = unsafe { win32wam::SendMessageW(HWND(hwnd as *mut _), win32controls::LVM_HITTEST, WPARAM(0), LPARAM(&mut hit as *mut _ as isize)) };
+                if row.0 < 0 {
+                    return None;
+                }
+                focused_list_view.set(hwnd as usize);
+                pending_command.set(Some(Command::OpenInNewTab(TryInto::<usize>::try_into(row.0).unwrap())));
+                command_notice.notice();
+                Some(0)
+            });
+            let middle_click_handler = match middle_click_handler {
+                Ok(handler) => Some(handler),
+                Err(e) => {
+                    log::error!("could not bind the middle click handler: {e}");
+                    None
+                }
+            };
+            self.columns.borrow_mut().push_back(Column {
+                proxy_icon,
+                list_view,
+                folder: None,
+                children: Vec::new(),
+                loader: None,
+                list_handler,
                 proxy_icon_handler,
                 selection: HashSet::new(),
+                error_label,
+                retry_button,
+                admin_button,
+                retry_handler,
+                admin_handler,
+                middle_click_handler,
+                icons: self.icons(),
+                icon_generation: 0,
+                view_mode: ViewMode::Details,
+                detail_columns: false,
+                hits_seen: 0,
+                sort_key: SortKey::Name,
+                sort_descending: false,
+                thumbnails: self.thumbnails(),
+                thumbnail_generation: 0,
+                thumbnail_list,
+                in_view: false,
+                has_proxy_icon: false,
+                width: new_width,
+                left: 0,
+                widths: self.widths.clone(),
+                frecency: self.frecency.clone(),
+            });
+        }
+        self.layout_columns();
+    }
+    fn destroy_column(&self, destroyed: Column) {
+        nwg::unbind_event_handler(&destroyed.list_handler);
+        nwg::unbind_event_handler(&destroyed.proxy_icon_handler);
+        nwg::unbind_event_handler(&destroyed.retry_handler);
+        nwg::unbind_event_handler(&destroyed.admin_handler);
+        if let Some(handler) = &destroyed.middle_click_handler {
+            let _ = nwg::unbind_raw_event_handler(handler);
+        }
+    }
+    /// Places the columns from `first_visible_column` on, as many as fit, and hides the rest
+    fn layout_columns(&self) {
+        let (width, height) = self.window.size();
+        let (width, height) = (TryInto::<i32>::try_into(width).unwrap(), TryInto::<i32>::try_into(height).unwrap());
+        let pick_bar = if self.pick.get().is_some() { PICK_BAR_HEIGHT } else { 0 };
+        let output_panel = if self.output_shown.get() { OUTPUT_PANEL_HEIGHT } else { 0 };
+        // Under the columns from the top: their scroll bar, the output panel and the pick bar
+        let bottom = height - pick_bar - output_panel;
+        let mut columns = self.columns.borrow_mut();
+        let column_count = columns.len();
+        // The furthest the chain can scroll, which is when its end is against the right of the window
+        let mut last_first = column_count;
+        let mut needed = -SPLITTER_WIDTH;
+        for column in columns.iter().rev() {
+            needed += column.width + SPLITTER_WIDTH;
+            if needed > width && last_first < column_count {
+                break;
+            }
+            last_first -= 1;
+        }
+        let first = self.first_visible_column.get().min(last_first);
+        self.first_visible_column.set(first);
+        let mut left = 0;
+        for (i, column) in columns.iter_mut().enumerate() {
+            column.in_view = i >= first && left < width;
+            if column.in_view {
+                column.place(left, TAB_STRIP_HEIGHT, bottom - SCROLL_BAR_HEIGHT);
+                left += column.width + SPLITTER_WIDTH;
+            }
+            column.update_visibility();
+        }
+        self.column_scroll_bar.set_range(0..last_first);
+        self.column_scroll_bar.set_pos(first);
+        self.column_scroll_bar.set_position(0, bottom - SCROLL_BAR_HEIGHT);
+        self.column_scroll_bar.set_size(TryInto::<u32>::try_into(width).unwrap(), TryInto::<u32>::try_into(SCROLL_BAR_HEIGHT).unwrap());
+        self.column_scroll_bar.set_enabled(last_first > 0);
+        let _ = unsafe { win32wam::SetWindowPos(self.tab_strip_hwnd(), HWND::default(), 0, 0, width, TAB_STRIP_HEIGHT, win32wam::SWP_NOZORDER | win32wam::SWP_NOACTIVATE) };
+        if output_panel > 0 {
+            self.output_label.set_position(8, bottom + 8);
+            self.output_label.set_size(220, 20);
+            self.output_input.set_position(236, bottom + 4);
+            self.output_input.set_size(TryInto::<u32>::try_into((width - 236 - 272).max(0)).unwrap(), 22);
+            self.output_run_button.set_position(width - 264, bottom + 3);
+            self.output_run_button.set_size(80, 24);
+            self.output_stop_button.set_position(width - 176, bottom + 3);
+            self.output_stop_button.set_size(80, 24);
+            self.output_close_button.set_position(width - 88, bottom + 3);
+            self.output_close_button.set_size(80, 24);
+            self.output_text.set_position(0, bottom + OUTPUT_HEADER_HEIGHT);
+            self.output_text
+                .set_size(TryInto::<u32>::try_into(width).unwrap(), TryInto::<u32>::try_into(output_panel - OUTPUT_HEADER_HEIGHT).unwrap());
+        }
+        if pick_bar > 0 {
+            let top = height - pick_bar;
+            self.pick_label.set_position(8, top + 8);
+            self.pick_label.set_size(TryInto::<u32>::try_into((width - 200).max(0)).unwrap(), 20);
+            self.pick_button.set_position(width - 176, top + 4);
+            self.pick_button.set_size(80, 24);
+            self.pick_cancel_button.set_position(width - 88, top + 4);
+            self.pick_cancel_button.set_size(80, 24);
+        }
+    }
+    /// Lets the gaps between columns be dragged to resize the column on their left,
+    /// or double-clicked to fit it to its longest name
+    fn bind_splitters(&self) {
+        let columns_ = Rc::downgrade(&self.columns);
+        let widths = self.widths.clone();
+        let resize_notice = self.resize_notice.sender();
+        // The column being dragged, and the mouse position its width is measured from
+        let dragging: Cell<Option<(usize, i32)>> = Cell::new(None);
+        // The splitter last clicked and when, to spot double clicks
+        let last_click: Cell<Option<(usize, i32)>> = Cell::new(None);
+        let handler = nwg::bind_raw_event_handler(&self.window.handle, SPLITTER_HANDLER_ID, move |hwnd, msg, _wparam, lparam| {
+            let columns = columns_.upgrade()?;
+            let hwnd = HWND(hwnd as *mut _);
+            let x = (lparam & 0xFFFF) as i16 as i32;
+            match msg {
+                win32wam::WM_SETCURSOR => {
+                    if (lparam & 0xFFFF) as u32 != win32wam::HTCLIENT {
+                        return None;
+                    }
+                    let mut point = POINT::default();
+                    unsafe {
+                        let _ = win32wam::GetCursorPos(&mut point);
+                        let _ = win32gdi::ScreenToClient(hwnd, &mut point);
+                    }
+                    let over_splitter = match columns.try_borrow() {
+                        Ok(columns) => splitter_at(&columns, point.x).is_some(),
+                        Err(_) => false,
+                    };
+                    if dragging.get().is_none() && !over_splitter {
+                        return None;
+                    }
+                    unsafe {
+                        win32wam::SetCursor(win32wam::LoadCursorW(HINSTANCE::default(), win32wam::IDC_SIZEWE).unwrap_or_default());
+                    }
+                    Some(1)
+                }
+                win32wam::WM_LBUTTONDOWN => {
+                    let mut columns = columns.try_borrow_mut().ok()?;
+                    let index = splitter_at(&columns, x)?;
+                    let now = unsafe { win32wam::GetMessageTime() };
+                    let double_click_time = unsafe { win32input::GetDoubleClickTime() } as i32;
+                    if let Some((clicked, time)) = last_click.take() {
+                        if clicked == index && now.wrapping_sub(time) <= double_click_time {
+                            let column = &mut columns[index];
+                            column.width = column.fitted_width();
+                            widths.borrow_mut().remember(column.folder_key().as_deref(), column.width);
+                            resize_notice.notice();
+                            return Some(0);
+                        }
+                    }
+                    last_click.set(Some((index, now)));
+                    dragging.set(Some((index, x - columns[index].width)));
+                    unsafe {
+                        win32input::SetCapture(hwnd);
+                    }
+                    Some(0)
+                }
+                win32wam::WM_MOUSEMOVE => {
+                    let (index, origin) = dragging.get()?;
+                    let mut columns = columns.try_borrow_mut().ok()?;
+                    let column = columns.get_mut(index)?;
+                    column.width = (x - origin).max(widths::MIN_COLUMN_WIDTH);
+                    resize_notice.notice();
+                    Some(0)
+                }
+                win32wam::WM_LBUTTONUP | win32wam::WM_CAPTURECHANGED => {
+                    let (index, _) = dragging.take()?;
+                    if msg == win32wam::WM_LBUTTONUP {
+                        let _ = unsafe { win32input::ReleaseCapture() };
+                    }
+                    let columns = columns.try_borrow().ok()?;
+                    let column = columns.get(index)?;
+                    widths.borrow_mut().remember(column.folder_key().as_deref(), column.width);
+                    None
+                }
+                _ => None,
+            }
+        });
+        match handler {
+            Ok(handler) => *self.splitter_handler.borrow_mut() = Some(handler),
+            Err(e) => log::error!("could not bind the column splitters: {e}"),
+        }
+    }
+    fn icons(&self) -> Rc<icons::IconCache> {
+        self.icons
+            .borrow_mut()
+            .get_or_insert_with(|| Rc::new(icons::IconCache::new(self.icon_notice.sender())))
+            .clone()
+    }
+    fn thumbnails(&self) -> Rc<thumbnails::ThumbnailCache> {
+        self.thumbnails
+            .borrow_mut()
+            .get_or_insert_with(|| Rc::new(thumbnails::ThumbnailCache::new(self.thumbnail_notice.sender())))
+            .clone()
+    }
+    /// Runs `f` on the column whose list view last had focus, if it's still around
+    fn with_focused_column(&self, f: impl FnOnce(&mut Column)) {
+        let focused = self.focused_list_view.get();
+        let mut columns = self.columns.borrow_mut();
+        if let Some(column) = columns.iter_mut().find(|column| column.list_view.handle.hwnd().unwrap() as usize == focused) {
+            f(column);
+        }
+    }
+    fn switch_column(&self, i: i32, path: Option<Folder>) {
+        let mut columns = self.columns.borrow_mut();
+        let idx = TryInto::<usize>::try_into(i).unwrap();
+        columns[idx].switch(path);
+    }
+
+    fn on_window_init(&self) {
+        logging::subscribe(self.log_notice.sender());
+        *self.widths.borrow_mut() = widths::ColumnWidths::load();
+        *self.frecency.borrow_mut() = frecency::Frecency::load();
+        self.terminal.set(terminal::Terminal::load());
+        self.check_terminal();
+        self.view_widths_per_folder_item.set_checked(self.widths.borrow().per_folder());
+        self.bind_splitters();
+        *self.shelf.borrow_mut() = shelf::Shelf::load();
+        self.init_shelf();
+        self.bind_dynamic_menus();
+        self.init_history();
+        self.bind_renames();
+        self.start_index();
+        self.init_tab_strip();
+        self.bind_tabs();
+        self.init_pick();
+        self.show_output_panel(false);
+        self.window.set_visible(true);
+        self.tabs.borrow_mut().push(Tab::default());
+        self.insert_tab_item(0, "Desktop");
+        self.switch_column(0, desktop());
+        self.reconcile_columns();
+        let restored = self.pick.get().is_none() && self.restore_session();
+        self.session_restored.set(true);
+        if let Some(query) = self.startup_jump.borrow_mut().take() {
+            self.jump(&query);
+        }
+        if let Some(path) = self.startup_path.borrow_mut().take() {
+            if restored {
+                self.open_tab(Some(&path));
+            } else if let Err(error) = self.reveal(&path) {
+                self.report(&error);
+            }
+        }
+        if let Some(claim) = self.instance_claim.borrow_mut().take() {
+            *self.instance_listener.borrow_mut() = Some(claim.listen(self.instance_notice.sender()));
+        }
+        if self.serve_control.get() {
+            *self.control.borrow_mut() = Some(control::Server::start(self.control_notice.sender()));
+        }
+    }
+    /// Does what later launches asked, each in a tab of its own, and comes to the front
+    fn on_instance_notice(&self) {
+        let requests = self.instance_listener.borrow().as_ref().map(instance::Listener::take_requests).unwrap_or_default();
+        for request in requests {
+            match request {
+                instance::Request::Show => {}
+                instance::Request::Reveal(path) => self.open_tab(Some(&path)),
+                instance::Request::Jump(query) => {
+                    if let Some(path) = self.jump_target(&query) {
+                        self.open_tab(Some(&path));
+                    }
+                }
+            }
+        }
+        let hwnd = HWND(self.window.handle.hwnd().unwrap() as *mut _);
+        unsafe {
+            if win32wam::IsIconic(hwnd).as_bool() {
+                let _ = win32wam::ShowWindow(hwnd, win32wam::SW_RESTORE);
+            }
+            let _ = win32wam::SetForegroundWindow(hwnd);
+        }
+    }
+    /// Answers the calls control clients have made, see `control`
+    fn on_control_notice(&self) {
+        let calls = self.control.borrow().as_ref().map(control::Server::take_calls).unwrap_or_default();
+        for call in calls {
+            let result = self.control_call(&call.method, &call.params);
+            if let Err(error) = &result {
+                log::warn!("control call {} failed: {error}", call.method);
+            }
+            call.reply(result);
+        }
+    }
+    fn control_call(&self, method: &str, params: &serde_json::Value) -> Result<serde_json::Value, String> {
+        let string = |name: &str| params.get(name).and_then(serde_json::Value::as_str).ok_or_else(|| format!("{method} needs a string {name:?}"));
+        let column_index = |name: &str| -> Result<usize, String> {
+            let count = self.columns.borrow().len();
+            match params.get(name).and_then(serde_json::Value::as_u64) {
+                Some(index) if (index as usize) < count => Ok(index as usize),
+                Some(index) => Err(format!("there's no column {index}, only {count}")),
+                None => Err(format!("{method} needs a column number {name:?}")),
+            }
+        };
+        match method {
+            "tabs" => {
+                let titles: Vec<String> = (0..self.tabs.borrow().len())
+                    .map(|index| if index == self.active_tab.get() { tab_title(&self.columns.borrow()) } else { tab_title(&self.tabs.borrow()[index].columns) })
+                    .collect();
+                Ok(serde_json::json!({"tabs": titles, "active": self.active_tab.get()}))
+            }
+            "columns" => {
+                let focused = self.focused_list_view.get();
+                let columns = self.columns.borrow();
+                Ok(columns.iter().map(|column| column_json(column, column.list_view.handle.hwnd().unwrap() as usize == focused)).collect())
+            }
+            "navigate" => {
+                let path = string("path")?;
+                if params.get("new_tab").and_then(serde_json::Value::as_bool).unwrap_or(false) {
+                    self.open_tab(None);
+                }
+                self.reveal(path).map_err(|error| error.to_string())?;
+                Ok(serde_json::Value::Null)
+            }
+            "select" => {
+                let index = column_index("column")?;
+                let names: Vec<&str> = params
+                    .get("names")
+                    .and_then(serde_json::Value::as_array)
+                    .ok_or("select needs a list of \"names\"")?
+                    .iter()
+                    .filter_map(serde_json::Value::as_str)
+                    .collect();
+                // By the name shown or the full path
+                self.select_in_column(index, |file, _| match file {
+                    File::Shell { display, for_parsing, .. } => names.iter().any(|name| *name == display.as_str() || *name == for_parsing.to_string_lossy()),
+                    File::Error(..) => false,
+                });
+                Ok(serde_json::Value::Null)
+            }
+            "focus" => {
+                let index = column_index("column")?;
+                let list_view = self.columns.borrow()[index].list_view.handle;
+                self.focused_list_view.set(list_view.hwnd().unwrap() as usize);
+                let _ = unsafe { win32input::SetFocus(HWND(list_view.hwnd().unwrap() as *mut _)) };
+                Ok(serde_json::Value::Null)
+            }
+            "tab" => {
+                let index = params.get("index").and_then(serde_json::Value::as_u64).ok_or("tab needs an \"index\"")? as usize;
+                if index >= self.tabs.borrow().len() {
+                    return Err(format!("there's no tab {index}"));
+                }
+                self.activate_tab(index);
+                Ok(serde_json::Value::Null)
+            }
+            "command" => {
+                let name = string("name")?;
+                if !self.run_named_command(name) {
+                    return Err(format!("no command called {name:?}"));
+                }
+                Ok(serde_json::Value::Null)
+            }
+            _ => Err(format!("no method called {method:?}")),
+        }
+    }
+    /// Runs what a menu item or shortcut would, for the control API. `false` if there's nothing by that name.
+    fn run_named_command(&self, name: &str) -> bool {
+        match name {
+            "cut" => self.on_cut(),
+            "copy" => self.on_copy(),
+            "paste" => self.on_paste(),
+            "staple" => self.on_staple(),
+            "remove_from_set" => self.on_remove_from_set(),
+            "undo" => self.on_undo(),
+            "redo" => self.on_redo(),
+            "new_folder" => self.on_new_folder(),
+            "edit_name" => self.on_edit_name(),
+            "invert_selection" => self.on_invert_selection(),
+            "select_files" => self.on_select_files(),
+            "select_folders" => self.on_select_folders(),
+            "new_tab" => self.on_new_tab(),
+            "close_tab" => self.on_close_tab(),
+            "go_to" => self.on_goto(),
+            "jump" => self.on_jump(),
+            "recent_folders" => self.on_recent_folders(),
+            _ => return false,
+        }
+        true
+    }
+    /// Shows the Pick and Cancel buttons, if picking
+    fn init_pick(&self) {
+        let pick = self.pick.get();
+        self.picking.set(pick.is_some());
+        self.pick_label.set_visible(pick.is_some());
+        self.pick_button.set_visible(pick.is_some());
+        self.pick_cancel_button.set_visible(pick.is_some());
+        let Some(pick) = pick else {
+            return;
+        };
+        let wanted = match (pick.dirs, pick.multiple) {
+            (false, false) => "a file",
+            (false, true) => "files",
+            (true, false) => "a folder",
+            (true, true) => "folders",
+        };
+        self.window.set_text(&format!("Stapler - pick {wanted}"));
+        self.pick_label.set_text(&format!("Select {wanted}, then Pick"));
+        self.pick_button.set_focus();
+    }
+    /// Takes the focused column's selection as the pick if it's what `--pick` asked for,
+    /// or the whole of a selection column, or with `--dirs` the column's own folder
+    fn on_pick(&self) {
+        let Some(pick) = self.pick.get() else {
+            return;
+        };
+        let mut picked = Vec::new();
+        self.with_focused_column(|column| {
+            picked = column.children.iter().filter(|child| column.selection.contains(child)).cloned().collect();
+            if picked.is_empty() {
+                match &column.folder {
+                    Some(Folder::Selection { .. }) => picked = column.children.clone(),
+                    Some(Folder::Shell { itemid, display, icon, for_parsing, .. }) if pick.dirs => {
+                        picked = vec![File::Shell { itemid: itemid.clone(), display: display.clone(), for_parsing: for_parsing.clone(), icon: *icon, size: Cell::new(None) }];
+                    }
+                    _ => {}
+                }
+            }
+        });
+        picked.retain(|file| !matches!(file, File::Error(..)));
+        let problem = if picked.is_empty() {
+            Some(if pick.dirs { "Select a folder first" } else { "Select a file first" })
+        } else if picked.len() > 1 && !pick.multiple {
+            Some("Select just one")
+        } else if picked.iter().any(|file| file.is_folder() != pick.dirs) {
+            Some(if pick.dirs { "Only folders can be picked" } else { "Only files can be picked" })
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            self.pick_label.set_text(problem);
+            return;
+        }
+        let paths: Vec<String> = picked
+            .iter()
+            .filter_map(|file| match file {
+                File::Shell { for_parsing, .. } => Some(for_parsing.to_string_lossy()),
+                File::Error(..) => None,
+            })
+            .collect();
+        log::info!("picked {paths:?}");
+        *self.picked.borrow_mut() = Some(paths);
+        self.window.close();
+    }
+    fn on_pick_cancel(&self) {
+        self.window.close();
+    }
+    /// Tells subscribed control clients where the active tab has got to, if that's changed
+    fn publish_navigation(&self, title: &str) {
+        let control = self.control.borrow();
+        let Some(control) = control.as_ref() else {
+            return;
+        };
+        let shown = (self.active_tab.get(), chain_path(&self.columns.borrow()));
+        if self.control_shown.borrow().as_ref() == Some(&shown) {
+            return;
+        }
+        control.publish(serde_json::json!({"event": "navigated", "tab": shown.0, "path": shown.1, "title": title}));
+        *self.control_shown.borrow_mut() = Some(shown);
+    }
+    fn on_debug_log_toggle(&self) {
+        if self.debug_window.visible() {
+            self.debug_window.set_visible(false);
+            self.debug_log_item.set_checked(false);
+        } else {
+            let _ = logging::take_pending();
+            let text = logging::recent().iter().map(|entry| entry.to_string()).collect::<Vec<_>>().join("\r\n");
+            self.debug_text.set_text(&text);
+            self.debug_text.scroll_lastline();
+            self.debug_window.set_visible(true);
+            self.debug_log_item.set_checked(true);
+        }
+    }
+    fn on_debug_window_close(&self, data: &nwg::EventData) {
+        // Only hide it, so it can be brought back from the menu
+        if let nwg::EventData::OnWindowClose(data) = data {
+            data.close(false);
+        }
+        self.debug_window.set_visible(false);
+        self.debug_log_item.set_checked(false);
+    }
+    fn on_log_notice(&self) {
+        let pending = logging::take_pending();
+        if self.debug_window.visible() {
+            for entry in pending {
+                self.debug_text.appendln(&entry.to_string());
+            }
+        }
+    }
+    fn on_icon_notice(&self) {
+        let resolved = self.icons().take_resolved();
+        let mut columns = self.columns.borrow_mut();
+        for column in columns.iter_mut() {
+            let handle = column.list_view.handle.hwnd().unwrap() as usize;
+            column.list_view.set_redraw(false);
+            for resolved in &resolved {
+                if resolved.list_view == handle && resolved.generation == column.icon_generation {
+                    column.set_icon(resolved.row, resolved.icon);
+                }
+            }
+            column.list_view.set_redraw(true);
+        }
+        let shelf_list = self.shelf_list.handle.hwnd().unwrap() as usize;
+        for resolved in &resolved {
+            if resolved.list_view == shelf_list && resolved.generation == self.shelf_icon_generation.get() {
+                let Some(item) = self.shelf_list.item(resolved.row, 0, 260) else {
+                    continue;
+                };
+                self.shelf_list.update_item(resolved.row, nwg::InsertListViewItem {
+                    index: Some(TryInto::<i32>::try_into(resolved.row).unwrap()),
+                    column_index: 0,
+                    text: Some(item.text),
+                    image: Some(resolved.icon),
+                });
+            }
+        }
+    }
+    fn on_thumbnail_notice(&self) {
+        let resolved = self.thumbnails().take_resolved();
+        let mut columns = self.columns.borrow_mut();
+        for resolved in resolved {
+            let column = columns.iter_mut().find(|column| {
+                column.list_view.handle.hwnd().unwrap() as usize == resolved.list_view
+                    && column.thumbnail_generation == resolved.generation
+                    && column.view_mode == ViewMode::Thumbnails
+            });
+            match column {
+                Some(column) => column.set_thumbnail(resolved.row, resolved.bitmap),
+                None => thumbnails::discard(resolved.bitmap),
+            }
+        }
+    }
+    /// Runs a list view shortcut, now that nothing is holding on to `columns`
+    fn on_command_notice(&self) {
+        match self.pending_command.take() {
+            Some(Command::Cut) => self.on_cut(),
+            Some(Command::Copy) => self.on_copy(),
+            Some(Command::Paste) => self.on_paste(),
+            Some(Command::CopyPathAs { of_folder }) => self.show_path_menu(of_folder),
+            Some(Command::Staple) => self.on_staple(),
+            Some(Command::ShelfDragged) => self.fill_shelf(),
+            Some(Command::RemoveFromSet) => self.on_remove_from_set(),
+            Some(Command::SelectMatching) => self.on_select_matching(),
+            Some(Command::Undo) => self.on_undo(),
+            Some(Command::Redo) => self.on_redo(),
+            Some(Command::NewFolder) => self.on_new_folder(),
+            Some(Command::NewFile(index)) => self.new_file(index),
+            Some(Command::EditName) => self.on_edit_name(),
+            Some(Command::Rename) => self.rename(),
+            Some(Command::OpenSet(index)) => self.open_set(index),
+            Some(Command::DeleteSet(index)) => self.delete_set(index),
+            Some(Command::GoTo) => self.on_goto(),
+            Some(Command::Jump) => self.on_jump(),
+            Some(Command::Back) => self.on_back(),
+            Some(Command::Forward) => self.on_forward(),
+            Some(Command::NewTab) => self.on_new_tab(),
+            Some(Command::CloseTab(index)) => self.close_tab(index.unwrap_or(self.active_tab.get())),
+            Some(Command::SwitchTab) => self.activate_tab(self.selected_tab_item()),
+            Some(Command::MoveTab { from, to }) => self.move_tab(from, to),
+            Some(Command::OpenInNewTab(row)) => self.open_in_new_tab(row),
+            Some(Command::Pick) => self.on_pick(),
+            Some(Command::RunUserCommand(index)) => self.run_user_command(index),
+            Some(Command::OpenTerminal) => self.on_open_terminal(),
+            Some(Command::RunHere) => self.on_run_here(),
+            Some(Command::CommandsPopup) => {
+                let (x, y) = nwg::GlobalCursor::position();
+                self.commands_popup.popup(x, y);
+            }
+            None => {}
+        }
+    }
+    fn on_cut(&self) {
+        self.put_selection_on_clipboard(true);
+    }
+    fn on_copy(&self) {
+        self.put_selection_on_clipboard(false);
+    }
+    fn put_selection_on_clipboard(&self, cut: bool) {
+        let mut itemids = Vec::new();
+        self.with_focused_column(|column| itemids = column.selected_itemids());
+        if itemids.is_empty() {
+            return;
+        }
+        if let Err(error) = clipboard::put_files(&itemids, cut) {
+            self.report(&error);
+        }
+    }
+    /// Pastes into the focused column's folder. Nothing here holds `columns`
+    /// while the shell's progress and conflict dialogs are up.
+    fn on_paste(&self) {
+        let mut target = None;
+        self.with_focused_column(|column| {
+            if let Some(Folder::Shell { item, for_parsing, .. }) = &column.folder {
+                target = Some((item.clone(), for_parsing.clone()));
+            }
+        });
+        let Some((folder, for_parsing)) = target else {
+            return;
+        };
+        let action = format!("pasting into {for_parsing}");
+        let as_files = self.paste_as_files_item.checked();
+        let mut done = Vec::new();
+        let new_file = |stem: &str, extension: &str, bytes: &[u8]| {
+            let path = PathBuf::from(for_parsing.to_os_string());
+            clipboard::write_new_file(&path, stem, extension, bytes)
+                .map(|path| {
+                    log::info!("pasted into {}", path.display());
+                    journal::Step::Created { path: path.to_string_lossy().into_owned() }
+                })
+                .map_err(|e| ShellError::new(action.clone(), windows::core::Error::from(e).code()))
+        };
+        let result = match clipboard::contents() {
+            Ok(clipboard::Contents::Files { items, cut }) => {
+                let owner = HWND(self.window.handle.hwnd().unwrap() as *mut _);
+                clipboard::paste_files(owner, &items, &folder, cut, &mut done).map_err(|e| ShellError::new(action.clone(), e.code()))
+            }
+            Ok(clipboard::Contents::Text(text)) if as_files => new_file("Pasted text", "txt", text.as_bytes()).map(|step| done.push(step)),
+            Ok(clipboard::Contents::Image(bmp)) if as_files => new_file("Pasted image", "bmp", &bmp).map(|step| done.push(step)),
+            Ok(_) => {
+                log::debug!("nothing on the clipboard to paste");
+                return;
+            }
+            Err(error) => Err(error),
+        };
+        self.record(&action, done);
+        match result {
+            Ok(()) => {}
+            // Cut files stay on the clipboard, and the column is left as it was
+            Err(error) if error.is_cancelled() => return,
+            Err(error) => self.report(&error),
+        }
+        self.with_focused_column(|column| column.refresh());
+    }
+    fn on_copy_path_as(&self) {
+        self.show_path_menu(false);
+    }
+    /// Offers the ways to copy the paths of the focused column's selection,
+    /// or of its folder if `of_folder` is set or nothing is selected
+    fn show_path_menu(&self, of_folder: bool) {
+        let mut paths = Vec::new();
+        self.with_focused_column(|column| {
+            if !of_folder {
+                paths = column.selected_paths();
+            }
+            if paths.is_empty() {
+                paths = column.folder_paths();
+            }
+        });
+        if paths.is_empty() {
+            return;
+        }
+        *self.path_menu_paths.borrow_mut() = paths;
+        self.path_relative_item.set_enabled(self.relative_base.borrow().is_some());
+        self.path_newlines_item.set_checked(!self.join_paths_with_spaces.get());
+        self.path_spaces_item.set_checked(self.join_paths_with_spaces.get());
+        let (x, y) = nwg::GlobalCursor::position();
+        self.path_menu.popup(x, y);
+    }
+    fn copy_paths(&self, format: path_formats::PathFormat) {
+        let paths = self.path_menu_paths.borrow();
+        let base = self.relative_base.borrow();
+        let text = path_formats::format_paths(paths.iter().map(String::as_str), format, base.as_deref(), self.join_paths_with_spaces.get());
+        let owner = HWND(self.window.handle.hwnd().unwrap() as *mut _);
+        match clipboard::put_text(owner, &text) {
+            Ok(()) => log::info!("copied {} paths as {format:?}", paths.len()),
+            Err(error) => self.report(&error),
+        }
+    }
+    fn on_copy_path_windows(&self) {
+        self.copy_paths(path_formats::PathFormat::Windows);
+    }
+    fn on_copy_path_quoted(&self) {
+        self.copy_paths(path_formats::PathFormat::Quoted);
+    }
+    fn on_copy_path_forward_slash(&self) {
+        self.copy_paths(path_formats::PathFormat::ForwardSlash);
+    }
+    fn on_copy_path_file_uri(&self) {
+        self.copy_paths(path_formats::PathFormat::FileUri);
+    }
+    fn on_copy_path_wsl(&self) {
+        self.copy_paths(path_formats::PathFormat::Wsl);
+    }
+    fn on_copy_path_relative(&self) {
+        self.copy_paths(path_formats::PathFormat::Relative);
+    }
+    fn on_set_relative_base(&self) {
+        let mut base = None;
+        self.with_focused_column(|column| base = column.folder_key());
+        if let Some(base) = &base {
+            log::info!("relative paths now start from {base}");
+        }
+        *self.relative_base.borrow_mut() = base;
+    }
+    fn on_join_paths_with_newlines(&self) {
+        self.join_paths_with_spaces.set(false);
+    }
+    fn on_join_paths_with_spaces(&self) {
+        self.join_paths_with_spaces.set(true);
+    }
+    fn on_staple(&self) {
+        let mut paths = Vec::new();
+        self.with_focused_column(|column| {
+            paths = column.selected_paths();
+            if paths.is_empty() {
+                paths = column.folder_paths();
+            }
+        });
+        let count = paths.len();
+        let added = self.shelf.borrow_mut().add(paths);
+        log::info!("stapled {added} of {count} items");
+        if self.shelf_window.visible() {
+            self.fill_shelf();
+        }
+    }
+    fn init_shelf(&self) {
+        for (text, width) in [("Name", 200), ("Folder", 280)] {
+            self.shelf_list.insert_column(nwg::InsertListViewColumn {
+                index: None,
+                fmt: None,
+                width: Some(width),
+                text: Some(text.into()),
+            });
+        }
+        let shelf_window = self.shelf_window.handle;
+        let shelf_list = self.shelf_list.handle;
+        let shelf = self.shelf.clone();
+        let pending_command = self.pending_command.clone();
+        let command_notice = self.command_notice.sender();
+        let handler = nwg::bind_raw_event_handler(&self.shelf_window.handle, SHELF_DRAG_HANDLER_ID, move |_hwnd, msg, _wparam, lparam| {
+            if msg == win32wam::WM_NOTIFY {
+                let header = unsafe { &*(lparam as *const win32controls::NMHDR) };
+                if header.code == win32controls::LVN_BEGINDRAG && header.hwndFrom.0 as usize == shelf_list.hwnd().unwrap() as usize {
+                    // Right away, while the button is still down, with the staples as they are now
+                    let selected = selected_rows(header.hwndFrom);
+                    let itemids = shelf_itemids(&shelf.borrow(), &selected);
+                    let owner = HWND(shelf_window.hwnd().unwrap() as *mut _);
+                    match itemids.and_then(|itemids| file_operations::drag(owner, &itemids).map_err(|e| ShellError::new("dragging from the shelf", e.code()))) {
+                        Ok(effect) => {
+                            log::debug!("dragged {} staples, effect {:?}", selected.len(), effect);
+                            // A move leaves the staples pointing at nothing, which the list should show
+                            pending_command.set(Some(Command::ShelfDragged));
+                            command_notice.notice();
+                        }
+                        Err(error) => {
+                            nwg::modal_error_message(shelf_window, "Stapler", &error.to_string());
+                        }
+                    }
+                }
+            }
+            None
+        });
+        match handler {
+            Ok(handler) => *self.shelf_drag_handler.borrow_mut() = Some(handler),
+            Err(e) => log::error!("could not bind dragging from the shelf: {e}"),
+        }
+    }
+    fn on_shelf_toggle(&self) {
+        if self.shelf_window.visible() {
+            self.shelf_window.set_visible(false);
+            self.show_shelf_item.set_checked(false);
+        } else {
+            self.shelf_list.set_image_list(Some(&self.image_list_small.borrow()), nwg::ListViewImageListType::Small);
+            self.fill_shelf();
+            self.shelf_window.set_visible(true);
+            self.show_shelf_item.set_checked(true);
+        }
+    }
+    fn on_shelf_window_close(&self, data: &nwg::EventData) {
+        // Only hide it, so it can be brought back from the menu
+        if let nwg::EventData::OnWindowClose(data) = data {
+            data.close(false);
+        }
+        self.shelf_window.set_visible(false);
+        self.show_shelf_item.set_checked(false);
+    }
+    /// Lists the staples, marking any that can't be found any more
+    fn fill_shelf(&self) {
+        let shelf = self.shelf.borrow();
+        let handle = self.shelf_list.handle.hwnd().unwrap() as usize;
+        let icons = self.icons();
+        let generation = icons.begin(handle);
+        self.shelf_icon_generation.set(generation);
+        self.shelf_list.set_redraw(false);
+        self.shelf_list.clear();
+        for (row, path) in shelf.items().iter().enumerate() {
+            let (folder, name) = path.rsplit_once('\\').unwrap_or(("", path));
+            let file = ItemId::from_parsing_name(path).ok().and_then(|itemid| File::named(itemid, None).ok());
+            let (text, image) = match file {
+                Some(File::Shell { itemid, display, for_parsing, .. }) => {
+                    let image = itemid.shell_item().ok().map(|item| icons.lookup(handle, generation, row, &item, &itemid, &for_parsing));
+                    (display, image)
+                }
+                _ => (format!("{name} (missing)"), None),
+            };
+            self.shelf_list.insert_item(nwg::InsertListViewItem {
+                index: Some(TryInto::<i32>::try_into(row).unwrap()),
+                column_index: 0,
+                text: Some(text),
+                image,
+            });
+            self.shelf_list.insert_item(nwg::InsertListViewItem {
+                index: Some(TryInto::<i32>::try_into(row).unwrap()),
+                column_index: 1,
+                text: Some(folder.to_owned()),
+                image: None,
+            });
+        }
+        self.shelf_list.set_redraw(true);
+    }
+    /// The staples an operation applies to: the selected ones, or all of them
+    fn shelf_selection(&self) -> Vec<usize> {
+        let selected = self.shelf_list.selected_items();
+        if selected.is_empty() {
+            (0..self.shelf.borrow().items().len()).collect()
+        } else {
+            selected
+        }
+    }
+    fn shelf_itemids(&self, indexes: &[usize]) -> Result<Vec<Rc<ItemId>>, ShellError> {
+        shelf_itemids(&self.shelf.borrow(), indexes)
+    }
+    fn on_shelf_copy_here(&self) {
+        self.shelf_transfer(false);
+    }
+    fn on_shelf_move_here(&self) {
+        self.shelf_transfer(true);
+    }
+    /// Copies or moves staples into the focused column's folder
+    fn shelf_transfer(&self, move_items: bool) {
+        let mut target = None;
+        self.with_focused_column(|column| {
+            if let Some(Folder::Shell { item, for_parsing, .. }) = &column.folder {
+                target = Some((item.clone(), for_parsing.clone()));
+            }
+        });
+        let Some((folder, for_parsing)) = target else {
+            nwg::modal_info_message(&self.shelf_window, "Stapler", "Click into a folder in the main window first, to copy or move the staples there.");
+            return;
+        };
+        let indexes = self.shelf_selection();
+        let action = format!("{} the shelf into {for_parsing}", if move_items { "moving" } else { "copying" });
+        let owner = HWND(self.shelf_window.handle.hwnd().unwrap() as *mut _);
+        let mut done = Vec::new();
+        let result = self.shelf_itemids(&indexes).and_then(|itemids| {
+            file_operations::shell_item_array(&itemids)
+                .and_then(|items| file_operations::transfer(owner, &items, &folder, move_items, &mut done))
+                .map_err(|e| ShellError::new(action.clone(), e.code()))
+        });
+        if move_items {
+            self.shelf.borrow_mut().retarget(&done);
+            self.fill_shelf();
+        }
+        self.record(&action, done);
+        match result {
+            Ok(()) => self.with_focused_column(|column| column.refresh()),
+            Err(error) => self.report(&error),
+        }
+    }
+    fn on_shelf_zip(&self) {
+        let paths: Vec<String> = {
+            let shelf = self.shelf.borrow();
+            self.shelf_selection().iter().filter_map(|&i| shelf.items().get(i).cloned()).collect()
+        };
+        self.zip(paths, &self.shelf_window);
+    }
+    /// Asks where to, then zips `paths` there in the background
+    fn zip(&self, paths: Vec<String>, parent: &nwg::Window) {
+        if paths.is_empty() || !self.zip_dialog.run(Some(parent)) {
+            return;
+        }
+        let Ok(zip) = self.zip_dialog.get_selected_item() else {
+            return;
+        };
+        let mut zip = PathBuf::from(zip);
+        if zip.extension().is_none() {
+            zip.set_extension("zip");
+        }
+        // tar can take a while, so it reports back through `on_zip_notice`
+        let zip_failure = self.zip_failure.clone();
+        let zip_notice = self.zip_notice.sender();
+        std::thread::spawn(move || match file_operations::zip(&paths, &zip) {
+            Ok(()) => log::info!("zipped {} items into {}", paths.len(), zip.display()),
+            Err(e) => {
+                log::error!("could not zip into {}: {e:#}", zip.display());
+                *zip_failure.lock().unwrap() = Some(format!("Could not zip into {}:\r\n{e:#}", zip.display()));
+                zip_notice.notice();
+            }
+        });
+    }
+    fn on_zip_notice(&self) {
+        if let Some(failure) = self.zip_failure.lock().unwrap().take() {
+            nwg::modal_error_message(&self.window, "Stapler", &failure);
+        }
+    }
+    fn on_shelf_remove(&self) {
+        let selected = self.shelf_list.selected_items();
+        if selected.is_empty() {
+            return;
+        }
+        self.shelf.borrow_mut().remove(&selected);
+        self.fill_shelf();
+    }
+    fn on_shelf_clear(&self) {
+        let params = nwg::MessageParams {
+            title: "Stapler",
+            content: "Take everything off the shelf?",
+            buttons: nwg::MessageButtons::YesNo,
+            icons: nwg::MessageIcons::Question,
+        };
+        if nwg::modal_message(&self.shelf_window, &params) == nwg::MessageChoice::Yes {
+            self.shelf.borrow_mut().clear();
+            self.fill_shelf();
+        }
+    }
+    fn on_shelf_key(&self, data: &nwg::EventData) {
+        let nwg::EventData::OnKey(key) = data else {
+            return;
+        };
+        let control = unsafe { win32input::GetKeyState(win32input::VK_CONTROL.0 as i32) } < 0;
+        match key {
+            0x2E => self.on_shelf_remove(),
+            0x43 if control => {
+                let selected = self.shelf_list.selected_items();
+                match self.shelf_itemids(&selected).and_then(|itemids| clipboard::put_files(&itemids, false)) {
+                    Ok(()) => {}
+                    Err(error) => self.report(&error),
+                }
+            }
+            _ => {}
+        }
+    }
+    /// What the Selection menu acts on: everything in the focused column if it's
+    /// showing a selection or search hits, otherwise what's selected in it
+    fn selection_set(&self) -> Vec<File> {
+        let mut files = Vec::new();
+        self.with_focused_column(|column| {
+            files = match &column.folder {
+                Some(Folder::Selection { .. }) | Some(Folder::Search { .. }) => column.children.clone(),
+                _ => column.children.iter().filter(|child| column.selection.contains(child)).cloned().collect(),
+            };
+        });
+        files
+    }
+    fn selection_itemids(&self) -> Vec<Rc<ItemId>> {
+        self.selection_set()
+            .into_iter()
+            .filter_map(|file| match file {
+                File::Shell { itemid, .. } => Some(itemid),
+                File::Error(..) => None,
+            })
+            .collect()
+    }
+    /// Lists the column the selection came from again, after some of it
+    /// might have moved or gone, and passes what's left along the chain
+    fn refresh_selection_source(&self) {
+        let focused = self.focused_list_view.get();
+        let mut columns = self.columns.borrow_mut();
+        let Some(mut index) = columns.iter().position(|column| column.list_view.handle.hwnd().unwrap() as usize == focused) else {
+            return;
+        };
+        if matches!(columns[index].folder, Some(Folder::Selection { .. })) && index > 0 {
+            index -= 1;
+        }
+        columns[index].refresh();
+        cascade_selection(&mut columns, index);
+        std::mem::drop(columns);
+        self.layout_notice.sender().notice();
+    }
+    fn on_selection_copy_to(&self) {
+        self.transfer_selection(false);
+    }
+    fn on_selection_move_to(&self) {
+        self.transfer_selection(true);
+    }
+    fn transfer_selection(&self, move_items: bool) {
+        let itemids = self.selection_itemids();
+        if itemids.is_empty() || !self.folder_dialog.run(Some(&self.window)) {
+            return;
+        }
+        let Ok(folder) = self.folder_dialog.get_selected_item() else {
+            return;
+        };
+        let folder = folder.to_string_lossy().into_owned();
+        let action = format!("{} the selection to {folder}", if move_items { "moving" } else { "copying" });
+        let owner = HWND(self.window.handle.hwnd().unwrap() as *mut _);
+        let mut done = Vec::new();
+        let result = ItemId::from_parsing_name(&folder)
+            .and_then(|folder| folder.shell_item())
+            .and_then(|folder| {
+                let items = file_operations::shell_item_array(&itemids)?;
+                file_operations::transfer(owner, &items, &folder, move_items, &mut done)
+            })
+            .map_err(|e| ShellError::new(action.clone(), e.code()));
+        self.record(&action, done);
+        match result {
+            Ok(()) if move_items => self.refresh_selection_source(),
+            Ok(()) => {}
+            Err(error) => self.report(&error),
+        }
+    }
+    fn on_selection_delete(&self) {
+        let itemids = self.selection_itemids();
+        if itemids.is_empty() {
+            return;
+        }
+        let owner = HWND(self.window.handle.hwnd().unwrap() as *mut _);
+        let mut done = Vec::new();
+        let result = file_operations::shell_item_array(&itemids)
+            .and_then(|items| file_operations::delete(owner, &items, &mut done))
+            .map_err(|e| ShellError::new("deleting the selection", e.code()));
+        self.record(format!("deleting {} items", itemids.len()), done);
+        match result {
+            Ok(()) => self.refresh_selection_source(),
+            Err(error) => self.report(&error),
+        }
+    }
+    fn on_selection_zip(&self) {
+        let paths = self
+            .selection_set()
+            .into_iter()
+            .filter_map(|file| match file {
+                File::Shell { for_parsing, .. } => Some(for_parsing.to_string_lossy()),
+                File::Error(..) => None,
+            })
+            .collect();
+        self.zip(paths, &self.window);
+    }
+    /// Asks for a program and starts it once with every file in the set
+    fn on_selection_open_with(&self) {
+        let paths: Vec<_> = self
+            .selection_set()
+            .into_iter()
+            .filter_map(|file| match file {
+                File::Shell { for_parsing, .. } => Some(for_parsing.to_os_string()),
+                File::Error(..) => None,
+            })
+            .collect();
+        if paths.is_empty() || !self.program_dialog.run(Some(&self.window)) {
+            return;
+        }
+        let Ok(program) = self.program_dialog.get_selected_item() else {
+            return;
+        };
+        match std::process::Command::new(&program).args(&paths).spawn() {
+            Ok(_) => log::info!("opened {} items with {}", paths.len(), program.display()),
+            Err(e) => log::error!("could not start {}: {e}", program.display()),
+        }
+    }
+    /// Takes the rows selected in a `Folder::Selection` column out of its set, and
+    /// deselects them where they came from so the two stay the same
+    fn on_remove_from_set(&self) {
+        let focused = self.focused_list_view.get();
+        let mut columns = self.columns.borrow_mut();
+        let Some(index) = columns.iter().position(|column| column.list_view.handle.hwnd().unwrap() as usize == focused) else {
+            return;
+        };
+        if index == 0 || !matches!(columns[index].folder, Some(Folder::Selection { .. })) {
+            return;
+        }
+        let removed = columns[index].selection.clone();
+        if removed.is_empty() {
+            return;
+        }
+        let Some(Folder::Selection { selection: set }) = &columns[index].folder else {
+            return;
+        };
+        let remaining: HashSet<File> = set.difference(&removed).cloned().collect();
+        let source = &mut columns[index - 1];
+        if source.selection == *set {
+            for (row, child) in source.children.iter().enumerate() {
+                if removed.contains(child) {
+                    source.list_view.select_item(row, false);
+                }
+            }
+            source.selection = remaining;
+            cascade_selection(&mut columns, index - 1);
+        } else {
+            // An opened set, which isn't anything selected in the column before
+            columns[index].switch(Some(remaining).filter(|set| !set.is_empty()).map(|selection| Folder::Selection { selection }));
+            for column in columns.iter_mut().skip(index + 1) {
+                column.switch(None);
+            }
+        }
+        std::mem::drop(columns);
+        self.layout_notice.sender().notice();
+    }
+    /// Selects the rows of the focused column `wanted` picks, given each one and whether
+    /// it's selected now, and passes the new selection along the chain
+    fn select_in_focused_column(&self, wanted: impl Fn(&File, bool) -> bool) {
+        let focused = self.focused_list_view.get();
+        let index = self.columns.borrow().iter().position(|column| column.list_view.handle.hwnd().unwrap() as usize == focused);
+        if let Some(index) = index {
+            self.select_in_column(index, wanted);
+        }
+    }
+    /// Like `select_in_focused_column`, for `columns[index]`
+    fn select_in_column(&self, index: usize, wanted: impl Fn(&File, bool) -> bool) {
+        let mut columns = self.columns.borrow_mut();
+        let column = &mut columns[index];
+        let mut selection = HashSet::new();
+        for (row, child) in column.children.iter().enumerate() {
+            let selected = wanted(child, column.selection.contains(child));
+            // The list view's change notifications are ignored while `columns` is borrowed
+            column.list_view.select_item(row, selected);
+            if selected {
+                selection.insert(child.clone());
+            }
+        }
+        log::info!("selected {} of {} items", selection.len(), column.children.len());
+        column.selection = selection;
+        if !cascade_selection(&mut columns, index) {
+            log::warn!("selection in the last column had nowhere to go");
+        }
+        std::mem::drop(columns);
+        self.layout_notice.sender().notice();
+    }
+    fn on_invert_selection(&self) {
+        self.select_in_focused_column(|file, selected| !selected && !matches!(file, File::Error(..)));
+    }
+    fn on_select_files(&self) {
+        self.select_in_focused_column(|file, _| !matches!(file, File::Error(..)) && !file.is_folder());
+    }
+    fn on_select_folders(&self) {
+        self.select_in_focused_column(|file, _| file.is_folder());
+    }
+    fn on_select_matching(&self) {
+        self.select_pattern_input.set_focus();
+        self.select_window.set_visible(true);
+    }
+    fn on_select_matching_cancel(&self) {
+        self.select_window.set_visible(false);
+    }
+    fn on_select_window_close(&self, data: &nwg::EventData) {
+        if let nwg::EventData::OnWindowClose(data) = data {
+            data.close(false);
+        }
+        self.select_window.set_visible(false);
+    }
+    /// Reads the Select matching window, complaining about anything that doesn't parse
+    fn select_criteria(&self) -> Result<matching::Criteria, String> {
+        let name = matching::NamePattern::parse(&self.select_pattern_input.text(), self.select_regex_check.check_state() == nwg::CheckBoxState::Checked)
+            .map_err(|e| format!("The name pattern isn't a regular expression:\r\n{e}"))?;
+        let number = |input: &nwg::TextInput, what: &str| -> Result<Option<u64>, String> {
+            let text = input.text();
+            let text = text.trim();
+            if text.is_empty() {
+                return Ok(None);
+            }
+            text.parse().map(Some).map_err(|_| format!("{what} should be a whole number, not {text:?}."))
+        };
+        let kind = match self.select_kind_combo.selection() {
+            Some(1) => matching::Kind::Files,
+            Some(2) => matching::Kind::Folders,
+            _ => matching::Kind::Anything,
+        };
+        Ok(matching::Criteria {
+            name,
+            kind,
+            min_size: number(&self.select_min_size_input, "The smallest size")?.map(|kb| kb * 1024),
+            max_size: number(&self.select_max_size_input, "The largest size")?.map(|kb| kb * 1024),
+            modified_within: number(&self.select_days_input, "The number of days")?.map(|days| std::time::Duration::from_secs(days * 24 * 60 * 60)),
+        })
+    }
+    /// Searches everything under the focused column's folder, showing the hits in the next column
+    fn on_search_ok(&self) {
+        let criteria = match self.select_criteria() {
+            Ok(criteria) => matching::Criteria {
+                name: criteria.name.map(matching::NamePattern::anywhere),
+                ..criteria
+            },
+            Err(message) => {
+                nwg::modal_info_message(&self.select_window, "Stapler", &message);
+                return;
+            }
+        };
+        let contents = Some(self.select_contents_input.text()).filter(|text| !text.trim().is_empty());
+        let focused = self.focused_list_view.get();
+        let mut columns = self.columns.borrow_mut();
+        let Some(index) = columns.iter().position(|column| column.list_view.handle.hwnd().unwrap() as usize == focused) else {
+            return;
+        };
+        let Some(Folder::Shell { itemid, icon, for_parsing, .. }) = columns[index].folder.clone() else {
+            std::mem::drop(columns);
+            nwg::modal_info_message(&self.select_window, "Stapler", "Click into a folder first, to search under it.");
+            return;
+        };
+        if index + 1 >= columns.len() {
+            return;
+        }
+        self.select_window.set_visible(false);
+        let root = PathBuf::from(for_parsing.to_os_string());
+        log::info!("searching {}", root.display());
+        let search = search::Search::start(root, search::Query { criteria, contents }, self.search_notice.sender());
+        // The results column takes the place of whatever the selection was showing
+        let column = &mut columns[index];
+        for (row, child) in column.children.iter().enumerate() {
+            if column.selection.contains(child) {
+                column.list_view.select_item(row, false);
+            }
+        }
+        column.selection.clear();
+        columns[index + 1].switch(Some(Folder::Search {
+            itemid,
+            icon,
+            for_parsing,
+            search: Rc::new(search),
+        }));
+        for column in columns.iter_mut().skip(index + 2) {
+            column.switch(None);
+        }
+        std::mem::drop(columns);
+        self.layout_notice.sender().notice();
+    }
+    /// Puts new hits into whichever columns are showing searches
+    fn on_search_notice(&self) {
+        // The columns are busy in some other handler; come back once it's done
+        let Ok(mut columns) = self.columns.try_borrow_mut() else {
+            self.search_notice.sender().notice();
+            return;
+        };
+        for column in columns.iter_mut() {
+            column.add_search_hits();
+        }
+    }
+    fn on_select_matching_ok(&self) {
+        let criteria = match self.select_criteria() {
+            Ok(criteria) => criteria,
+            Err(message) => {
+                nwg::modal_info_message(&self.select_window, "Stapler", &message);
+                return;
+            }
+        };
+        self.select_window.set_visible(false);
+        let add = self.select_add_check.check_state() == nwg::CheckBoxState::Checked;
+        self.select_in_focused_column(|file, selected| {
+            if add && selected {
+                return true;
+            }
+            let File::Shell { display, for_parsing, .. } = file else {
+                return false;
+            };
+            // The name first, so rows it rules out never go near the disk
+            if !criteria.name.as_ref().is_none_or(|pattern| pattern.matches(display)) {
+                return false;
+            }
+            let is_folder = criteria.kind != matching::Kind::Anything && file.is_folder();
+            let size = (criteria.min_size.is_some() || criteria.max_size.is_some()).then(|| file.size()).flatten();
+            // Only files and folders on disk have a date to go by, so nothing else matches one
+            let modified = criteria
+                .modified_within
+                .and_then(|_| std::fs::metadata(for_parsing.to_os_string()).ok())
+                .and_then(|metadata| metadata.modified().ok());
+            criteria.matches(display, is_folder, size, modified)
+        });
+    }
+    /// Listens for the menu items made at run time, then makes them
+    fn bind_dynamic_menus(&self) {
+        let menu_items = Rc::downgrade(&self.dynamic_menu_items);
+        let pending_command = self.pending_command.clone();
+        let command_notice = self.command_notice.sender();
+        let handler = nwg::bind_event_handler(&self.window.handle, &self.window.handle, move |evt, _evt_data, handle| {
+            if evt != nwg::Event::OnMenuItemSelected {
+                return;
+            }
+            let Some(menu_items) = menu_items.upgrade() else {
+                return;
+            };
+            let Ok(menu_items) = menu_items.try_borrow() else {
+                return;
+            };
+            if let Some((_, command)) = menu_items.iter().find(|(item, _)| item.handle == handle) {
+                pending_command.set(Some(*command));
+                command_notice.notice();
+            }
+        });
+        *self.dynamic_menu_handler.borrow_mut() = Some(handler);
+        self.fill_sets_menu();
+        self.load_templates();
+        self.fill_commands_menu();
+    }
+    /// Makes the Open and Delete entries for each saved set
+    fn fill_sets_menu(&self) {
+        let names = selection_sets::names();
+        let mut set_items = self.dynamic_menu_items.borrow_mut();
+        // Dropping the old items takes them out of the menus
+        set_items.retain(|(_, command)| !matches!(command, Command::OpenSet(_) | Command::DeleteSet(_)));
+        for (menu, make_command) in [
+            (&self.open_set_menu, Command::OpenSet as fn(usize) -> Command),
+            (&self.delete_set_menu, Command::DeleteSet as fn(usize) -> Command),
+        ] {
+            if names.is_empty() {
+                let mut item = nwg::MenuItem::default();
+                match nwg::MenuItem::builder().text("(none saved)").disabled(true).parent(menu).build(&mut item) {
+                    Ok(()) => set_items.push((item, Command::OpenSet(usize::MAX))),
+                    Err(e) => log::error!("could not fill the Sets menu: {e}"),
+                }
+            }
+            for (index, name) in names.iter().enumerate() {
+                let mut item = nwg::MenuItem::default();
+                match nwg::MenuItem::builder().text(&name.replace('&', "&&")).parent(menu).build(&mut item) {
+                    Ok(()) => set_items.push((item, make_command(index))),
+                    Err(e) => log::error!("could not add {name} to the Sets menu: {e}"),
+                }
+            }
+        }
+        *self.set_names.borrow_mut() = names;
+    }
+    /// Keeps what a command did to files, so it can be undone
+    fn record(&self, description: impl Into<String>, steps: Vec<journal::Step>) {
+        self.journal.borrow_mut().record(description, steps);
+        self.update_history();
+    }
+    fn init_history(&self) {
+        for (text, width) in [("", 60), ("What", 360), ("Items", 60)] {
+            self.history_list.insert_column(nwg::InsertListViewColumn {
+                index: None,
+                fmt: None,
+                width: Some(width),
+                text: Some(text.into()),
             });
         }
     }
-    fn switch_column(&self, i: i32, path: Option<Folder>) {
+    /// Brings the Undo and Redo menu items and the history window up to date
+    fn update_history(&self) {
+        let journal = self.journal.borrow();
+        self.undo_item.set_enabled(journal.can_undo());
+        self.redo_item.set_enabled(journal.can_redo());
+        self.history_undo_button.set_enabled(journal.can_undo());
+        self.history_redo_button.set_enabled(journal.can_redo());
+        if !self.history_window.visible() {
+            return;
+        }
+        self.history_list.clear();
+        // What would be redone last at the top, down to what would be undone last
+        let redo: Vec<_> = journal.redo_entries().collect();
+        let entries = redo.iter().rev().map(|entry| ("Undone", *entry)).chain(journal.undo_entries().map(|entry| ("Done", entry)));
+        for (row, (state, entry)) in entries.enumerate() {
+            let row = TryInto::<i32>::try_into(row).unwrap();
+            self.history_list.insert_items_row(Some(row), &[state.to_owned(), entry.description.clone(), entry.steps.len().to_string()]);
+        }
+    }
+    fn on_history_toggle(&self) {
+        if self.history_window.visible() {
+            self.history_window.set_visible(false);
+            self.history_item.set_checked(false);
+        } else {
+            self.history_window.set_visible(true);
+            self.history_item.set_checked(true);
+            self.update_history();
+        }
+    }
+    fn on_history_window_close(&self, data: &nwg::EventData) {
+        if let nwg::EventData::OnWindowClose(data) = data {
+            data.close(false);
+        }
+        self.history_window.set_visible(false);
+        self.history_item.set_checked(false);
+    }
+    fn on_undo(&self) {
+        self.reverse(true);
+    }
+    fn on_redo(&self) {
+        self.reverse(false);
+    }
+    /// Undoes or redoes the newest entry, unless things have changed since
+    fn reverse(&self, undo: bool) {
+        let verb = if undo { "undo" } else { "redo" };
+        let owner = HWND(self.window.handle.hwnd().unwrap() as *mut _);
+        let newest = {
+            let mut journal = self.journal.borrow_mut();
+            if undo { journal.take_undo() } else { journal.take_redo() }
+        };
+        let Some(newest) = newest else {
+            return;
+        };
+        let description = newest.description.clone();
+        // Not borrowed meanwhile, as the shell pumps messages and the history window may be drawn
+        let (reversal, result) = newest.reverse(owner);
+        self.journal.borrow_mut().put_back(undo, reversal);
+        match result {
+            Ok(_) => log::info!("{verb}: {description}"),
+            Err(journal::Refusal::Diverged(reasons)) => {
+                let question = format!(
+                    "Can't {verb} {description}, because things have changed since:\r\n\r\n{}\r\n\r\nTake it out of the history?",
+                    reasons.join("\r\n")
+                );
+                let params = nwg::MessageParams {
+                    title: "Stapler",
+                    content: &question,
+                    buttons: nwg::MessageButtons::YesNo,
+                    icons: nwg::MessageIcons::Warning,
+                };
+                if nwg::modal_message(&self.window, &params) == nwg::MessageChoice::Yes {
+                    let mut journal = self.journal.borrow_mut();
+                    if undo {
+                        journal.forget_undo();
+                    } else {
+                        journal.forget_redo();
+                    }
+                }
+            }
+            Err(journal::Refusal::Failed(e)) => self.report(&ShellError::new(format!("trying to {verb} {description}"), e.code())),
+        }
+        self.update_history();
+        self.refresh_all_columns();
+    }
+    /// Lists every column again after files have changed anywhere, passing
+    /// the selection on from the first column that lost some of its
+    fn refresh_all_columns(&self) {
+        let mut columns = self.columns.borrow_mut();
+        for index in 0..columns.len() {
+            let before = columns[index].selection.len();
+            columns[index].refresh();
+            if columns[index].selection.len() != before {
+                cascade_selection(&mut columns, index);
+                break;
+            }
+        }
+        std::mem::drop(columns);
+        self.layout_notice.sender().notice();
+    }
+    /// Reads the templates again, the user's straight away and the shell's in the background
+    fn load_templates(&self) {
+        *self.shell_templates_reader.borrow_mut() = Some(templates::ShellTemplates::start(self.templates_notice.sender()));
+        self.fill_new_menu();
+    }
+    fn on_templates_notice(&self) {
+        let shell_templates = self.shell_templates_reader.borrow().as_ref().and_then(|reader| reader.take());
+        if let Some(shell_templates) = shell_templates {
+            *self.shell_templates.borrow_mut() = shell_templates;
+            self.fill_new_menu();
+        }
+    }
+    /// Makes an entry under File > New for each template
+    fn fill_new_menu(&self) {
+        let templates = templates::load(&self.shell_templates.borrow());
+        let mut menu_items = self.dynamic_menu_items.borrow_mut();
+        menu_items.retain(|(_, command)| !matches!(command, Command::NewFile(_)));
+        for (index, template) in templates.iter().enumerate() {
+            let text = format!("{} (.{})", template.name, template.extension).replace('&', "&&");
+            let mut item = nwg::MenuItem::default();
+            match nwg::MenuItem::builder().text(&text).parent(&self.new_menu).build(&mut item) {
+                Ok(()) => menu_items.push((item, Command::NewFile(index))),
+                Err(e) => log::error!("could not add {text} to the New menu: {e}"),
+            }
+        }
+        log::debug!("{} templates", templates.len());
+        *self.templates.borrow_mut() = templates;
+    }
+    /// Puts the user's commands in the Commands menu and the right-click menu
+    fn fill_commands_menu(&self) {
+        let commands = user_commands::load();
+        let mut menu_items = self.dynamic_menu_items.borrow_mut();
+        menu_items.retain(|(_, command)| !matches!(command, Command::RunUserCommand(_)));
+        for (index, command) in commands.iter().enumerate() {
+            let mut text = command.name.replace('&', "&&");
+            if let Some(key) = command.key {
+                text = format!("{text}\t{}", key.label());
+            }
+            for menu in [&self.commands_menu, &self.commands_popup] {
+                let mut item = nwg::MenuItem::default();
+                match nwg::MenuItem::builder().text(&text).parent(menu).build(&mut item) {
+                    Ok(()) => menu_items.push((item, Command::RunUserCommand(index))),
+                    Err(e) => log::error!("could not add {} to the Commands menu: {e}", command.name),
+                }
+            }
+        }
+        if commands.is_empty() {
+            let mut item = nwg::MenuItem::default();
+            match nwg::MenuItem::builder().text("(no commands)").disabled(true).parent(&self.commands_popup).build(&mut item) {
+                Ok(()) => menu_items.push((item, Command::RunUserCommand(usize::MAX))),
+                Err(e) => log::error!("could not fill the right-click menu: {e}"),
+            }
+        }
+        log::debug!("{} commands", commands.len());
+        *self.user_commands.borrow_mut() = commands;
+    }
+    fn on_edit_commands(&self) {
+        match user_commands::path() {
+            Ok(path) => {
+                if let Err(e) = std::process::Command::new("notepad.exe").arg(&path).spawn() {
+                    log::error!("could not open {}: {e}", path.display());
+                }
+            }
+            Err(e) => log::error!("could not make the commands file: {e:#}"),
+        }
+    }
+    /// Runs one of the user's commands on the focused column's selection and folder
+    fn run_user_command(&self, index: usize) {
+        let (mut folder, mut paths) = (None, Vec::new());
+        self.with_focused_column(|column| {
+            if let Some(Folder::Shell { for_parsing, .. }) = &column.folder {
+                folder = Some(for_parsing.to_string_lossy());
+            }
+            paths = column.selected_paths();
+        });
+        let commands = self.user_commands.borrow();
+        let Some(command) = commands.get(index) else {
+            return;
+        };
+        let invocations = match command.expand(folder.as_deref(), &paths) {
+            Ok(invocations) => invocations,
+            Err(message) => {
+                nwg::modal_info_message(&self.window, "Stapler", &message);
+                return;
+            }
+        };
+        if command.output {
+            let title = command.name.clone();
+            std::mem::drop(commands);
+            self.run_in_output_panel(&title, invocations);
+        } else {
+            for invocation in &invocations {
+                invocation.spawn();
+            }
+        }
+    }
+    /// Shows the output panel with what `invocations` print, stopping whatever it was showing
+    fn run_in_output_panel(&self, title: &str, invocations: Vec<user_commands::Invocation>) {
+        *self.output_run.borrow_mut() = None;
+        *self.output_title.borrow_mut() = title.to_owned();
+        self.output_label.set_text(&format!("{title}: running..."));
+        self.output_text.set_text("");
+        self.output_stop_button.set_enabled(true);
+        self.show_output_panel(true);
+        *self.output_run.borrow_mut() = Some(output::Run::start(invocations, self.output_notice.sender()));
+    }
+    fn show_output_panel(&self, shown: bool) {
+        self.output_shown.set(shown);
+        self.output_label.set_visible(shown);
+        self.output_input.set_visible(shown);
+        self.output_run_button.set_visible(shown);
+        self.output_stop_button.set_visible(shown);
+        self.output_close_button.set_visible(shown);
+        self.output_text.set_visible(shown);
+        self.layout_columns();
+    }
+    fn on_output_notice(&self) {
+        let run = self.output_run.borrow();
+        let Some(run) = run.as_ref() else {
+            return;
+        };
+        let output = run.take_output();
+        if !output.is_empty() {
+            self.output_text.append(&output);
+            self.output_text.scroll_lastline();
+        }
+        if run.finished() {
+            self.output_label.set_text(&format!("{}: done", self.output_title.borrow()));
+            self.output_stop_button.set_enabled(false);
+        }
+    }
+    fn on_output_stop(&self) {
+        if let Some(run) = self.output_run.borrow().as_ref() {
+            run.stop();
+        }
+    }
+    fn on_output_close(&self) {
+        *self.output_run.borrow_mut() = None;
+        self.show_output_panel(false);
+    }
+    /// The focused column's folder, if it's one on disk, or else tells the user
+    fn focused_disk_folder(&self) -> Option<PathBuf> {
+        let mut folder = None;
+        self.with_focused_column(|column| {
+            if let Some(Folder::Shell { for_parsing, .. }) = &column.folder {
+                folder = Some(PathBuf::from(for_parsing.to_os_string())).filter(|folder| folder.is_dir());
+            }
+        });
+        if folder.is_none() {
+            nwg::modal_info_message(&self.window, "Stapler", "Click into a folder on disk first.");
+        }
+        folder
+    }
+    fn on_open_terminal(&self) {
+        let Some(folder) = self.focused_disk_folder() else {
+            return;
+        };
+        let terminal = self.terminal.get();
+        if let Err(e) = terminal.open(&folder) {
+            nwg::modal_error_message(&self.window, "Stapler", &format!("Could not start {terminal:?}:\r\n{e}"));
+        }
+    }
+    fn choose_terminal(&self, terminal: terminal::Terminal) {
+        self.terminal.set(terminal);
+        terminal.save();
+        self.check_terminal();
+    }
+    fn check_terminal(&self) {
+        let terminal = self.terminal.get();
+        self.terminal_windows_terminal_item.set_checked(terminal == terminal::Terminal::WindowsTerminal);
+        self.terminal_cmd_item.set_checked(terminal == terminal::Terminal::Cmd);
+        self.terminal_powershell_item.set_checked(terminal == terminal::Terminal::PowerShell);
+        self.terminal_wsl_item.set_checked(terminal == terminal::Terminal::Wsl);
+    }
+    fn on_terminal_windows_terminal(&self) {
+        self.choose_terminal(terminal::Terminal::WindowsTerminal);
+    }
+    fn on_terminal_cmd(&self) {
+        self.choose_terminal(terminal::Terminal::Cmd);
+    }
+    fn on_terminal_powershell(&self) {
+        self.choose_terminal(terminal::Terminal::PowerShell);
+    }
+    fn on_terminal_wsl(&self) {
+        self.choose_terminal(terminal::Terminal::Wsl);
+    }
+    fn on_run_here(&self) {
+        if !self.output_shown.get() {
+            self.output_label.set_text("");
+            self.show_output_panel(true);
+        }
+        self.output_input.set_focus();
+    }
+    fn on_output_key(&self, data: &nwg::EventData) {
+        match data {
+            nwg::EventData::OnKey(0x0D) => self.on_output_run(),
+            nwg::EventData::OnKey(0x1B) => self.on_output_close(),
+            _ => {}
+        }
+    }
+    /// Runs what's typed in the output panel in the focused column's folder
+    fn on_output_run(&self) {
+        let line = self.output_input.text().trim().to_owned();
+        if line.is_empty() {
+            return;
+        }
+        let Some(folder) = self.focused_disk_folder() else {
+            return;
+        };
+        let title = folder.file_name().map_or_else(|| folder.display().to_string(), |name| name.to_string_lossy().into_owned());
+        self.run_in_output_panel(&title, vec![user_commands::Invocation { line, dir: Some(folder) }]);
+        self.output_input.set_text("");
+        self.output_input.set_focus();
+    }
+    fn on_edit_templates(&self) {
+        match templates::dir() {
+            Ok(dir) => {
+                if let Err(e) = std::process::Command::new("explorer.exe").arg(&dir).spawn() {
+                    log::error!("could not open {}: {e}", dir.display());
+                }
+            }
+            Err(e) => log::error!("could not make the templates folder: {e:#}"),
+        }
+    }
+    fn on_new_folder(&self) {
+        self.create_in_focused_folder("making a new folder", templates::create_folder);
+    }
+    fn new_file(&self, index: usize) {
+        let templates = self.templates.borrow();
+        let Some(template) = templates.get(index) else {
+            return;
+        };
+        self.create_in_focused_folder(&format!("making a new {}", template.name), |folder| template.create(folder));
+    }
+    /// Makes something in the focused column's folder with `create`, then selects it
+    /// and starts editing its name
+    fn create_in_focused_folder(&self, action: &str, create: impl FnOnce(&std::path::Path) -> std::io::Result<PathBuf>) {
+        let mut folder = None;
+        self.with_focused_column(|column| {
+            if let Some(Folder::Shell { for_parsing, .. }) = &column.folder {
+                folder = Some(PathBuf::from(for_parsing.to_os_string()));
+            }
+        });
+        let Some(folder) = folder else {
+            nwg::modal_info_message(&self.window, "Stapler", "Click into a folder first, to make something new in it.");
+            return;
+        };
+        let created = match create(&folder) {
+            Ok(created) => created,
+            Err(e) => {
+                self.report(&ShellError::new(format!("{action} in {}", folder.display()), windows::core::Error::from(e).code()));
+                return;
+            }
+        };
+        log::info!("made {}", created.display());
+        let created = created.to_string_lossy().into_owned();
+        self.record(action, vec![journal::Step::Created { path: created.clone() }]);
+        self.with_focused_column(|column| column.refresh());
+        let is_created = |file: &File| matches!(file, File::Shell { for_parsing, .. } if for_parsing.to_string_lossy().eq_ignore_ascii_case(&created));
+        self.select_in_focused_column(|file, _| is_created(file));
+        self.with_focused_column(|column| {
+            if let Some(row) = column.children.iter().position(is_created) {
+                column.edit_name(row);
+            }
+        });
+    }
+    fn on_edit_name(&self) {
+        self.with_focused_column(|column| {
+            if let Some(row) = column.children.iter().position(|child| column.selection.contains(child)) {
+                column.edit_name(row);
+            }
+        });
+    }
+    /// Lets list views have their labels edited, and turns the edits into `Command::Rename`
+    fn bind_renames(&self) {
+        let pending_rename = self.pending_rename.clone();
+        let pending_command = self.pending_command.clone();
+        let command_notice = self.command_notice.sender();
+        let handler = nwg::bind_raw_event_handler(&self.window.handle, RENAME_HANDLER_ID, move |_hwnd, msg, _wparam, lparam| {
+            if msg != win32wam::WM_NOTIFY {
+                return None;
+            }
+            // Every notification comes through here, and only this one is an `NMLVDISPINFOW`
+            let header = unsafe { &*(lparam as *const win32controls::NMHDR) };
+            if header.code != win32controls::LVN_ENDLABELEDITW {
+                return None;
+            }
+            let info = unsafe { &*(lparam as *const win32controls::NMLVDISPINFOW) };
+            // No text means the edit was cancelled
+            if info.item.pszText.is_null() {
+                return None;
+            }
+            let name = unsafe { info.item.pszText.to_string() }.unwrap_or_default();
+            let row = TryInto::<usize>::try_into(info.item.iItem).unwrap();
+            *pending_rename.borrow_mut() = Some((info.hdr.hwndFrom.0 as usize, row, name));
+            pending_command.set(Some(Command::Rename));
+            command_notice.notice();
+            // Keeps the old label; the refresh after renaming shows the new one
+            Some(0)
+        });
+        match handler {
+            Ok(handler) => *self.rename_handler.borrow_mut() = Some(handler),
+            Err(e) => log::error!("could not bind the rename handler: {e}"),
+        }
+    }
+    fn rename(&self) {
+        let Some((list_view, row, name)) = self.pending_rename.borrow_mut().take() else {
+            return;
+        };
+        let name = name.trim();
+        let mut item = None;
+        for column in self.columns.borrow().iter() {
+            if column.list_view.handle.hwnd().unwrap() as usize == list_view {
+                if let Some(File::Shell { itemid, display, .. }) = column.children.get(row) {
+                    if display != name {
+                        item = Some((itemid.clone(), display.clone()));
+                    }
+                }
+            }
+        }
+        let Some((itemid, display)) = item.filter(|_| !name.is_empty()) else {
+            return;
+        };
+        let owner = HWND(self.window.handle.hwnd().unwrap() as *mut _);
+        let action = format!("renaming {display} to {name}");
+        let mut done = Vec::new();
+        let result = itemid
+            .shell_item()
+            .and_then(|item| file_operations::rename(owner, &item, name, &mut done))
+            .map_err(|e| ShellError::new(action.clone(), e.code()));
+        self.record(action, done);
+        if let Err(error) = result {
+            self.report(&error);
+            return;
+        }
+        log::info!("renamed {display} to {name}");
+        self.focused_list_view.set(list_view);
+        self.with_focused_column(|column| column.refresh());
+        self.select_in_focused_column(|file, _| matches!(file, File::Shell { display, .. } if display == name));
+    }
+    /// The parsing names of what the Selection menu would act on
+    fn selection_set_paths(&self) -> Vec<String> {
+        self.selection_set()
+            .into_iter()
+            .filter_map(|file| match file {
+                File::Shell { for_parsing, .. } => Some(for_parsing.to_string_lossy()),
+                File::Error(..) => None,
+            })
+            .collect()
+    }
+    fn on_save_set(&self) {
+        let paths = self.selection_set_paths();
+        if paths.is_empty() {
+            nwg::modal_info_message(&self.window, "Stapler", "Select some items first, to save them as a set.");
+            return;
+        }
+        *self.set_paths.borrow_mut() = paths;
+        self.set_name_input.set_text("");
+        self.set_name_window.set_visible(true);
+        self.set_name_input.set_focus();
+    }
+    fn on_set_name_window_close(&self, data: &nwg::EventData) {
+        if let nwg::EventData::OnWindowClose(data) = data {
+            data.close(false);
+        }
+        self.set_name_window.set_visible(false);
+    }
+    fn on_set_name_key(&self, data: &nwg::EventData) {
+        match data {
+            nwg::EventData::OnKey(0x0D) => self.on_set_name_ok(),
+            nwg::EventData::OnKey(0x1B) => self.set_name_window.set_visible(false),
+            _ => {}
+        }
+    }
+    fn on_set_name_ok(&self) {
+        let name = self.set_name_input.text().trim().to_owned();
+        let paths = self.set_paths.borrow().clone();
+        if selection_sets::names().iter().any(|other| other.eq_ignore_ascii_case(&name)) {
+            let question = format!("There's already a set called {name}. Replace it?");
+            let params = nwg::MessageParams {
+                title: "Stapler",
+                content: &question,
+                buttons: nwg::MessageButtons::YesNo,
+                icons: nwg::MessageIcons::Question,
+            };
+            if nwg::modal_message(&self.set_name_window, &params) != nwg::MessageChoice::Yes {
+                return;
+            }
+        }
+        match selection_sets::save(&name, &paths) {
+            Ok(()) => {
+                log::info!("saved {} items as the set {name}", paths.len());
+                self.set_name_window.set_visible(false);
+                self.fill_sets_menu();
+            }
+            // Left open, so the name can be fixed
+            Err(e) => {
+                log::error!("could not save the set {name}: {e:#}");
+                nwg::modal_error_message(&self.set_name_window, "Stapler", &format!("Could not save the set:\r\n{e:#}"));
+            }
+        }
+    }
+    fn open_set(&self, index: usize) {
+        let Some(name) = self.set_names.borrow().get(index).cloned() else {
+            return;
+        };
+        match selection_sets::load(&name) {
+            Ok(paths) => {
+                log::info!("opening the set {name}");
+                self.show_set(paths);
+            }
+            Err(e) => {
+                log::error!("could not open the set {name}: {e:#}");
+                nwg::modal_error_message(&self.window, "Stapler", &format!("Could not open the set {name}:\r\n{e:#}"));
+            }
+        }
+    }
+    /// Shows `paths` as a `Folder::Selection` in the second column, clearing
+    /// the selection in the first so nothing else claims that column
+    fn show_set(&self, paths: Vec<String>) {
+        let selection: HashSet<File> = paths
+            .iter()
+            .map(|path| {
+                ItemId::from_parsing_name(path)
+                    .map_err(|e| ShellError::new(format!("finding {path}"), e.code()))
+                    .and_then(|itemid| File::named(itemid, None))
+                    .unwrap_or_else(File::Error)
+            })
+            .collect();
+        if !selection.is_empty() {
+            self.show_in_second_column(Folder::Selection { selection });
+        }
+    }
+    /// Shows `folder`, which doesn't live anywhere in the chain, in the column after the
+    /// Desktop, clearing the Desktop's selection and everything after
+    fn show_in_second_column(&self, folder: Folder) {
         let mut columns = self.columns.borrow_mut();
-        let idx = TryInto::<usize>::try_into(i).unwrap();
-        columns[idx].switch(path);
+        if columns.len() < 2 {
+            return;
+        }
+        let first = &mut columns[0];
+        for (row, child) in first.children.iter().enumerate() {
+            if first.selection.contains(child) {
+                first.list_view.select_item(row, false);
+            }
+        }
+        first.selection.clear();
+        columns[1].switch(Some(folder));
+        for column in columns.iter_mut().skip(2) {
+            column.switch(None);
+        }
+        std::mem::drop(columns);
+        self.first_visible_column.set(0);
+        self.layout_notice.sender().notice();
     }
-
-    fn on_window_init(&self) {
-        self.window.set_visible(true);
-        let (sysobj, icon) = unsafe {
-            let sysobj = win32shell::SHGetDesktopFolder().unwrap();
-            let icon = match sysobj.cast::<win32shell::IPersistFolder2>().and_then(|ip| ip.GetCurFolder()) {
-                Ok(cur_folder) => {
-                    Some(win32shell::SHMapPIDLToSystemImageListIndex(
-                        &sysobj,
-                        cur_folder,
-                        None,
-                    ))
-                }
-                Err(_) => None,
+    fn delete_set(&self, index: usize) {
+        let Some(name) = self.set_names.borrow().get(index).cloned() else {
+            return;
+        };
+        let question = format!("Delete the saved set {name}? The items in it stay where they are.");
+        let params = nwg::MessageParams {
+            title: "Stapler",
+            content: &question,
+            buttons: nwg::MessageButtons::YesNo,
+            icons: nwg::MessageIcons::Question,
+        };
+        if nwg::modal_message(&self.window, &params) == nwg::MessageChoice::Yes {
+            match selection_sets::delete(&name) {
+                Ok(()) => self.fill_sets_menu(),
+                Err(e) => {
+                    log::error!("could not delete the set {name}: {e:#}");
+                    nwg::modal_error_message(&self.window, "Stapler", &format!("Could not delete the set {name}:\r\n{e:#}"));
+                }
+            }
+        }
+    }
+    fn on_export_set(&self) {
+        let paths = self.selection_set_paths();
+        if paths.is_empty() || !self.export_set_dialog.run(Some(&self.window)) {
+            return;
+        }
+        let Ok(file) = self.export_set_dialog.get_selected_item() else {
+            return;
+        };
+        let mut file = PathBuf::from(file);
+        if file.extension().is_none() {
+            file.set_extension("txt");
+        }
+        match selection_sets::write_list(&file, &paths) {
+            Ok(()) => log::info!("exported {} items to {}", paths.len(), file.display()),
+            Err(e) => {
+                log::error!("could not export to {}: {e:#}", file.display());
+                nwg::modal_error_message(&self.window, "Stapler", &format!("Could not export to {}:\r\n{e:#}", file.display()));
+            }
+        }
+    }
+    /// Saves a list someone else exported as a set of our own, and opens it
+    fn on_import_set(&self) {
+        if !self.import_set_dialog.run(Some(&self.window)) {
+            return;
+        }
+        let Ok(file) = self.import_set_dialog.get_selected_item() else {
+            return;
+        };
+        let file = PathBuf::from(file);
+        let name = file.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        // As when saving a set by name, rather than quietly losing the old one
+        if selection_sets::names().iter().any(|other| other.eq_ignore_ascii_case(&name)) {
+            let question = format!("There's already a set called {name}. Replace it?");
+            let params = nwg::MessageParams {
+                title: "Stapler",
+                content: &question,
+                buttons: nwg::MessageButtons::YesNo,
+                icons: nwg::MessageIcons::Question,
             };
-            (sysobj, icon)
-        };
-        let desktop = match sysobj.cast::<win32shell::IShellFolder>() {
-            Ok(sysobj) => Folder::Shell {
-                display: "Desktop".into(),
-                for_parsing: Vec::new(),
-                sysobj,
-                icon,
-            },
-            Err(e) => Folder::Error(format!("{e:?}")),
+            if nwg::modal_message(&self.window, &params) != nwg::MessageChoice::Yes {
+                return;
+            }
+        }
+        let result = selection_sets::read_list(&file).and_then(|paths| {
+            selection_sets::save(&name, &paths)?;
+            Ok(paths)
+        });
+        match result {
+            Ok(paths) => {
+                log::info!("imported {} items from {} as the set {name}", paths.len(), file.display());
+                self.fill_sets_menu();
+                self.show_set(paths);
+            }
+            Err(e) => {
+                log::error!("could not import {}: {e:#}", file.display());
+                nwg::modal_error_message(&self.window, "Stapler", &format!("Could not import {}:\r\n{e:#}", file.display()));
+            }
+        }
+    }
+    /// Indexes the folders in `index::roots_file` from scratch, stopping any earlier indexing
+    fn start_index(&self) {
+        *self.index.borrow_mut() = Some(index::Index::start(index::roots(), self.index_notice.sender()));
+    }
+    fn on_edit_index_roots(&self) {
+        match index::roots_file() {
+            Ok(path) => {
+                if let Err(e) = std::process::Command::new("notepad.exe").arg(&path).spawn() {
+                    log::error!("could not open {}: {e}", path.display());
+                }
+            }
+            Err(e) => log::error!("could not make the list of folders to index: {e:#}"),
+        }
+    }
+    fn on_goto(&self) {
+        self.goto_input.set_text("");
+        self.on_goto_input();
+        self.goto_window.set_visible(true);
+        self.goto_input.set_focus();
+    }
+    fn on_goto_window_close(&self, data: &nwg::EventData) {
+        if let nwg::EventData::OnWindowClose(data) = data {
+            data.close(false);
+        }
+        self.goto_window.set_visible(false);
+    }
+    /// Looks up what's been typed so far, with the hits coming back in `on_index_notice`
+    fn on_goto_input(&self) {
+        if let Some(index) = self.index.borrow().as_ref() {
+            index.find(&self.goto_input.text(), GOTO_HITS);
+        }
+    }
+    fn on_index_notice(&self) {
+        let index = self.index.borrow();
+        let Some(index) = index.as_ref() else {
+            return;
+        };
+        let Some(found) = index.found() else {
+            return;
+        };
+        let hits = found.hits.len();
+        self.goto_list.set_collection(found.hits);
+        self.goto_list.set_selection((hits > 0).then_some(0));
+        let status = format!("{} items indexed", found.indexed);
+        self.goto_status.set_text(&if index.walking() { format!("{status}, still indexing...") } else { status });
+    }
+    /// Moves through the hits without leaving the text box, and goes to one with Enter
+    fn on_goto_key(&self, data: &nwg::EventData) {
+        let nwg::EventData::OnKey(key) = data else {
+            return;
+        };
+        match key {
+            0x0D => self.on_goto_ok(),
+            0x1B => self.goto_window.set_visible(false),
+            _ => step_hits(&self.goto_list, *key),
+        }
+    }
+    fn on_goto_ok(&self) {
+        let Some(path) = self.goto_list.selection().and_then(|row| self.goto_list.collection().get(row).cloned()) else {
+            return;
+        };
+        self.goto_window.set_visible(false);
+        if let Err(error) = self.reveal(&path) {
+            self.report(&error);
+        }
+    }
+    fn on_jump(&self) {
+        self.jump_input.set_text("");
+        self.on_jump_input();
+        self.jump_window.set_visible(true);
+        self.jump_input.set_focus();
+    }
+    fn on_jump_window_close(&self, data: &nwg::EventData) {
+        if let nwg::EventData::OnWindowClose(data) = data {
+            data.close(false);
+        }
+        self.jump_window.set_visible(false);
+    }
+    fn on_jump_input(&self) {
+        let mut found = self.frecency.borrow().find(&self.jump_input.text());
+        found.truncate(RECENT_FOLDERS);
+        let any = !found.is_empty();
+        self.jump_list.set_collection(found);
+        self.jump_list.set_selection(any.then_some(0));
+    }
+    fn on_jump_key(&self, data: &nwg::EventData) {
+        let nwg::EventData::OnKey(key) = data else {
+            return;
+        };
+        match key {
+            0x0D => self.on_jump_ok(),
+            0x1B => self.jump_window.set_visible(false),
+            _ => step_hits(&self.jump_list, *key),
+        }
+    }
+    fn on_jump_ok(&self) {
+        let Some(path) = self.jump_list.selection().and_then(|row| self.jump_list.collection().get(row).cloned()) else {
+            return;
+        };
+        self.jump_window.set_visible(false);
+        if let Err(error) = self.reveal(&path) {
+            self.report(&error);
+        }
+    }
+    /// Goes to the likeliest folder for `query`, as `--jump` does
+    fn jump(&self, query: &str) {
+        let Some(path) = self.jump_target(query) else {
+            return;
+        };
+        if let Err(error) = self.reveal(&path) {
+            self.report(&error);
+        }
+    }
+    /// The likeliest folder for `query` that's still there, after telling the user if there's none
+    fn jump_target(&self, query: &str) -> Option<String> {
+        let found = self.frecency.borrow().find(query);
+        let mut best = None;
+        for path in found {
+            if ItemId::from_parsing_name(&path).is_ok() {
+                best = Some(path);
+                break;
+            }
+            self.frecency.borrow_mut().forget(&path);
+        }
+        let Some(path) = best else {
+            nwg::modal_info_message(&self.window, "Stapler", &format!("No folder you've been to matches {query:?}."));
+            return None;
+        };
+        Some(path)
+    }
+    /// Shows `Folder::Recent` in the second column
+    fn on_recent_folders(&self) {
+        self.show_in_second_column(Folder::Recent);
+    }
+    /// Makes `StaplerApp::tab_strip`. nwg's `TabsContainer` would hide and show `Tab` pages
+    /// as the selection changes, but the columns aren't on any.
+    fn init_tab_strip(&self) {
+        let built = nwg::ControlBase::build_hwnd()
+            .class_name("SysTabControl32")
+            .flags((win32wam::WS_CHILD | win32wam::WS_VISIBLE | win32wam::WS_CLIPSIBLINGS).0 | win32controls::TCS_FOCUSNEVER)
+            .size((DEFAULT_WIDTH, TAB_STRIP_HEIGHT))
+            .position((0, 0))
+            .parent(Some(self.window.handle))
+            .build();
+        match built {
+            Ok(handle) => self.tab_strip.set(handle),
+            Err(e) => {
+                log::error!("could not make the tab strip: {e}");
+                return;
+            }
+        }
+        if let Some(font) = nwg::Font::global_default() {
+            unsafe { win32wam::SendMessageW(self.tab_strip_hwnd(), win32wam::WM_SETFONT, WPARAM(font.handle as usize), LPARAM(1)) };
+        }
+    }
+    fn tab_strip_hwnd(&self) -> HWND {
+        HWND(self.tab_strip.get().hwnd().map_or(std::ptr::null_mut(), |hwnd| hwnd as *mut _))
+    }
+    fn insert_tab_item(&self, index: usize, title: &str) {
+        let title = HSTRING::from(title);
+        let item = win32controls::TCITEMW {
+            mask: win32controls::TCIF_TEXT,
+            pszText: PWSTR(title.as_ptr() as *mut _),
+            ..Default::default()
+        };
+        unsafe { win32wam::SendMessageW(self.tab_strip_hwnd(), win32controls::TCM_INSERTITEMW, WPARAM(index), LPARAM(&item as *const _ as isize)) };
+    }
+    fn set_tab_item(&self, index: usize, title: &str) {
+        let title = HSTRING::from(title);
+        let item = win32controls::TCITEMW {
+            mask: win32controls::TCIF_TEXT,
+            pszText: PWSTR(title.as_ptr() as *mut _),
+            ..Default::default()
+        };
+        unsafe { win32wam::SendMessageW(self.tab_strip_hwnd(), win32controls::TCM_SETITEMW, WPARAM(index), LPARAM(&item as *const _ as isize)) };
+    }
+    /// The tab the strip shows as selected, which the user may just have clicked
+    fn selected_tab_item(&self) -> usize {
+        let selected = unsafe { win32wam::SendMessageW(self.tab_strip_hwnd(), win32controls::TCM_GETCURSEL, WPARAM(0), LPARAM(0)) };
+        TryInto::<usize>::try_into(selected.0).unwrap_or(self.active_tab.get())
+    }
+    /// Follows clicks in the tab strip: picking a tab, dragging one to somewhere
+    /// else in the strip, or middle clicking to close it
+    fn bind_tabs(&self) {
+        let tab_strip = self.tab_strip_hwnd().0 as usize;
+        let pending_command = self.pending_command.clone();
+        let command_notice = self.command_notice.sender();
+        let switches = nwg::bind_raw_event_handler(&self.window.handle, TAB_HANDLER_ID, move |_hwnd, msg, _wparam, lparam| {
+            if msg != win32wam::WM_NOTIFY {
+                return None;
+            }
+            let header = unsafe { &*(lparam as *const win32controls::NMHDR) };
+            if header.code == win32controls::TCN_SELCHANGE && header.hwndFrom.0 as usize == tab_strip {
+                pending_command.set(Some(Command::SwitchTab));
+                command_notice.notice();
+            }
+            None
+        });
+        let pending_command = self.pending_command.clone();
+        let command_notice = self.command_notice.sender();
+        // The tab the left button went down on
+        let dragging: Cell<Option<usize>> = Cell::new(None);
+        let clicks = nwg::bind_raw_event_handler(&self.tab_strip.get(), TAB_HANDLER_ID, move |hwnd, msg, _wparam, lparam| {
+            let mut hit = win32controls::TCHITTESTINFO {
+                pt: POINT { x: (lparam & 0xFFFF) as i16 as i32, y: ((lparam >> 16) & 0xFFFF) as i16 as i32 },
+                ..Default::default()
+            };
+            let mut tab_at = || {
+                let index = unsafe { win32wam::SendMessageW(HWND(hwnd as *mut _), win32controls::TCM_HITTEST, WPARAM(0), LPARAM(&mut hit as *mut _ as isize)) };
+                TryInto::<usize>::try_into(index.0).ok()
+            };
+            let command = match msg {
+                win32wam::WM_LBUTTONDOWN => {
+                    dragging.set(tab_at());
+                    None
+                }
+                win32wam::WM_LBUTTONUP => match (dragging.take(), tab_at()) {
+                    (Some(from), Some(to)) if from != to => Some(Command::MoveTab { from, to }),
+                    _ => None,
+                },
+                win32wam::WM_MBUTTONUP => tab_at().map(|index| Command::CloseTab(Some(index))),
+                _ => None,
+            };
+            if let Some(command) = command {
+                pending_command.set(Some(command));
+                command_notice.notice();
+            }
+            None
+        });
+        for handler in [switches, clicks] {
+            match handler {
+                Ok(handler) => self.tab_handlers.borrow_mut().push(handler),
+                Err(e) => log::error!("could not bind the tab handler: {e}"),
+            }
+        }
+    }
+    /// Parks the active tab's chain, hidden, and brings out the one at `index`
+    fn activate_tab(&self, index: usize) {
+        let active = self.active_tab.get();
+        let mut tabs = self.tabs.borrow_mut();
+        if index == active || index >= tabs.len() {
+            return;
+        }
+        let mut columns = self.columns.borrow_mut();
+        for column in columns.iter_mut() {
+            column.in_view = false;
+            column.update_visibility();
+        }
+        tabs[active].columns = std::mem::take(&mut *columns);
+        tabs[active].first_visible_column = self.first_visible_column.get();
+        *columns = std::mem::take(&mut tabs[index].columns);
+        self.first_visible_column.set(tabs[index].first_visible_column);
+        // Searches carried on while they were parked
+        for column in columns.iter_mut() {
+            column.add_search_hits();
+        }
+        let focused = columns.iter().rev().find(|column| column.folder.is_some()).map(|column| column.list_view.handle);
+        std::mem::drop(columns);
+        std::mem::drop(tabs);
+        self.active_tab.set(index);
+        unsafe { win32wam::SendMessageW(self.tab_strip_hwnd(), win32controls::TCM_SETCURSEL, WPARAM(index), LPARAM(0)) };
+        self.reconcile_columns();
+        if let Some(list_view) = focused {
+            self.focused_list_view.set(list_view.hwnd().unwrap() as usize);
+            let _ = unsafe { win32input::SetFocus(HWND(list_view.hwnd().unwrap() as *mut _)) };
+        }
+        self.session_changed();
+    }
+    /// Opens a new tab after the others and shows it, revealing `path` in it if there is one
+    fn open_tab(&self, path: Option<&str>) {
+        let index = {
+            let mut tabs = self.tabs.borrow_mut();
+            tabs.push(Tab::default());
+            tabs.len() - 1
+        };
+        self.insert_tab_item(index, "Desktop");
+        self.activate_tab(index);
+        self.switch_column(0, desktop());
+        self.reconcile_columns();
+        if let Some(path) = path {
+            if let Err(error) = self.reveal(path) {
+                self.report(&error);
+            }
+        }
+        self.on_layout_notice();
+    }
+    /// Opens a tab on the focused column's folder
+    fn on_new_tab(&self) {
+        let focused = self.focused_list_view.get();
+        let path = self
+            .columns
+            .borrow()
+            .iter()
+            .find(|column| column.list_view.handle.hwnd().unwrap() as usize == focused)
+            .and_then(|column| match &column.folder {
+                Some(Folder::Shell { for_parsing, .. }) => Some(for_parsing.to_string_lossy()),
+                _ => None,
+            });
+        self.open_tab(path.as_deref());
+    }
+    fn on_close_tab(&self) {
+        self.close_tab(self.active_tab.get());
+    }
+    /// Closes the tab at `index`, unless it's the only one
+    fn close_tab(&self, index: usize) {
+        let count = self.tabs.borrow().len();
+        if count < 2 || index >= count {
+            return;
+        }
+        let active = self.active_tab.get();
+        if index == active {
+            self.activate_tab(if index + 1 < count { index + 1 } else { index - 1 });
+        }
+        let closed = self.tabs.borrow_mut().remove(index);
+        for column in closed.columns {
+            self.destroy_column(column);
+        }
+        unsafe { win32wam::SendMessageW(self.tab_strip_hwnd(), win32controls::TCM_DELETEITEM, WPARAM(index), LPARAM(0)) };
+        if self.active_tab.get() > index {
+            self.active_tab.set(self.active_tab.get() - 1);
+        }
+        self.session_changed();
+    }
+    /// Moves the tab at `from` to `to`, keeping whichever was active active
+    fn move_tab(&self, from: usize, to: usize) {
+        let mut tabs = self.tabs.borrow_mut();
+        if from >= tabs.len() || to >= tabs.len() {
+            return;
+        }
+        let tab = tabs.remove(from);
+        tabs.insert(to, tab);
+        let active = self.active_tab.get();
+        let active = if active == from {
+            to
+        } else if from < active && active <= to {
+            active - 1
+        } else if to <= active && active < from {
+            active + 1
+        } else {
+            active
+        };
+        self.active_tab.set(active);
+        // Every name in between has shifted along one
+        let titles: Vec<String> = tabs
+            .iter()
+            .enumerate()
+            .map(|(index, tab)| if index == active { tab_title(&self.columns.borrow()) } else { tab_title(&tab.columns) })
+            .collect();
+        std::mem::drop(tabs);
+        for (index, title) in titles.iter().enumerate() {
+            self.set_tab_item(index, title);
+        }
+        unsafe { win32wam::SendMessageW(self.tab_strip_hwnd(), win32controls::TCM_SETCURSEL, WPARAM(active), LPARAM(0)) };
+        self.session_changed();
+    }
+    /// Opens the folder at `row` of the focused column in a tab of its own
+    fn open_in_new_tab(&self, row: usize) {
+        let focused = self.focused_list_view.get();
+        let file = self
+            .columns
+            .borrow()
+            .iter()
+            .find(|column| column.list_view.handle.hwnd().unwrap() as usize == focused)
+            .and_then(|column| column.children.get(row).cloned());
+        let Some(File::Shell { for_parsing, .. }) = file.filter(|file| file.is_folder()) else {
+            return;
+        };
+        self.open_tab(Some(&for_parsing.to_string_lossy()));
+    }
+    /// Saves the tabs as they are now, so they aren't lost if stapler doesn't get to close
+    fn session_changed(&self) {
+        // Picking shouldn't lose the tabs the user had open
+        if self.session_restored.get() && self.pick.get().is_none() {
+            self.save_session();
+        }
+    }
+    fn save_session(&self) {
+        let tabs = self.tabs.borrow();
+        let active = self.active_tab.get();
+        let tabs = tabs
+            .iter()
+            .enumerate()
+            .map(|(index, tab)| if index == active { chain_path(&self.columns.borrow()) } else { chain_path(&tab.columns) })
+            .collect();
+        session::save(&session::Session { tabs, active });
+    }
+    /// Opens the tabs there were when stapler last closed, if there were any
+    fn restore_session(&self) -> bool {
+        let Some(session) = session::load() else {
+            return false;
+        };
+        log::info!("restoring {} tabs", session.tabs.len());
+        for (index, path) in session.tabs.iter().enumerate() {
+            if index > 0 {
+                self.open_tab(None);
+            }
+            if let Some(path) = path {
+                if let Err(error) = self.reveal(path) {
+                    log::warn!("could not reopen {path}: {error}");
+                }
+            }
+        }
+        self.activate_tab(session.active.min(session.tabs.len() - 1));
+        self.on_layout_notice();
+        true
+    }
+    /// Shows `path` as clicking down to it from the Desktop would have, with it
+    /// and every folder on the way selected in their columns
+    fn reveal(&self, path: &str) -> Result<(), ShellError> {
+        let item = ItemId::from_parsing_name(path)
+            .and_then(|itemid| itemid.shell_item())
+            .map_err(|e| ShellError::new(format!("finding {path}"), e.code()))?;
+        // The Desktop has no parent, and is always in the first column
+        let mut chain = Vec::new();
+        let mut next = Some(item);
+        while let Some(item) = next {
+            next = unsafe { item.GetParent() }.ok();
+            if next.is_some() {
+                chain.push(File::from_item(&item)?);
+            }
+        }
+        chain.reverse();
+        log::info!("revealing {path}, {} levels down", chain.len());
+        let mut revealed = None;
+        for (index, file) in chain.into_iter().enumerate() {
+            let mut columns = self.columns.borrow_mut();
+            let column = &mut columns[index];
+            let Some(row) = column.children.iter().position(|child| *child == file) else {
+                // Hidden, most likely, so it can only be shown as far as the folder it's in
+                log::warn!("could not find {path} in the listing of {}", file.parent_path());
+                break;
+            };
+            for (other, child) in column.children.iter().enumerate() {
+                if column.selection.contains(child) {
+                    column.list_view.select_item(other, false);
+                }
+            }
+            column.list_view.select_item(row, true);
+            column.scroll_to(row);
+            column.selection = HashSet::from([file]);
+            revealed = Some(column.list_view.handle);
+            cascade_selection(&mut columns, index);
+            std::mem::drop(columns);
+            // So there's a column after this one to look in next
+            self.reconcile_columns();
+        }
+        if let Some(list_view) = revealed {
+            self.focused_list_view.set(list_view.hwnd().unwrap() as usize);
+            let _ = unsafe { win32input::SetFocus(HWND(list_view.hwnd().unwrap() as *mut _)) };
+        }
+        self.on_layout_notice();
+        Ok(())
+    }
+    fn on_paste_as_files_toggle(&self) {
+        self.paste_as_files_item.set_checked(!self.paste_as_files_item.checked());
+    }
+    /// Tells the user about something they asked for that failed
+    fn report(&self, error: &ShellError) {
+        if error.is_cancelled() {
+            return;
+        }
+        nwg::modal_error_message(&self.window, "Stapler", &error.to_string());
+    }
+    fn on_view_details(&self) {
+        self.with_focused_column(|column| column.set_view_mode(ViewMode::Details));
+    }
+    fn on_view_thumbnails(&self) {
+        self.with_focused_column(|column| column.set_view_mode(ViewMode::Thumbnails));
+    }
+    /// Scrolls the chain so its end is in view, after it gained or lost columns
+    fn on_layout_notice(&self) {
+        self.first_visible_column.set(usize::MAX);
+        self.reconcile_columns();
+        let title = tab_title(&self.columns.borrow());
+        self.set_tab_item(self.active_tab.get(), &title);
+        self.publish_navigation(&title);
+        self.record_navigation();
+    }
+    /// Adds where the active tab was to its Back list, if it's gone to another folder since
+    fn record_navigation(&self) {
+        let folder = chain_folder(&self.columns.borrow());
+        let mut tabs = self.tabs.borrow_mut();
+        let Some(tab) = tabs.get_mut(self.active_tab.get()) else {
+            return;
+        };
+        if tab.shown == folder {
+            return;
+        }
+        let left = std::mem::replace(&mut tab.shown, folder);
+        tab.back.push(left);
+        if tab.back.len() > TAB_HISTORY {
+            tab.back.remove(0);
+        }
+        tab.forward.clear();
+        std::mem::drop(tabs);
+        self.session_changed();
+    }
+    fn on_back(&self) {
+        self.go_through_history(true);
+    }
+    fn on_forward(&self) {
+        self.go_through_history(false);
+    }
+    /// Takes the active tab to the folder before the one it's showing, or after
+    fn go_through_history(&self, back: bool) {
+        let mut tabs = self.tabs.borrow_mut();
+        let Some(tab) = tabs.get_mut(self.active_tab.get()) else {
+            return;
+        };
+        let (from, to) = if back { (&mut tab.back, &mut tab.forward) } else { (&mut tab.forward, &mut tab.back) };
+        let Some(folder) = from.pop() else {
+            return;
+        };
+        // Already where it's going, so `record_navigation` leaves the lists be
+        to.push(std::mem::replace(&mut tab.shown, folder.clone()));
+        std::mem::drop(tabs);
+        match folder {
+            Some(folder) => {
+                if let Err(error) = self.reveal(&folder) {
+                    self.report(&error);
+                }
+            }
+            None => self.show_desktop(),
+        }
+    }
+    /// Empties the first column's selection, so there's only the Desktop
+    fn show_desktop(&self) {
+        let mut columns = self.columns.borrow_mut();
+        let Some(column) = columns.front_mut() else {
+            return;
         };
-        self.switch_column(0, Some(desktop));
+        for (row, child) in column.children.iter().enumerate() {
+            if column.selection.contains(child) {
+                column.list_view.select_item(row, false);
+            }
+        }
+        column.selection.clear();
+        cascade_selection(&mut columns, 0);
+        std::mem::drop(columns);
+        self.on_layout_notice();
+    }
+    /// A splitter moved, so everything to its right has to as well
+    fn on_resize_notice(&self) {
+        self.layout_columns();
+    }
+    fn on_widths_per_folder_toggle(&self) {
+        let per_folder = !self.widths.borrow().per_folder();
+        self.widths.borrow_mut().set_per_folder(per_folder);
+        self.view_widths_per_folder_item.set_checked(per_folder);
+    }
+    fn on_column_scroll(&self) {
+        self.first_visible_column.set(self.column_scroll_bar.pos());
+        self.layout_columns();
     }
     fn on_window_close(&self) {
-        self.reconcile_columns(0);
+        // Picking shouldn't lose the tabs the user had open
+        if self.pick.get().is_none() {
+            self.save_session();
+        }
+        self.frecency.borrow_mut().flush();
+        for handler in [&self.splitter_handler, &self.shelf_drag_handler, &self.rename_handler] {
+            if let Some(handler) = handler.borrow_mut().take() {
+                let _ = nwg::unbind_raw_event_handler(&handler);
+            }
+        }
+        for handler in self.tab_handlers.borrow_mut().drain(..) {
+            let _ = nwg::unbind_raw_event_handler(&handler);
+        }
+        let tabs = std::mem::take(&mut *self.tabs.borrow_mut());
+        for column in tabs.into_iter().flat_map(|tab| tab.columns) {
+            self.destroy_column(column);
+        }
+        if let Some(handler) = self.dynamic_menu_handler.borrow_mut().take() {
+            nwg::unbind_event_handler(&handler);
+        }
+        let columns = std::mem::take(&mut *self.columns.borrow_mut());
+        for column in columns {
+            self.destroy_column(column);
+        }
         nwg::stop_thread_dispatch();
     }
     fn on_window_size(&self) {
@@ -531,14 +4878,159 @@ impl StaplerApp {
                 };
             }
         }
-        let count = calculate_column_count(TryInto::<i32>::try_into(self.window.size().0).unwrap());
-        self.reconcile_columns(count);
+        self.reconcile_columns();
+    }
+}
+
+/// Command line options
+struct Args {
+    log_level: log::LevelFilter,
+    /// Words to find a visited folder by, to go to at start
+    jump: Option<String>,
+    /// A file or folder to reveal at start, made absolute if it's in the file system
+    path: Option<String>,
+    /// Serve the control API, see `control`
+    control: bool,
+    pick: Option<Pick>,
+    /// Start another instance instead of handing `path` or `jump` to the running one
+    new_instance: bool,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = OsString>) -> Result<Args> {
+        let mut parsed = Args {
+            log_level: log::LevelFilter::Info,
+            jump: None,
+            path: None,
+            new_instance: false,
+            control: false,
+            pick: None,
+        };
+        let (mut multiple, mut dirs, mut null) = (false, false, false);
+        while let Some(arg) = args.next() {
+            match arg.to_str() {
+                Some("--log-level") => {
+                    let level = args.next().context("--log-level needs one of off, error, warn, info, debug, trace")?;
+                    parsed.log_level = level.to_string_lossy().parse().with_context(|| format!("bad log level {}", level.display()))?;
+                }
+                Some("--jump") => {
+                    let query = args.next().context("--jump needs words from the folder's path")?;
+                    parsed.jump = Some(query.to_string_lossy().into_owned());
+                }
+                Some("--new-instance") => parsed.new_instance = true,
+                Some("--control") => parsed.control = true,
+                Some("--pick") => parsed.pick = Some(Pick::default()),
+                Some("--multiple") => multiple = true,
+                Some("--dirs") => dirs = true,
+                Some("--null") => null = true,
+                _ if !arg.to_string_lossy().starts_with("--") && parsed.path.is_none() => {
+                    let path = arg.to_string_lossy().into_owned();
+                    // Relative to where this launch was started, which the running instance wouldn't know
+                    parsed.path = Some(if is_file_system_path(&path) {
+                        std::path::absolute(&arg).with_context(|| format!("bad path {}", arg.display()))?.to_string_lossy().into_owned()
+                    } else {
+                        path
+                    });
+                }
+                _ => bail!("unknown argument {}", arg.display()),
+            }
+        }
+        match &mut parsed.pick {
+            Some(pick) => *pick = Pick { multiple, dirs, null },
+            None if multiple || dirs || null => bail!("--multiple, --dirs and --null only go with --pick"),
+            None => {}
+        }
+        Ok(parsed)
+    }
+    /// What to ask the running instance for instead of starting another
+    fn requests(&self) -> Vec<instance::Request> {
+        let mut requests = Vec::new();
+        if let Some(query) = &self.jump {
+            requests.push(instance::Request::Jump(query.clone()));
+        }
+        if let Some(path) = &self.path {
+            requests.push(instance::Request::Reveal(path.clone()));
+        }
+        if requests.is_empty() {
+            requests.push(instance::Request::Show);
+        }
+        requests
+    }
+}
+
+/// Whether `arg` is a file system path, rather than a shell parsing name like
+/// `::{20D04FE0-3AEA-1069-A2D8-08002B30309D}` or `shell:Downloads` that `std::path::absolute` would mangle
+fn is_file_system_path(arg: &str) -> bool {
+    if arg.starts_with("::") {
+        return false;
     }
+    // A colon anywhere but after a drive letter is some other namespace's
+    match arg.find(':') {
+        None => true,
+        Some(1) => arg.as_bytes()[0].is_ascii_alphabetic(),
+        Some(_) => false,
+    }
+}
+
+/// How `--pick` was asked to pick
+#[derive(Clone, Copy, Debug, Default)]
+struct Pick {
+    /// Allow more than one
+    multiple: bool,
+    /// Folders rather than files
+    dirs: bool,
+    /// Print the paths ended by NULs rather than newlines, for paths with newlines in
+    null: bool,
 }
 
 fn main() {
+    let args = match Args::parse(std::env::args_os().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e:#}");
+            std::process::exit(2);
+        }
+    };
+    logging::init(args.log_level);
+    // A pick is answered by this process, whatever else is running, and control
+    // clients want the stapler they started, not one that was already there
+    let claim = if args.new_instance || args.pick.is_some() || args.control {
+        None
+    } else {
+        let claim = instance::claim();
+        // Another instance has it, so it does this launch's work. If it won't, start anyway.
+        if claim.is_none() && instance::send(&args.requests()) {
+            return;
+        }
+        claim
+    };
+    // The clipboard wants OLE, not just COM
+    if let Err(e) = unsafe { OleInitialize(None) } {
+        log::error!("could not start OLE: {}", e.message().trim());
+    }
     nwg::init().unwrap();
     let _ = nwg::Font::set_global_family("Segoe UI");
-    let _app = StaplerApp::build_ui(StaplerApp::default()).unwrap();
+    let app = StaplerApp {
+        startup_jump: RefCell::new(args.jump),
+        startup_path: RefCell::new(args.path),
+        instance_claim: RefCell::new(claim),
+        serve_control: Cell::new(args.control),
+        pick: Cell::new(args.pick),
+        ..StaplerApp::default()
+    };
+    let app = StaplerApp::build_ui(app).unwrap();
     nwg::dispatch_thread_events();
+    if let Some(pick) = args.pick {
+        // Cancelled, or closed without picking
+        let Some(paths) = app.picked.take() else {
+            std::process::exit(1);
+        };
+        let end = if pick.null { "\0" } else { "\n" };
+        let text: String = paths.iter().map(|path| format!("{path}{end}")).collect();
+        let mut stdout = std::io::stdout().lock();
+        if let Err(e) = stdout.write_all(text.as_bytes()).and_then(|()| stdout.flush()) {
+            log::error!("could not print what was picked: {e}");
+            std::process::exit(3);
+        }
+    }
 }
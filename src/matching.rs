@@ -0,0 +1,264 @@
+//! What "Select matching" picks out of a column, and what a search looks for:
+//! a glob or regex on the name, narrowed by type, size and how recently it was modified.
+//! Also the looser, scored matching behind Go to anything.
+
+use std::time::{Duration, SystemTime};
+
+pub enum NamePattern {
+    /// `*.jpg;*.png`, matched without case
+    Glob(Vec<String>),
+    Regex(regex::Regex),
+}
+
+impl NamePattern {
+    /// Reads what was typed into the name box; an empty box matches everything
+    pub fn parse(text: &str, regex: bool) -> anyhow::Result<Option<NamePattern>> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(None);
+        }
+        if regex {
+            let regex = regex::RegexBuilder::new(text).case_insensitive(true).build()?;
+            return Ok(Some(NamePattern::Regex(regex)));
+        }
+        let globs = text.split(';').map(str::trim).filter(|glob| !glob.is_empty()).map(str::to_lowercase).collect();
+        Ok(Some(NamePattern::Glob(globs)))
+    }
+    /// What a search looks for: a part without wildcards can be anywhere in the
+    /// name, so `report` finds `Q3 report.docx`
+    pub fn anywhere(self) -> NamePattern {
+        match self {
+            NamePattern::Glob(globs) => NamePattern::Glob(
+                globs.into_iter().map(|glob| if glob.contains(['*', '?', '[']) { glob } else { format!("*{glob}*") }).collect(),
+            ),
+            regex => regex,
+        }
+    }
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Glob(globs) => {
+                let name: Vec<char> = name.to_lowercase().chars().collect();
+                globs.iter().any(|glob| glob_matches(&glob.chars().collect::<Vec<_>>(), &name))
+            }
+            NamePattern::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    Anything,
+    Files,
+    Folders,
+}
+
+pub struct Criteria {
+    pub name: Option<NamePattern>,
+    pub kind: Kind,
+    /// In bytes. Folders never match a size bound.
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub modified_within: Option<Duration>,
+}
+
+impl Criteria {
+    /// `size` and `modified` are `None` for anything not in the file system
+    pub fn matches(&self, name: &str, is_folder: bool, size: Option<u64>, modified: Option<SystemTime>) -> bool {
+        let kind = match self.kind {
+            Kind::Anything => true,
+            Kind::Files => !is_folder,
+            Kind::Folders => is_folder,
+        };
+        let size_ok = |bound: Option<u64>, ok: fn(u64, u64) -> bool| bound.is_none_or(|bound| size.is_some_and(|size| ok(size, bound)));
+        let recent = self.modified_within.is_none_or(|within| {
+            modified.and_then(|modified| modified.elapsed().ok()).is_some_and(|age| age <= within)
+        });
+        kind
+            && size_ok(self.min_size, |size, min| size >= min)
+            && size_ok(self.max_size, |size, max| size <= max)
+            && recent
+            && self.name.as_ref().is_none_or(|pattern| pattern.matches(name))
+    }
+}
+
+/// How well `term`, in lower case, matches the item `name` in `folder`: its characters
+/// have to appear in order, best of all in the name, with runs of them and the starts
+/// of words counting for more. `None` if it doesn't match at all.
+pub fn fuzzy_score(term: &[char], folder: &str, name: &str) -> Option<i32> {
+    if let Some(score) = subsequence_score(term, name.chars()) {
+        // Shorter names are likelier to be what was meant
+        return Some(100 + score * 2 - TryInto::<i32>::try_into(name.len().min(100)).unwrap() / 4);
+    }
+    subsequence_score(term, folder.chars().chain(std::iter::once('\\')).chain(name.chars()))
+}
+
+fn subsequence_score(term: &[char], text: impl Iterator<Item = char>) -> Option<i32> {
+    let mut wanted = term.iter().peekable();
+    let mut score = 0;
+    let mut run = 0;
+    let mut previous: Option<char> = None;
+    for c in text {
+        let Some(&&next) = wanted.peek() else {
+            break;
+        };
+        if c.to_lowercase().eq(std::iter::once(next)) {
+            wanted.next();
+            run += 1;
+            score += run;
+            let word_start = previous.is_none_or(|previous| !previous.is_alphanumeric() || (previous.is_lowercase() && c.is_uppercase()));
+            if word_start {
+                score += 3;
+            }
+        } else {
+            run = 0;
+        }
+        previous = Some(c);
+    }
+    wanted.peek().is_none().then_some(score)
+}
+
+/// `*`, `?` and `[a-z]` or `[!abc]` classes, over the whole of `text`. Only the
+/// latest `*` is ever gone back to, so a glob with many of them stays quick.
+fn glob_matches(glob: &[char], text: &[char]) -> bool {
+    let (mut g, mut t) = (0, 0);
+    // Just after the latest `*`, and where in `text` it has matched up to so far
+    let mut star = None;
+    while t < text.len() {
+        if glob.get(g) == Some(&'*') {
+            g += 1;
+            star = Some((g, t));
+        } else if let Some(taken) = one_matches(&glob[g..], text[t]) {
+            g += taken;
+            t += 1;
+        } else if let Some((after_star, matched)) = star {
+            // Let the `*` take one more character, and try the rest again from there
+            g = after_star;
+            t = matched + 1;
+            star = Some((after_star, t));
+        } else {
+            return false;
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+/// How much of the start of `glob` matched `c`, if it did: one character, or a whole class
+fn one_matches(glob: &[char], c: char) -> Option<usize> {
+    match *glob.first()? {
+        '*' => None,
+        '?' => Some(1),
+        '[' => {
+            let Some(close) = glob.iter().skip(2).position(|&c| c == ']').map(|i| i + 2) else {
+                // No closing bracket, so it's just a character
+                return (c == '[').then_some(1);
+            };
+            let (negated, class) = match glob[1] {
+                '!' => (true, &glob[2..close]),
+                _ => (false, &glob[1..close]),
+            };
+            let mut in_class = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    in_class |= class[i] <= c && c <= class[i + 2];
+                    i += 3;
+                } else {
+                    in_class |= class[i] == c;
+                    i += 1;
+                }
+            }
+            (in_class != negated).then_some(close + 1)
+        }
+        g => (g == c).then_some(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(glob: &str, text: &str) -> bool {
+        glob_matches(&glob.chars().collect::<Vec<_>>(), &text.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob("*.jpg", "holiday.jpg"));
+        assert!(!glob("*.jpg", "holiday.jpg.txt"));
+        assert!(glob("a*b*c", "aXbYc"));
+        assert!(glob("a*b*c", "abcbc"));
+        assert!(!glob("a*b*c", "acb"));
+        assert!(glob("?at", "cat"));
+        assert!(!glob("?at", "at"));
+        assert!(glob("*", ""));
+        assert!(glob("**", "anything"));
+        assert!(!glob("", "x"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(glob("img[0-9].png", "img7.png"));
+        assert!(!glob("img[0-9].png", "imgx.png"));
+        assert!(glob("[!a]*", "banana"));
+        assert!(!glob("[!a]*", "apple"));
+        assert!(glob("[]x]", "]"));
+        // An unclosed bracket is just a bracket
+        assert!(glob("[ab", "[ab"));
+    }
+
+    #[test]
+    fn many_stars_stay_quick() {
+        let text = "a".repeat(200);
+        assert!(!glob(&format!("{}b", "*a".repeat(30)), &text));
+    }
+
+    #[test]
+    fn patterns() {
+        let pattern = NamePattern::parse(" *.JPG ; *.png ", false).unwrap().unwrap();
+        assert!(pattern.matches("Photo.jpg"));
+        assert!(pattern.matches("shot.PNG"));
+        assert!(!pattern.matches("notes.txt"));
+        assert!(NamePattern::parse("  ", false).unwrap().is_none());
+        let pattern = NamePattern::parse(r"^\d+\.txt$", true).unwrap().unwrap();
+        assert!(pattern.matches("42.TXT"));
+        assert!(!pattern.matches("x42.txt"));
+        assert!(NamePattern::parse("(", true).is_err());
+    }
+
+    #[test]
+    fn plain_text_is_exact_unless_searching() {
+        let pattern = NamePattern::parse("report; *.txt", false).unwrap().unwrap();
+        assert!(pattern.matches("Report"));
+        assert!(!pattern.matches("Q3 report.docx"));
+        let pattern = pattern.anywhere();
+        assert!(pattern.matches("Q3 report.docx"));
+        assert!(pattern.matches("notes.txt"));
+        assert!(!pattern.matches("notes.txt.bak"));
+    }
+
+    #[test]
+    fn criteria() {
+        let criteria = Criteria {
+            name: None,
+            kind: Kind::Files,
+            min_size: Some(10),
+            max_size: Some(100),
+            modified_within: Some(Duration::from_secs(60)),
+        };
+        let now = Some(SystemTime::now());
+        assert!(criteria.matches("a", false, Some(50), now));
+        assert!(!criteria.matches("a", true, Some(50), now));
+        assert!(!criteria.matches("a", false, Some(5), now));
+        assert!(!criteria.matches("a", false, None, now));
+        assert!(!criteria.matches("a", false, Some(50), Some(SystemTime::now() - Duration::from_secs(3600))));
+    }
+
+    #[test]
+    fn fuzzy() {
+        let term: Vec<char> = "rep".chars().collect();
+        assert!(fuzzy_score(&term, r"C:\Docs", "Q3 report.docx").is_some());
+        assert!(fuzzy_score(&term, r"C:\Docs", "notes.txt").is_none());
+        // Found in the name beats found only across the path
+        assert!(fuzzy_score(&term, r"C:\Docs", "report.docx") > fuzzy_score(&term, r"C:\Repos\x", "main.rs"));
+    }
+}
@@ -0,0 +1,219 @@
+//! Runs commands in the background for the output panel, collecting what they
+//! print as it comes.
+
+use std::io::Read;
+use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Globalization::{GetOEMCP, IsDBCSLeadByteEx, MultiByteToWideChar, CP_UTF8, MULTI_BYTE_TO_WIDE_CHAR_FLAGS};
+use windows::Win32::System::JobObjects as win32jobs;
+
+use crate::user_commands::Invocation;
+
+/// A command line that's running, and everything it's started
+struct Running {
+    child: Child,
+    /// `None` if the job couldn't be made, when stopping only stops `cmd.exe`
+    job: Option<Job>,
+}
+
+pub struct Run {
+    /// Printed and not yet taken, with `\r\n` line ends for the text box
+    output: Arc<Mutex<String>>,
+    /// The one running now, so it can be stopped
+    running: Arc<Mutex<Option<Running>>>,
+    stopped: Arc<AtomicBool>,
+    finished: Arc<AtomicBool>,
+}
+
+impl Run {
+    /// Runs `invocations` one after the other, firing `notice` whenever there's more output
+    pub fn start(invocations: Vec<Invocation>, notice: nwg::NoticeSender) -> Run {
+        let output = Arc::new(Mutex::new(String::new()));
+        let running = Arc::new(Mutex::new(None));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicBool::new(false));
+        let (output_, running_, stopped_, finished_) = (output.clone(), running.clone(), stopped.clone(), finished.clone());
+        let spawned = std::thread::Builder::new().name("output".into()).spawn(move || {
+            for invocation in invocations {
+                if stopped_.load(Ordering::Acquire) {
+                    break;
+                }
+                let print = |text: &str| {
+                    output_.lock().unwrap().push_str(&text.replace("\r\n", "\n").replace('\n', "\r\n"));
+                    notice.notice();
+                };
+                print(&format!("> {}\n", invocation.line));
+                run(&invocation, &running_, &print);
+            }
+            finished_.store(true, Ordering::Release);
+            notice.notice();
+        });
+        if let Err(e) = spawned {
+            log::error!("could not start running commands: {e}");
+            finished.store(true, Ordering::Release);
+        }
+        Run { output, running, stopped, finished }
+    }
+    pub fn take_output(&self) -> String {
+        std::mem::take(&mut *self.output.lock().unwrap())
+    }
+    pub fn finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+    /// Kills what's running, along with whatever it started, and skips the rest
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Release);
+        if let Some(running) = self.running.lock().unwrap().as_mut() {
+            let killed = match &running.job {
+                Some(job) => unsafe { win32jobs::TerminateJobObject(job.handle(), 1) }.map_err(std::io::Error::from),
+                None => running.child.kill(),
+            };
+            if let Err(e) = killed {
+                log::warn!("could not stop the command: {e}");
+            }
+        }
+    }
+}
+
+// Nothing's left to show the output of a run that's been replaced
+impl Drop for Run {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// A job object for a command line to run in, so that stopping it stops everything it
+/// started too. Whatever's still in it when it's dropped is killed.
+struct Job(OwnedHandle);
+
+impl Job {
+    fn new() -> windows::core::Result<Job> {
+        let job = Job(unsafe { OwnedHandle::from_raw_handle(win32jobs::CreateJobObjectW(None, PCWSTR::null())?.0) });
+        let mut limits = win32jobs::JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+        limits.BasicLimitInformation.LimitFlags = win32jobs::JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        unsafe {
+            win32jobs::SetInformationJobObject(
+                job.handle(),
+                win32jobs::JobObjectExtendedLimitInformation,
+                &limits as *const _ as *const _,
+                TryInto::<u32>::try_into(size_of::<win32jobs::JOBOBJECT_EXTENDED_LIMIT_INFORMATION>()).unwrap(),
+            )?
+        };
+        Ok(job)
+    }
+    fn handle(&self) -> HANDLE {
+        HANDLE(self.0.as_raw_handle())
+    }
+    /// Puts `child` in the job. `cmd.exe` is put in as soon as it's started, which is
+    /// well before it gets round to starting anything itself.
+    fn assign(self, child: &Child) -> windows::core::Result<Job> {
+        unsafe { win32jobs::AssignProcessToJobObject(self.handle(), HANDLE(child.as_raw_handle()))? };
+        Ok(self)
+    }
+}
+
+/// Runs one command line, passing on what it prints to stdout and stderr as it comes
+fn run(invocation: &Invocation, running: &Mutex<Option<Running>>, print: &(dyn Fn(&str) + Sync)) {
+    log::info!("running {} in {:?}, keeping its output", invocation.line, invocation.dir);
+    let spawned = invocation.command().stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
+    let (stdout, stderr) = match spawned {
+        Ok(mut spawned) => {
+            let pipes = (spawned.stdout.take(), spawned.stderr.take());
+            let job = match Job::new().and_then(|job| job.assign(&spawned)) {
+                Ok(job) => Some(job),
+                Err(e) => {
+                    log::warn!("stopping {} won't stop what it starts: {}", invocation.line, e.message().trim());
+                    None
+                }
+            };
+            *running.lock().unwrap() = Some(Running { child: spawned, job });
+            pipes
+        }
+        Err(e) => {
+            print(&format!("could not run it: {e}\n"));
+            return;
+        }
+    };
+    std::thread::scope(|scope| {
+        if let Some(stderr) = stderr {
+            scope.spawn(|| pass_on(stderr, print));
+        }
+        if let Some(stdout) = stdout {
+            pass_on(stdout, print);
+        }
+    });
+    // Both pipes are closed, so it's done or as good as
+    let status = running.lock().unwrap().take().map(|mut running| running.child.wait());
+    match status {
+        Some(Ok(status)) if status.success() => print("\n"),
+        Some(Ok(status)) => print(&format!("exited with {}\n\n", status.code().map_or("no code".to_owned(), |code| code.to_string()))),
+        Some(Err(e)) => print(&format!("could not wait for it: {e}\n\n")),
+        None => {}
+    }
+}
+
+/// Passes on what comes down `pipe` as text. Console programs print in the OEM code page,
+/// and a character can be split between two reads, so its start waits for the rest.
+fn pass_on(mut pipe: impl Read, print: &(dyn Fn(&str) + Sync)) {
+    let code_page = unsafe { GetOEMCP() };
+    let mut buffer = [0u8; 4096];
+    let mut pending = Vec::new();
+    loop {
+        match pipe.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => {
+                pending.extend_from_slice(&buffer[..read]);
+                let whole = whole_characters(code_page, &pending);
+                if whole > 0 {
+                    print(&decode(code_page, &pending[..whole]));
+                    pending.drain(..whole);
+                }
+            }
+        }
+    }
+    if !pending.is_empty() {
+        print(&decode(code_page, &pending));
+    }
+}
+
+/// How many of `bytes` make whole characters in `code_page`, leaving off the start of one that isn't
+fn whole_characters(code_page: u32, bytes: &[u8]) -> usize {
+    if code_page == CP_UTF8 {
+        // Back to where the last character starts
+        for start in (bytes.len().saturating_sub(3)..bytes.len()).rev() {
+            let length = match bytes[start] {
+                0x80..=0xBF => continue,
+                0xC0..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                0xF0..=0xF7 => 4,
+                _ => 1,
+            };
+            return if start + length > bytes.len() { start } else { bytes.len() };
+        }
+        return bytes.len();
+    }
+    // Double-byte code pages, where a lead byte always has one more after it
+    let mut whole = 0;
+    while whole < bytes.len() {
+        let lead = unsafe { IsDBCSLeadByteEx(code_page, bytes[whole]) }.is_ok();
+        if lead && whole + 1 == bytes.len() {
+            break;
+        }
+        whole += if lead { 2 } else { 1 };
+    }
+    whole
+}
+
+fn decode(code_page: u32, bytes: &[u8]) -> String {
+    let flags = MULTI_BYTE_TO_WIDE_CHAR_FLAGS::default();
+    let length = unsafe { MultiByteToWideChar(code_page, flags, bytes, None) };
+    let mut wide = vec![0u16; TryInto::<usize>::try_into(length).unwrap_or(0)];
+    let written = unsafe { MultiByteToWideChar(code_page, flags, bytes, Some(&mut wide)) };
+    wide.truncate(TryInto::<usize>::try_into(written).unwrap_or(0));
+    String::from_utf16_lossy(&wide)
+}
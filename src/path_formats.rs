@@ -0,0 +1,144 @@
+//! The ways "Copy path as" can write a path, for pasting into tickets,
+//! shells and other places that want something other than `C:\...`.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathFormat {
+    /// As the shell parses it, `C:\Users\me`
+    Windows,
+    /// `"C:\Users\me"`
+    Quoted,
+    /// `C:/Users/me`
+    ForwardSlash,
+    /// `file:///C:/Users/me`
+    FileUri,
+    /// `/mnt/c/Users/me`, or the distribution's own path for `\\wsl$` shares
+    Wsl,
+    /// Relative to a base folder, like `..\me\Documents`
+    Relative,
+}
+
+/// Writes `path`, a `SIGDN_DESKTOPABSOLUTEPARSING` name, in `format`.
+/// `Relative` falls back to `Windows` without a `base`, or if `path` is on another drive.
+pub fn format_path(path: &str, format: PathFormat, base: Option<&str>) -> String {
+    match format {
+        PathFormat::Windows => path.to_owned(),
+        PathFormat::Quoted => format!("\"{path}\""),
+        PathFormat::ForwardSlash => path.replace('\\', "/"),
+        PathFormat::FileUri => file_uri(path),
+        PathFormat::Wsl => wsl(path),
+        PathFormat::Relative => base.and_then(|base| relative(path, base)).unwrap_or_else(|| path.to_owned()),
+    }
+}
+
+/// Formats every path and joins them, one per line or separated by spaces
+pub fn format_paths<'a>(paths: impl IntoIterator<Item = &'a str>, format: PathFormat, base: Option<&str>, with_spaces: bool) -> String {
+    let separator = if with_spaces { " " } else { "\r\n" };
+    paths.into_iter().map(|path| format_path(path, format, base)).collect::<Vec<_>>().join(separator)
+}
+
+fn file_uri(path: &str) -> String {
+    // `\\?\` only tells Windows not to parse the path, and means nothing in a URI
+    let path = match strip_prefix_ignore_case(path, "\\\\?\\UNC\\") {
+        Some(unc) => format!("\\\\{unc}"),
+        None => path.strip_prefix("\\\\?\\").unwrap_or(path).to_owned(),
+    };
+    let (prefix, rest) = match path.strip_prefix("\\\\") {
+        // `\\server\share` becomes `file://server/share`
+        Some(unc) => ("file://", unc),
+        None => ("file:///", path.as_str()),
+    };
+    let mut uri = prefix.to_owned();
+    for byte in rest.bytes() {
+        match byte {
+            b'\\' => uri.push('/'),
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+fn wsl(path: &str) -> String {
+    for share in ["\\\\wsl$\\", "\\\\wsl.localhost\\"] {
+        if let Some(rest) = strip_prefix_ignore_case(path, share) {
+            // Skip the distribution name
+            let inside = rest.split_once('\\').map_or("", |(_, inside)| inside);
+            return format!("/{}", inside.replace('\\', "/"));
+        }
+    }
+    let bytes = path.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        let drive = (bytes[0] as char).to_ascii_lowercase();
+        let rest = path[2..].trim_start_matches('\\').replace('\\', "/");
+        return format!("/mnt/{drive}/{rest}").trim_end_matches('/').to_owned();
+    }
+    path.replace('\\', "/")
+}
+
+fn relative(path: &str, base: &str) -> Option<String> {
+    let path_parts: Vec<_> = path.split('\\').filter(|part| !part.is_empty()).collect();
+    let base_parts: Vec<_> = base.split('\\').filter(|part| !part.is_empty()).collect();
+    let common = path_parts
+        .iter()
+        .zip(&base_parts)
+        .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+        .count();
+    // Nothing in common means another drive or share, which has no relative path
+    if common == 0 {
+        return None;
+    }
+    let mut parts = vec![".."; base_parts.len() - common];
+    parts.extend(&path_parts[common..]);
+    if parts.is_empty() {
+        return Some(".".to_owned());
+    }
+    Some(parts.join("\\"))
+}
+
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &text[prefix.len()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        let path = r"C:\Users\me\My Documents";
+        assert_eq!(format_path(path, PathFormat::Windows, None), path);
+        assert_eq!(format_path(path, PathFormat::Quoted, None), r#""C:\Users\me\My Documents""#);
+        assert_eq!(format_path(path, PathFormat::ForwardSlash, None), "C:/Users/me/My Documents");
+        assert_eq!(format_path(path, PathFormat::FileUri, None), "file:///C:/Users/me/My%20Documents");
+        assert_eq!(format_path(r"\\server\share\a b", PathFormat::FileUri, None), "file://server/share/a%20b");
+        assert_eq!(format_path(r"\\?\C:\long\a b", PathFormat::FileUri, None), "file:///C:/long/a%20b");
+        assert_eq!(format_path(r"\\?\UNC\server\share\a b", PathFormat::FileUri, None), "file://server/share/a%20b");
+    }
+
+    #[test]
+    fn wsl_paths() {
+        assert_eq!(format_path(r"C:\Users\me", PathFormat::Wsl, None), "/mnt/c/Users/me");
+        assert_eq!(format_path(r"D:\", PathFormat::Wsl, None), "/mnt/d");
+        assert_eq!(format_path(r"\\wsl$\Ubuntu\home\me", PathFormat::Wsl, None), "/home/me");
+        assert_eq!(format_path(r"\\WSL.localhost\Debian\etc", PathFormat::Wsl, None), "/etc");
+    }
+
+    #[test]
+    fn relative_paths() {
+        let base = Some(r"C:\Users\me\Documents");
+        assert_eq!(format_path(r"C:\Users\me\Pictures\a.jpg", PathFormat::Relative, base), r"..\Pictures\a.jpg");
+        assert_eq!(format_path(r"c:\users\ME\Documents\x", PathFormat::Relative, base), "x");
+        assert_eq!(format_path(r"C:\Users\me\Documents", PathFormat::Relative, base), ".");
+        // Another drive, or no base at all, stays as it is
+        assert_eq!(format_path(r"D:\x", PathFormat::Relative, base), r"D:\x");
+        assert_eq!(format_path(r"C:\x", PathFormat::Relative, None), r"C:\x");
+    }
+
+    #[test]
+    fn joined() {
+        let paths = [r"C:\a", r"C:\b"];
+        assert_eq!(format_paths(paths, PathFormat::Quoted, None, true), r#""C:\a" "C:\b""#);
+        assert_eq!(format_paths(paths, PathFormat::Windows, None, false), "C:\\a\r\nC:\\b");
+    }
+}
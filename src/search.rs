@@ -0,0 +1,164 @@
+//! Recursive searches from a folder, walked on a worker thread with the hits
+//! handed back as they're found, for a `Folder::Search` column to show.
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::matching;
+
+/// The longest the worker sits on hits before telling the UI thread about them
+const NOTICE_INTERVAL: Duration = Duration::from_millis(150);
+
+pub struct Query {
+    pub criteria: matching::Criteria,
+    /// Text the file has to contain, as UTF-8 or UTF-16, without case
+    pub contents: Option<String>,
+}
+
+pub struct Search {
+    /// Every hit so far, in the order they were found
+    hits: Arc<Mutex<Vec<PathBuf>>>,
+    finished: Arc<AtomicBool>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Search {
+    /// Starts walking `root`. `notice` fires whenever there are new hits, and when it's finished.
+    pub fn start(root: PathBuf, query: Query, notice: nwg::NoticeSender) -> Search {
+        let hits = Arc::new(Mutex::new(Vec::new()));
+        let finished = Arc::new(AtomicBool::new(false));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (hits_, finished_, cancelled_) = (hits.clone(), finished.clone(), cancelled.clone());
+        std::thread::Builder::new()
+            .name("search".into())
+            .spawn(move || {
+                let started = Instant::now();
+                walk(&root, &query, &hits_, &cancelled_, &notice);
+                log::info!("searched {} in {:?}, {} hits", root.display(), started.elapsed(), hits_.lock().unwrap().len());
+                finished_.store(true, Ordering::Release);
+                notice.notice();
+            })
+            .expect("failed to start the search thread");
+        Search { hits, finished, cancelled }
+    }
+    /// The hits after the first `seen`
+    pub fn hits_since(&self, seen: usize) -> Vec<PathBuf> {
+        self.hits.lock().unwrap().get(seen..).map_or(Vec::new(), <[PathBuf]>::to_vec)
+    }
+    pub fn finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+}
+
+// Nobody is looking at the results any more
+impl Drop for Search {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+}
+
+fn walk(root: &Path, query: &Query, hits: &Mutex<Vec<PathBuf>>, cancelled: &AtomicBool, notice: &nwg::NoticeSender) {
+    let mut folders = vec![root.to_owned()];
+    let mut pending = 0;
+    let mut last_notice = Instant::now();
+    while let Some(folder) = folders.pop() {
+        let entries = match fs::read_dir(&folder) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::debug!("search skipped {}: {e}", folder.display());
+                continue;
+            }
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            if cancelled.load(Ordering::Acquire) {
+                log::debug!("search of {} cancelled", root.display());
+                return;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let path = entry.path();
+            let is_folder = metadata.is_dir();
+            // Junctions and symlinks could lead round in circles
+            if is_folder && !metadata.file_type().is_symlink() {
+                folders.push(path.clone());
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let size = metadata.is_file().then(|| metadata.len());
+            if !query.criteria.matches(&name, is_folder, size, metadata.modified().ok()) {
+                continue;
+            }
+            if let Some(text) = &query.contents {
+                if is_folder || !contains(&path, text) {
+                    continue;
+                }
+            }
+            hits.lock().unwrap().push(path);
+            pending += 1;
+            if last_notice.elapsed() >= NOTICE_INTERVAL {
+                notice.notice();
+                pending = 0;
+                last_notice = Instant::now();
+            }
+        }
+        if pending > 0 && last_notice.elapsed() >= NOTICE_INTERVAL {
+            notice.notice();
+            pending = 0;
+            last_notice = Instant::now();
+        }
+    }
+}
+
+/// Whether the file at `path` has `text` in it, as UTF-8 or UTF-16, read a piece at a time.
+/// Only ASCII letters are matched regardless of case, which is what matters for code and logs.
+fn contains(path: &Path, text: &str) -> bool {
+    let text = text.to_ascii_lowercase();
+    let utf8: Vec<u8> = text.clone().into_bytes();
+    let utf16: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    if utf8.is_empty() {
+        return true;
+    }
+    let Ok(mut file) = fs::File::open(path) else {
+        return false;
+    };
+    // Even, so the tail kept from one read to the next doesn't split a UTF-16 code unit
+    let overlap = utf16.len().max(utf8.len()).next_multiple_of(2);
+    let mut buffer = vec![0; 64 * 1024 + overlap];
+    let mut folded = Vec::with_capacity(buffer.len());
+    let mut kept = 0;
+    // Where in the file `buffer` starts
+    let mut offset = 0;
+    loop {
+        let read = match file.read(&mut buffer[kept..]) {
+            Ok(0) | Err(_) => return false,
+            Ok(read) => read,
+        };
+        let filled = kept + read;
+        let window = &buffer[..filled];
+        // Bytes of multi-byte UTF-8 sequences are all above 0x7F, so folding bytes only touches ASCII
+        folded.clear();
+        folded.extend_from_slice(window);
+        folded.make_ascii_lowercase();
+        if folded.windows(utf8.len()).any(|candidate| candidate == utf8) {
+            return true;
+        }
+        // UTF-16 is folded a code unit at a time, lined up with the start of the file
+        folded.clear();
+        for unit in window[offset % 2..].chunks_exact(2) {
+            let unit = u16::from_le_bytes([unit[0], unit[1]]);
+            let unit = if (0x41..=0x5A).contains(&unit) { unit + 0x20 } else { unit };
+            folded.extend_from_slice(&unit.to_le_bytes());
+        }
+        if folded.windows(utf16.len()).step_by(2).any(|candidate| candidate == utf16) {
+            return true;
+        }
+        // Keep the tail, in case the text straddles two reads
+        kept = overlap.min(filled);
+        buffer.copy_within(filled - kept..filled, 0);
+        offset += filled - kept;
+    }
+}
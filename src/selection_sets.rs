@@ -0,0 +1,59 @@
+//! Selections saved under a name, one file each in `%LOCALAPPDATA%\stapler\sets`.
+//! A set is a plain text list of parsing names, one per line, which is also
+//! what gets exported and imported so sets can be passed around.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The saved sets' names, alphabetically
+pub fn names() -> Vec<String> {
+    let entries = match dir().and_then(|dir| Ok(fs::read_dir(dir)?)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("could not list the saved sets: {e:#}");
+            return Vec::new();
+        }
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("txt")))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_owned()))
+        .collect();
+    names.sort_by_key(|name| name.to_lowercase());
+    names
+}
+
+/// Saves `paths` as the set `name`, replacing any set already called that
+pub fn save(name: &str, paths: &[String]) -> anyhow::Result<()> {
+    if name.is_empty() || name.contains(['\\', '/', ':', '*', '?', '"', '<', '>', '|']) {
+        anyhow::bail!("{name:?} can't be used as a set name");
+    }
+    write_list(&dir()?.join(format!("{name}.txt")), paths)
+}
+
+pub fn load(name: &str) -> anyhow::Result<Vec<String>> {
+    read_list(&dir()?.join(format!("{name}.txt")))
+}
+
+pub fn delete(name: &str) -> anyhow::Result<()> {
+    Ok(fs::remove_file(dir()?.join(format!("{name}.txt")))?)
+}
+
+/// Reads a list of parsing names, skipping blank lines
+pub fn read_list(path: &Path) -> anyhow::Result<Vec<String>> {
+    let text = fs::read_to_string(path)?;
+    Ok(text.lines().map(str::trim).filter(|line| !line.is_empty()).map(str::to_owned).collect())
+}
+
+pub fn write_list(path: &Path, paths: &[String]) -> anyhow::Result<()> {
+    let text: String = paths.iter().map(|path| format!("{path}\r\n")).collect();
+    Ok(fs::write(path, text)?)
+}
+
+/// Where the sets are kept
+pub fn dir() -> anyhow::Result<PathBuf> {
+    let dir = crate::app_data_dir()?.join("sets");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
@@ -0,0 +1,46 @@
+//! The tabs that were open when stapler last closed, kept in
+//! `%LOCALAPPDATA%\stapler\session.tsv` so they can be opened again.
+
+use std::fs;
+use std::path::PathBuf;
+
+pub struct Session {
+    /// What each tab had opened deepest, as a parsing name, or `None` for just the Desktop
+    pub tabs: Vec<Option<String>>,
+    /// Which of `tabs` was showing
+    pub active: usize,
+}
+
+/// The last session, if there was one with any tabs
+pub fn load() -> Option<Session> {
+    let text = match path().and_then(|path| Ok(fs::read_to_string(path)?)) {
+        Ok(text) => text,
+        Err(e) => {
+            log::debug!("no saved session: {e:#}");
+            return None;
+        }
+    };
+    let mut session = Session { tabs: Vec::new(), active: 0 };
+    for line in text.lines() {
+        match line.split_once('\t') {
+            Some(("active", active)) => session.active = active.parse().unwrap_or(0),
+            Some(("tab", path)) => session.tabs.push(Some(path.to_owned()).filter(|path| !path.is_empty())),
+            _ => log::warn!("skipping bad line in the session: {line:?}"),
+        }
+    }
+    (!session.tabs.is_empty()).then_some(session)
+}
+
+pub fn save(session: &Session) {
+    let mut text = format!("active\t{}\r\n", session.active);
+    for tab in &session.tabs {
+        text.push_str(&format!("tab\t{}\r\n", tab.as_deref().unwrap_or("")));
+    }
+    if let Err(e) = path().and_then(|path| Ok(fs::write(path, text)?)) {
+        log::warn!("could not save the session: {e:#}");
+    }
+}
+
+fn path() -> anyhow::Result<PathBuf> {
+    Ok(crate::app_data_dir()?.join("session.tsv"))
+}
@@ -0,0 +1,77 @@
+//! The shelf: items stapled from any column, kept until they're taken off
+//! again, in `%LOCALAPPDATA%\stapler\shelf.txt`.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::journal::Step;
+
+/// Parsing names, in the order they were stapled
+#[derive(Default)]
+pub struct Shelf {
+    items: Vec<String>,
+}
+
+impl Shelf {
+    /// Reads the saved shelf, or starts an empty one
+    pub fn load() -> Shelf {
+        match path().and_then(|path| Ok(fs::read_to_string(path)?)) {
+            Ok(text) => Shelf {
+                items: text.lines().filter(|line| !line.is_empty()).map(str::to_owned).collect(),
+            },
+            Err(e) => {
+                log::debug!("no saved shelf: {e:#}");
+                Shelf::default()
+            }
+        }
+    }
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+    /// Staples `paths` on the end, skipping any already there. Returns how many were new.
+    pub fn add(&mut self, paths: impl IntoIterator<Item = String>) -> usize {
+        let before = self.items.len();
+        for path in paths {
+            if !self.items.iter().any(|item| item.eq_ignore_ascii_case(&path)) {
+                self.items.push(path);
+            }
+        }
+        self.save();
+        self.items.len() - before
+    }
+    /// Takes the items at `indexes` off
+    pub fn remove(&mut self, indexes: &[usize]) {
+        let mut i = 0;
+        self.items.retain(|_| {
+            i += 1;
+            !indexes.contains(&(i - 1))
+        });
+        self.save();
+    }
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.save();
+    }
+    /// Points the staples that `steps` moved at where they went, even if
+    /// the rest of the move stopped part way
+    pub fn retarget(&mut self, steps: &[Step]) {
+        for step in steps {
+            if let Step::Moved { from, to } = step {
+                if let Some(item) = self.items.iter_mut().find(|item| item.eq_ignore_ascii_case(from)) {
+                    *item = to.clone();
+                }
+            }
+        }
+        self.save();
+    }
+    fn save(&self) {
+        let text: String = self.items.iter().map(|item| format!("{item}\r\n")).collect();
+        if let Err(e) = path().and_then(|path| Ok(fs::write(path, text)?)) {
+            log::warn!("could not save the shelf: {e:#}");
+        }
+    }
+}
+
+fn path() -> anyhow::Result<PathBuf> {
+    Ok(crate::app_data_dir()?.join("shelf.txt"))
+}
@@ -0,0 +1,238 @@
+//! What File > New can make: the files in `%LOCALAPPDATA%\stapler\templates`,
+//! which the user can add to or change, then the shell's own "New" entries.
+
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use windows::core::{HSTRING, PCSTR, PCWSTR, PWSTR};
+use windows::Win32::Globalization::{WideCharToMultiByte, CP_ACP};
+use windows::Win32::System::Registry as win32registry;
+
+use crate::clipboard;
+
+/// Put in the templates folder the first time it's made
+const STARTERS: [(&str, &[u8]); 3] = [
+    ("Text Document.txt", b""),
+    ("Markdown Document.md", b"# Title\r\n"),
+    ("Rust Source File.rs", b"fn main() {\r\n}\r\n"),
+];
+
+#[derive(Clone)]
+pub struct Template {
+    /// For the menu, like "Text Document"
+    pub name: String,
+    /// Without the dot
+    pub extension: String,
+    source: Source,
+}
+
+#[derive(Clone)]
+enum Source {
+    Empty,
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+/// The user's templates, then `shell`'s for any extension they don't cover
+pub fn load(shell: &[Template]) -> Vec<Template> {
+    let mut templates = match user_templates() {
+        Ok(templates) => templates,
+        Err(e) => {
+            log::warn!("could not read the templates folder: {e:#}");
+            Vec::new()
+        }
+    };
+    for template in shell {
+        if !templates.iter().any(|existing| existing.extension.eq_ignore_ascii_case(&template.extension)) {
+            templates.push(template.clone());
+        }
+    }
+    templates
+}
+
+/// The shell's templates, read on a thread of their own as that means looking
+/// through every extension in `HKEY_CLASSES_ROOT`
+pub struct ShellTemplates {
+    found: Arc<Mutex<Option<Vec<Template>>>>,
+}
+
+impl ShellTemplates {
+    /// Starts reading them, firing `notice` once they're ready
+    pub fn start(notice: nwg::NoticeSender) -> ShellTemplates {
+        let found = Arc::new(Mutex::new(None));
+        let found_ = found.clone();
+        let spawned = std::thread::Builder::new().name("templates".into()).spawn(move || {
+            let templates = shell_templates();
+            log::debug!("{} shell templates", templates.len());
+            *found_.lock().unwrap() = Some(templates);
+            notice.notice();
+        });
+        if let Err(e) = spawned {
+            log::error!("could not start reading the shell's templates: {e}");
+        }
+        ShellTemplates { found }
+    }
+    pub fn take(&self) -> Option<Vec<Template>> {
+        self.found.lock().unwrap().take()
+    }
+}
+
+/// Where the user's templates live, made and filled with starters if it's not there yet
+pub fn dir() -> anyhow::Result<PathBuf> {
+    let dir = crate::app_data_dir()?.join("templates");
+    if !dir.exists() {
+        fs::create_dir_all(&dir)?;
+        for (name, contents) in STARTERS {
+            fs::write(dir.join(name), contents)?;
+        }
+    }
+    Ok(dir)
+}
+
+impl Template {
+    /// Makes `New <name>.<extension>` in `folder`, numbered if that's taken
+    pub fn create(&self, folder: &Path) -> io::Result<PathBuf> {
+        let bytes = match &self.source {
+            Source::Empty => Vec::new(),
+            Source::Bytes(bytes) => bytes.clone(),
+            Source::File(path) => fs::read(path)?,
+        };
+        clipboard::write_new_file(folder, &format!("New {}", self.name), &self.extension, &bytes)
+    }
+}
+
+/// Makes `New folder` in `folder`, numbered if that's taken
+pub fn create_folder(folder: &Path) -> io::Result<PathBuf> {
+    for n in 1.. {
+        let name = if n == 1 { "New folder".to_owned() } else { format!("New folder ({n})") };
+        let path = folder.join(name);
+        match fs::create_dir(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+fn user_templates() -> anyhow::Result<Vec<Template>> {
+    let mut templates: Vec<Template> = fs::read_dir(dir()?)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            Some(Template {
+                name: path.file_stem()?.to_str()?.to_owned(),
+                extension: path.extension()?.to_str()?.to_owned(),
+                source: Source::File(path),
+            })
+        })
+        .collect();
+    templates.sort_by_key(|template| template.name.to_lowercase());
+    Ok(templates)
+}
+
+/// `HKEY_CLASSES_ROOT\.ext\ShellNew`, or `.ext\<ProgID>\ShellNew`, for every
+/// extension that has one and says how to make the file without running anything
+fn shell_templates() -> Vec<Template> {
+    let mut templates = Vec::new();
+    for extension in subkeys(win32registry::HKEY_CLASSES_ROOT, "").into_iter().filter(|key| key.starts_with('.')) {
+        let prog_id = string_value(&extension, "");
+        let mut candidates = vec![format!("{extension}\\ShellNew")];
+        if let Some(prog_id) = &prog_id {
+            candidates.push(format!("{extension}\\{prog_id}\\ShellNew"));
+        }
+        let Some(source) = candidates.iter().find_map(|key| shell_new_source(key)) else {
+            continue;
+        };
+        let name = prog_id.as_deref().and_then(|prog_id| string_value(prog_id, "")).unwrap_or_else(|| format!("{} File", extension[1..].to_uppercase()));
+        templates.push(Template {
+            name,
+            extension: extension[1..].to_owned(),
+            source,
+        });
+    }
+    templates.sort_by_key(|template| template.name.to_lowercase());
+    templates
+}
+
+fn shell_new_source(key: &str) -> Option<Source> {
+    if value(key, "NullFile").is_some() {
+        return Some(Source::Empty);
+    }
+    if let Some((kind, data)) = value(key, "Data") {
+        return Some(Source::Bytes(match kind {
+            // Explorer writes text in the ANSI code page, without its terminator
+            win32registry::REG_SZ | win32registry::REG_EXPAND_SZ => ansi(&wide(&data)),
+            _ => data,
+        }));
+    }
+    let file_name = string_value(key, "FileName")?;
+    let path = Path::new(&file_name);
+    if path.is_absolute() {
+        return Some(Source::File(path.to_owned()));
+    }
+    // Relative names are looked for in the places Explorer looks
+    let folders = [
+        std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("Microsoft\\Windows\\Templates")),
+        std::env::var_os("ProgramData").map(|dir| PathBuf::from(dir).join("Microsoft\\Windows\\Templates")),
+        std::env::var_os("WINDIR").map(|dir| PathBuf::from(dir).join("ShellNew")),
+    ];
+    folders.into_iter().flatten().map(|folder| folder.join(path)).find(|path| path.is_file()).map(Source::File)
+}
+
+fn subkeys(root: win32registry::HKEY, key: &str) -> Vec<String> {
+    let mut opened = win32registry::HKEY::default();
+    if unsafe { win32registry::RegOpenKeyExW(root, &HSTRING::from(key), 0, win32registry::KEY_READ, &mut opened) }.is_err() {
+        return Vec::new();
+    }
+    let mut names = Vec::new();
+    let mut buffer = [0u16; 256];
+    for index in 0.. {
+        let mut length = buffer.len() as u32;
+        let result = unsafe { win32registry::RegEnumKeyExW(opened, index, PWSTR(buffer.as_mut_ptr()), &mut length, None, PWSTR::null(), None, None) };
+        if result.is_err() {
+            break;
+        }
+        names.push(String::from_utf16_lossy(&buffer[..length as usize]));
+    }
+    let _ = unsafe { win32registry::RegCloseKey(opened) };
+    names
+}
+
+/// The type and raw bytes of the value `name` under `HKEY_CLASSES_ROOT\key`, `""` being the default value
+fn value(key: &str, name: &str) -> Option<(win32registry::REG_VALUE_TYPE, Vec<u8>)> {
+    let key = HSTRING::from(key);
+    let name = HSTRING::from(name);
+    let value = if name.is_empty() { PCWSTR::null() } else { PCWSTR(name.as_ptr()) };
+    let mut size = 0u32;
+    unsafe { win32registry::RegGetValueW(win32registry::HKEY_CLASSES_ROOT, &key, value, win32registry::RRF_RT_ANY, None, None, Some(&mut size)) }.ok().ok()?;
+    let mut data = vec![0u8; size as usize];
+    let mut kind = win32registry::REG_VALUE_TYPE::default();
+    unsafe { win32registry::RegGetValueW(win32registry::HKEY_CLASSES_ROOT, &key, value, win32registry::RRF_RT_ANY, Some(&mut kind), Some(data.as_mut_ptr() as *mut _), Some(&mut size)) }.ok().ok()?;
+    data.truncate(size as usize);
+    Some((kind, data))
+}
+
+fn string_value(key: &str, name: &str) -> Option<String> {
+    let (_, data) = value(key, name)?;
+    Some(String::from_utf16_lossy(&wide(&data))).filter(|text| !text.is_empty())
+}
+
+/// A `REG_SZ` value's text, up to its terminator
+fn wide(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).take_while(|c| *c != 0).collect()
+}
+
+fn ansi(wide: &[u16]) -> Vec<u8> {
+    if wide.is_empty() {
+        return Vec::new();
+    }
+    let length = unsafe { WideCharToMultiByte(CP_ACP, 0, wide, None, PCSTR::null(), None) };
+    let mut bytes = vec![0u8; TryInto::<usize>::try_into(length).unwrap_or(0)];
+    let written = unsafe { WideCharToMultiByte(CP_ACP, 0, wide, Some(&mut bytes), PCSTR::null(), None) };
+    bytes.truncate(TryInto::<usize>::try_into(written).unwrap_or(0));
+    bytes
+}
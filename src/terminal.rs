@@ -0,0 +1,83 @@
+//! Which terminal Open terminal here starts, kept in `%LOCALAPPDATA%\stapler\terminal.txt`.
+
+use std::fs;
+use std::os::windows::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// So a console program gets a window of its own rather than sharing ours
+const CREATE_NEW_CONSOLE: u32 = 0x0000_0010;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Terminal {
+    #[default]
+    WindowsTerminal,
+    Cmd,
+    PowerShell,
+    /// The default distribution's shell
+    Wsl,
+}
+
+impl Terminal {
+    /// As it's saved
+    fn key(self) -> &'static str {
+        match self {
+            Terminal::WindowsTerminal => "wt",
+            Terminal::Cmd => "cmd",
+            Terminal::PowerShell => "powershell",
+            Terminal::Wsl => "wsl",
+        }
+    }
+    /// The one chosen last time, or Windows Terminal
+    pub fn load() -> Terminal {
+        let text = match path().and_then(|path| Ok(fs::read_to_string(path)?)) {
+            Ok(text) => text,
+            Err(e) => {
+                log::debug!("no terminal chosen: {e:#}");
+                return Terminal::default();
+            }
+        };
+        let text = text.trim();
+        [Terminal::WindowsTerminal, Terminal::Cmd, Terminal::PowerShell, Terminal::Wsl]
+            .into_iter()
+            .find(|terminal| terminal.key() == text)
+            .unwrap_or_else(|| {
+                log::warn!("unknown terminal {text:?}");
+                Terminal::default()
+            })
+    }
+    pub fn save(self) {
+        if let Err(e) = path().and_then(|path| Ok(fs::write(path, self.key())?)) {
+            log::warn!("could not save the terminal: {e:#}");
+        }
+    }
+    /// Starts it in `folder`
+    pub fn open(self, folder: &Path) -> std::io::Result<()> {
+        let mut command = match self {
+            // It's not a console program, and starts its shell wherever it's told
+            Terminal::WindowsTerminal => {
+                let mut command = Command::new("wt.exe");
+                command.arg("-d").arg(folder);
+                command
+            }
+            Terminal::Cmd => Command::new("cmd.exe"),
+            Terminal::PowerShell => {
+                let mut command = Command::new("powershell.exe");
+                command.arg("-NoLogo");
+                command
+            }
+            Terminal::Wsl => {
+                let mut command = Command::new("wsl.exe");
+                command.arg("--cd").arg(folder);
+                command
+            }
+        };
+        log::info!("opening {self:?} in {}", folder.display());
+        command.current_dir(folder).creation_flags(CREATE_NEW_CONSOLE).spawn()?;
+        Ok(())
+    }
+}
+
+fn path() -> anyhow::Result<PathBuf> {
+    Ok(crate::app_data_dir()?.join("terminal.txt"))
+}
@@ -0,0 +1,212 @@
+//! Thumbnails for columns in `ViewMode::Thumbnails`, rendered on a worker
+//! thread through `IShellItemImageFactory` and the shell's thumbnail cache.
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+use windows::Win32::Foundation::{HANDLE, HWND, SIZE};
+use windows::Win32::Graphics::Gdi as win32gdi;
+use windows::Win32::System::Com::{CoInitializeEx, COINIT_APARTMENTTHREADED, COINIT_DISABLE_OLE1DDE};
+use windows::Win32::UI::Controls as win32controls;
+use windows::Win32::UI::Shell as win32shell;
+
+use crate::ItemId;
+
+/// The width and height of a thumbnail, in pixels
+pub const THUMBNAIL_SIZE: i32 = 96;
+
+/// One row waiting for its thumbnail
+struct Job {
+    /// The list view the row is in, as an `HWND`
+    list_view: usize,
+    /// The `ThumbnailCache::begin` this row was requested under
+    generation: u64,
+    row: usize,
+    /// `ItemId::as_bytes`
+    itemid: Vec<u8>,
+}
+
+/// A thumbnail that has come back from the worker
+pub struct Resolved {
+    pub list_view: usize,
+    pub generation: u64,
+    pub row: usize,
+    /// A `THUMBNAIL_SIZE` square `HBITMAP`. Whoever takes it must delete it.
+    pub bitmap: usize,
+}
+
+pub struct ThumbnailCache {
+    jobs: mpsc::Sender<Job>,
+    /// Filled by the worker, drained by `take_resolved`
+    resolved: Arc<Mutex<Vec<Resolved>>>,
+    /// The live generation of each list view. Jobs from older ones get skipped.
+    generations: Arc<Mutex<HashMap<usize, u64>>>,
+}
+
+impl ThumbnailCache {
+    /// Starts the worker. `notice` fires whenever `take_resolved` has something.
+    pub fn new(notice: nwg::NoticeSender) -> ThumbnailCache {
+        let (jobs, receiver) = mpsc::channel();
+        let resolved = Arc::new(Mutex::new(Vec::new()));
+        let generations = Arc::new(Mutex::new(HashMap::new()));
+        let resolved_ = resolved.clone();
+        let generations_ = generations.clone();
+        std::thread::Builder::new()
+            .name("thumbnails".into())
+            .spawn(move || work(receiver, resolved_, generations_, notice))
+            .expect("failed to start the thumbnail thread");
+        ThumbnailCache {
+            jobs,
+            resolved,
+            generations,
+        }
+    }
+    /// Forgets every row queued for `list_view`, and returns the generation to queue new ones under
+    pub fn begin(&self, list_view: usize) -> u64 {
+        let mut generations = self.generations.lock().unwrap();
+        let generation = generations.get(&list_view).map_or(0, |generation| generation + 1);
+        generations.insert(list_view, generation);
+        generation
+    }
+    pub fn request(&self, list_view: usize, generation: u64, row: usize, itemid: &ItemId) {
+        let _ = self.jobs.send(Job {
+            list_view,
+            generation,
+            row,
+            itemid: itemid.as_bytes().to_vec(),
+        });
+    }
+    /// Everything the worker has finished since the last call
+    pub fn take_resolved(&self) -> Vec<Resolved> {
+        std::mem::take(&mut *self.resolved.lock().unwrap())
+    }
+}
+
+/// An image list to hold one column's thumbnails. It belongs to the
+/// list view it's given to, which destroys it along with itself.
+pub fn new_image_list() -> nwg::ImageList {
+    let handle = unsafe { win32controls::ImageList_Create(THUMBNAIL_SIZE, THUMBNAIL_SIZE, win32controls::ILC_COLOR32, 0, 64) };
+    nwg::ImageList {
+        handle: handle.0 as *mut _,
+        owned: false,
+    }
+}
+
+/// Moves a `Resolved::bitmap` into an image list, returning its index there
+pub fn add_to(image_list: &nwg::ImageList, bitmap: usize) -> i32 {
+    unsafe {
+        let bitmap = win32gdi::HBITMAP(bitmap as *mut _);
+        let index = win32controls::ImageList_Add(win32controls::HIMAGELIST(image_list.handle as _), bitmap, win32gdi::HBITMAP::default());
+        let _ = win32gdi::DeleteObject(bitmap);
+        index
+    }
+}
+
+/// Drops a `Resolved::bitmap` that nobody wants any more
+pub fn discard(bitmap: usize) {
+    unsafe {
+        let _ = win32gdi::DeleteObject(win32gdi::HBITMAP(bitmap as *mut _));
+    }
+}
+
+/// Empties an image list from `new_image_list`
+pub fn clear(image_list: &nwg::ImageList) {
+    unsafe {
+        let _ = win32controls::ImageList_Remove(win32controls::HIMAGELIST(image_list.handle as _), -1);
+    }
+}
+
+fn work(jobs: mpsc::Receiver<Job>, resolved: Arc<Mutex<Vec<Resolved>>>, generations: Arc<Mutex<HashMap<usize, u64>>>, notice: nwg::NoticeSender) {
+    if let Err(e) = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED | COINIT_DISABLE_OLE1DDE) }.ok() {
+        log::error!("thumbnail thread could not start COM: {}", e.message().trim());
+        return;
+    }
+    for job in jobs {
+        if generations.lock().unwrap().get(&job.list_view) != Some(&job.generation) {
+            continue;
+        }
+        let bitmap = match unsafe { render(job.itemid.as_ptr() as *const _) } {
+            Ok(bitmap) => bitmap,
+            Err(e) => {
+                log::debug!("no thumbnail for row {}: {}", job.row, e.message().trim());
+                continue;
+            }
+        };
+        let mut resolved = resolved.lock().unwrap();
+        // One notice per batch, rather than one per row
+        if resolved.is_empty() {
+            notice.notice();
+        }
+        resolved.push(Resolved {
+            list_view: job.list_view,
+            generation: job.generation,
+            row: job.row,
+            bitmap: bitmap.0 as usize,
+        });
+    }
+}
+
+/// Gets the item's thumbnail, or its large icon if it has none,
+/// centred in a `THUMBNAIL_SIZE` square
+unsafe fn render(pidl: *const win32shell::Common::ITEMIDLIST) -> windows::core::Result<win32gdi::HBITMAP> {
+    let factory: win32shell::IShellItemImageFactory = win32shell::SHCreateItemFromIDList(pidl)?;
+    let size = SIZE {
+        cx: THUMBNAIL_SIZE,
+        cy: THUMBNAIL_SIZE,
+    };
+    let image = factory.GetImage(size, win32shell::SIIGBF_RESIZETOFIT)?;
+    let framed = frame(image);
+    let _ = win32gdi::DeleteObject(image);
+    framed
+}
+
+/// Draws a bitmap no bigger than `THUMBNAIL_SIZE` in the middle of a
+/// transparent one exactly that size, since image lists only take one size
+unsafe fn frame(image: win32gdi::HBITMAP) -> windows::core::Result<win32gdi::HBITMAP> {
+    let mut info = win32gdi::BITMAP::default();
+    win32gdi::GetObjectW(image, std::mem::size_of::<win32gdi::BITMAP>() as i32, Some(&mut info as *mut _ as *mut _));
+    let screen = win32gdi::GetDC(HWND::default());
+    let source = win32gdi::CreateCompatibleDC(screen);
+    let target = win32gdi::CreateCompatibleDC(screen);
+    let header = win32gdi::BITMAPINFO {
+        bmiHeader: win32gdi::BITMAPINFOHEADER {
+            biSize: std::mem::size_of::<win32gdi::BITMAPINFOHEADER>() as u32,
+            biWidth: THUMBNAIL_SIZE,
+            // Negative for top-down
+            biHeight: -THUMBNAIL_SIZE,
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: win32gdi::BI_RGB.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut bits = std::ptr::null_mut();
+    let framed = win32gdi::CreateDIBSection(target, &header, win32gdi::DIB_RGB_COLORS, &mut bits, HANDLE::default(), 0);
+    if let Ok(framed) = framed {
+        let width = info.bmWidth.min(THUMBNAIL_SIZE);
+        let height = info.bmHeight.abs().min(THUMBNAIL_SIZE);
+        let old_source = win32gdi::SelectObject(source, image);
+        let old_target = win32gdi::SelectObject(target, framed);
+        let x = (THUMBNAIL_SIZE - width) / 2;
+        let y = (THUMBNAIL_SIZE - height) / 2;
+        if info.bmBitsPixel == 32 {
+            let blend = win32gdi::BLENDFUNCTION {
+                BlendOp: win32gdi::AC_SRC_OVER as u8,
+                BlendFlags: 0,
+                SourceConstantAlpha: 255,
+                AlphaFormat: win32gdi::AC_SRC_ALPHA as u8,
+            };
+            let _ = win32gdi::AlphaBlend(target, x, y, width, height, source, 0, 0, width, height, blend);
+        } else {
+            let _ = win32gdi::StretchBlt(target, x, y, width, height, source, 0, 0, width, height, win32gdi::SRCCOPY);
+        }
+        win32gdi::SelectObject(source, old_source);
+        win32gdi::SelectObject(target, old_target);
+    }
+    let _ = win32gdi::DeleteDC(source);
+    let _ = win32gdi::DeleteDC(target);
+    win32gdi::ReleaseDC(HWND::default(), screen);
+    framed
+}
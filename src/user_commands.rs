@@ -0,0 +1,315 @@
+//! The user's own commands, from `%LOCALAPPDATA%\stapler\commands.ini`, for the
+//! Commands menu, the list views' right-click menu, shortcuts and double clicks.
+
+use std::fs;
+use std::os::windows::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// So running a command through `cmd.exe` doesn't flash a console
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+/// Put in `commands.ini` the first time it's made
+const STARTER: &str = "\
+# Commands for the Commands menu and the right-click menu, one [section] each.
+#
+#   run     the command line, run by cmd.exe
+#   dir     where it runs, the column's folder if not given
+#   key     a shortcut in the columns, like Ctrl+Shift+V or F7
+#   output  yes to show what it prints in the output panel
+#   opens   extensions it opens on double click instead of their usual program, or *
+#
+# In run and dir, these stand for what's selected in the focused column:
+#
+#   {path}    each selected item's full path, in quotes, running once for each
+#   {paths}   every selected item's full path, in quotes, running once for all of them
+#   {folder}  the column's folder, in quotes
+#   {name}    each item's name, {stem} without its extension and {ext} just the extension,
+#             none in quotes, so \"{stem}.mp4\" works
+#
+# Whatever they stand for is escaped, so cmd.exe never acts on a & or % in a name.
+#
+# Use Edit commands to change this file, then Reload commands.
+
+[Open in VS Code]
+run = code {paths}
+key = Ctrl+Shift+V
+
+[Run tests here]
+run = cargo test
+output = yes
+
+[ffprobe]
+run = ffprobe -hide_banner {path}
+output = yes
+";
+
+/// A key in the columns, with the modifiers held for it
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Shortcut {
+    pub control: bool,
+    pub shift: bool,
+    /// A virtual key code
+    pub key: u32,
+}
+
+impl Shortcut {
+    fn parse(text: &str) -> Option<Shortcut> {
+        let mut shortcut = Shortcut { control: false, shift: false, key: 0 };
+        for part in text.split('+').map(str::trim) {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => shortcut.control = true,
+                "shift" => shortcut.shift = true,
+                key if key.len() == 1 && key.chars().all(|c| c.is_ascii_alphanumeric()) => {
+                    shortcut.key = key.to_ascii_uppercase().as_bytes()[0] as u32;
+                }
+                // F1 is 0x70, up to F24
+                key if key.starts_with('f') => match key[1..].parse::<u32>() {
+                    Ok(n @ 1..=24) => shortcut.key = 0x6F + n,
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        }
+        (shortcut.key != 0).then_some(shortcut)
+    }
+    /// How the menus show it
+    pub fn label(&self) -> String {
+        let key = match self.key {
+            0x70..=0x87 => format!("F{}", self.key - 0x6F),
+            key => char::from_u32(key).map(String::from).unwrap_or_default(),
+        };
+        format!("{}{}{key}", if self.control { "Ctrl+" } else { "" }, if self.shift { "Shift+" } else { "" })
+    }
+}
+
+pub struct UserCommand {
+    pub name: String,
+    /// The command line, with placeholders
+    run: String,
+    /// Where it runs, with placeholders
+    dir: Option<String>,
+    pub key: Option<Shortcut>,
+    /// Whether what it prints goes to the output panel
+    pub output: bool,
+    /// Lowercase extensions without the dot, or `*`
+    opens: Vec<String>,
+}
+
+/// One command line to run, with everything filled in
+pub struct Invocation {
+    pub line: String,
+    pub dir: Option<PathBuf>,
+}
+
+/// The user's commands, made with some starters the first time
+pub fn load() -> Vec<UserCommand> {
+    let text = match path().and_then(|path| Ok(fs::read_to_string(path)?)) {
+        Ok(text) => text,
+        Err(e) => {
+            log::warn!("could not read the commands: {e:#}");
+            return Vec::new();
+        }
+    };
+    let mut commands: Vec<UserCommand> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            commands.push(UserCommand { name: name.trim().to_owned(), run: String::new(), dir: None, key: None, output: false, opens: Vec::new() });
+            continue;
+        }
+        let (Some(command), Some((setting, value))) = (commands.last_mut(), line.split_once('=')) else {
+            log::warn!("skipping bad line in the commands: {line:?}");
+            continue;
+        };
+        let value = value.trim();
+        match setting.trim() {
+            "run" => command.run = value.to_owned(),
+            "dir" => command.dir = Some(value.to_owned()),
+            "key" => match Shortcut::parse(value) {
+                Some(key) => command.key = Some(key),
+                None => log::warn!("{} has a shortcut that isn't one: {value:?}", command.name),
+            },
+            "output" => command.output = matches!(value.to_ascii_lowercase().as_str(), "yes" | "true" | "1"),
+            "opens" => command.opens = value.split_whitespace().map(|extension| extension.trim_start_matches('.').to_ascii_lowercase()).collect(),
+            setting => log::warn!("{} has an unknown setting {setting:?}", command.name),
+        }
+    }
+    commands.retain(|command| {
+        if command.run.is_empty() {
+            log::warn!("{} has nothing to run", command.name);
+        }
+        !command.run.is_empty()
+    });
+    commands
+}
+
+/// The commands file, made with some starters if it's not there yet
+pub fn path() -> anyhow::Result<PathBuf> {
+    let path = crate::app_data_dir()?.join("commands.ini");
+    if !path.exists() {
+        fs::write(&path, STARTER.replace('\n', "\r\n"))?;
+    }
+    Ok(path)
+}
+
+impl UserCommand {
+    /// Whether double-clicking `path` runs this rather than opening it as usual
+    pub fn opens(&self, path: &str) -> bool {
+        let extension = Path::new(path).extension().map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        self.opens.iter().any(|opens| opens == "*" || Some(opens) == extension.as_ref())
+    }
+    /// The command lines for the selected `paths` in `folder`: one for each path
+    /// if the command uses one at a time, or else just the one
+    pub fn expand(&self, folder: Option<&str>, paths: &[String]) -> Result<Vec<Invocation>, String> {
+        let each = ["{path}", "{name}", "{stem}", "{ext}"].iter().any(|placeholder| self.run.contains(placeholder) || self.dir.as_deref().is_some_and(|dir| dir.contains(placeholder)));
+        let needs_paths = each || self.run.contains("{paths}");
+        if needs_paths && paths.is_empty() {
+            return Err(format!("{} needs something selected.", self.name));
+        }
+        let needs_folder = self.run.contains("{folder}") || self.dir.as_deref().is_some_and(|dir| dir.contains("{folder}"));
+        if needs_folder && folder.is_none() {
+            return Err(format!("{} needs a folder, so click into one first.", self.name));
+        }
+        let one: Vec<Option<&str>> = if each { paths.iter().map(|path| Some(path.as_str())).collect() } else { vec![None] };
+        Ok(one
+            .into_iter()
+            .map(|path| {
+                let filled = |text: &str, for_cmd: bool| fill(text, for_cmd, folder, paths, path);
+                let dir = match &self.dir {
+                    Some(dir) => Some(PathBuf::from(filled(dir, false))),
+                    // Shell folders like This PC have no place on disk
+                    None => folder.map(PathBuf::from).filter(|folder| folder.is_dir()),
+                };
+                Invocation { line: filled(&self.run, true), dir }
+            })
+            .collect())
+    }
+}
+
+impl Invocation {
+    /// Runs it through `cmd.exe`, so shell built-ins, batch files and `PATH` all work
+    pub fn command(&self) -> Command {
+        let mut command = Command::new("cmd.exe");
+        // `/s` takes the outer quotes off and leaves the rest of the line alone. `/v:off`
+        // keeps `!` from expanding anything, whatever the registry says.
+        command.raw_arg(format!("/d /v:off /s /c \"{}\"", self.line)).creation_flags(CREATE_NO_WINDOW);
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+    /// Starts it without waiting or keeping its output
+    pub fn spawn(&self) {
+        log::info!("running {} in {:?}", self.line, self.dir);
+        if let Err(e) = self.command().stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn() {
+            log::error!("could not run {}: {e}", self.line);
+        }
+    }
+}
+
+/// Replaces the placeholders in `text`. Anything else in braces is left alone, for the
+/// commands that use them themselves. For the command line, paths are quoted and
+/// everything filled in is escaped so `cmd.exe` passes it on as it is.
+fn fill(text: &str, for_cmd: bool, folder: Option<&str>, paths: &[String], path: Option<&str>) -> String {
+    let put = |filled: &mut String, value: &str, quote: bool| {
+        if !for_cmd {
+            filled.push_str(value);
+            return;
+        }
+        if quote {
+            filled.push('"');
+        }
+        // In our quotes, or the user's
+        let quoted = filled.matches('"').count() % 2 == 1;
+        filled.push_str(&cmd_escape(value, quoted));
+        if quote {
+            filled.push('"');
+        }
+    };
+    let name = path.map(|path| Path::new(path).file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned()));
+    let mut filled = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            break;
+        };
+        filled.push_str(&rest[..start]);
+        match (&rest[start + 1..end], path, folder, name.as_deref()) {
+            ("path", Some(path), ..) => put(&mut filled, path, true),
+            ("paths", ..) => {
+                for (index, path) in paths.iter().enumerate() {
+                    if index > 0 {
+                        filled.push(' ');
+                    }
+                    put(&mut filled, path, true);
+                }
+            }
+            ("folder", _, Some(folder), _) => put(&mut filled, folder, true),
+            ("name", .., Some(name)) => put(&mut filled, name, false),
+            ("stem", .., Some(name)) => put(&mut filled, name.rsplit_once('.').map_or(name, |(stem, _)| stem), false),
+            ("ext", .., Some(name)) => put(&mut filled, name.rsplit_once('.').map_or("", |(_, extension)| extension), false),
+            _ => filled.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// `value` escaped so `cmd.exe` takes it literally, whether it's `quoted` or not. Outside
+/// quotes, `^` escapes what cmd would act on. Inside, only `%` still expands and `^` is
+/// just a `^`, so the quotes are closed around it; programs join the pieces back up.
+fn cmd_escape(value: &str, quoted: bool) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '%' if quoted => escaped.push_str("\"^%\""),
+            '&' | '|' | '<' | '>' | '(' | ')' | '^' | '%' | '!' if !quoted => {
+                escaped.push('^');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortcuts() {
+        assert_eq!(Shortcut::parse("Ctrl+Shift+V"), Some(Shortcut { control: true, shift: true, key: 0x56 }));
+        assert_eq!(Shortcut::parse(" f7 "), Some(Shortcut { control: false, shift: false, key: 0x76 }));
+        assert_eq!(Shortcut::parse("control + 2").map(|shortcut| shortcut.key), Some(0x32));
+        assert_eq!(Shortcut::parse("Ctrl+F24").map(|shortcut| shortcut.key), Some(0x87));
+        assert_eq!(Shortcut::parse("F25"), None);
+        assert_eq!(Shortcut::parse("Ctrl"), None);
+        assert_eq!(Shortcut::parse("Alt+X"), None);
+        assert_eq!(Shortcut::parse("ctrl+shift+f3").unwrap().label(), "Ctrl+Shift+F3");
+    }
+
+    #[test]
+    fn placeholders() {
+        let paths = vec![r"C:\Videos\a b.mov".to_owned(), r"C:\Videos\c.mov".to_owned()];
+        let first = Some(paths[0].as_str());
+        assert_eq!(fill("ffmpeg -i {path} \"{stem}.mp4\"", true, None, &paths, first), r#"ffmpeg -i "C:\Videos\a b.mov" "a b.mp4""#);
+        assert_eq!(fill("code {paths}", true, None, &paths, None), r#"code "C:\Videos\a b.mov" "C:\Videos\c.mov""#);
+        assert_eq!(fill("echo {ext} {folder}", true, Some(r"C:\Videos"), &paths, first), r#"echo mov "C:\Videos""#);
+        assert_eq!(fill(r"{folder}\out {size}", false, Some(r"C:\Videos"), &paths, None), r"C:\Videos\out {size}");
+    }
+
+    #[test]
+    fn names_are_not_commands() {
+        let paths = vec![r"C:\x\a&calc (1)%PATH%.txt".to_owned()];
+        let first = Some(paths[0].as_str());
+        assert_eq!(fill("echo {name}", true, None, &paths, first), "echo a^&calc ^(1^)^%PATH^%.txt");
+        assert_eq!(fill("echo {path}", true, None, &paths, first), r#"echo "C:\x\a&calc (1)"^%"PATH"^%".txt""#);
+        assert_eq!(fill("echo \"{stem}\" %TEMP%", true, None, &paths, first), r#"echo "a&calc (1)"^%"PATH"^%"" %TEMP%"#);
+        assert_eq!(fill("{name}", false, None, &paths, first), "a&calc (1)%PATH%.txt");
+    }
+}
@@ -0,0 +1,87 @@
+//! Named pipes that are only for the user who made them: named after the user's SID
+//! and logon session, so each user and each sign-in gets its own, and with a DACL
+//! that lets nobody else open them.
+
+use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+
+use windows::core::{HSTRING, PWSTR};
+use windows::Win32::Foundation::{LocalFree, BOOL, HANDLE, HLOCAL};
+use windows::Win32::Security::{self as win32security, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES};
+use windows::Win32::Security::Authorization as win32auth;
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+/// Who stapler is running as, from its token
+struct User {
+    /// Like `S-1-5-21-...`
+    sid: String,
+    /// Tells the same user signed in twice apart, as with fast user switching or Remote Desktop
+    logon_session: u64,
+}
+
+fn user() -> windows::core::Result<User> {
+    let mut token = HANDLE::default();
+    unsafe { OpenProcessToken(GetCurrentProcess(), win32security::TOKEN_QUERY, &mut token)? };
+    let token = unsafe { OwnedHandle::from_raw_handle(token.0) };
+    let handle = HANDLE(token.as_raw_handle());
+    // The SID comes after the `TOKEN_USER` that points to it, so ask how much room that takes
+    let mut needed = 0;
+    let _ = unsafe { win32security::GetTokenInformation(handle, win32security::TokenUser, None, 0, &mut needed) };
+    let mut buffer = vec![0u64; TryInto::<usize>::try_into(needed).unwrap().div_ceil(8)];
+    unsafe { win32security::GetTokenInformation(handle, win32security::TokenUser, Some(buffer.as_mut_ptr() as *mut _), needed, &mut needed)? };
+    let token_user = unsafe { &*(buffer.as_ptr() as *const win32security::TOKEN_USER) };
+    let mut sid = PWSTR::null();
+    unsafe { win32auth::ConvertSidToStringSidW(token_user.User.Sid, &mut sid)? };
+    let text = unsafe { sid.to_string() };
+    unsafe { LocalFree(HLOCAL(sid.0 as *mut _)) };
+    let mut statistics = win32security::TOKEN_STATISTICS::default();
+    unsafe {
+        win32security::GetTokenInformation(
+            handle,
+            win32security::TokenStatistics,
+            Some(&mut statistics as *mut _ as *mut _),
+            TryInto::<u32>::try_into(size_of::<win32security::TOKEN_STATISTICS>()).unwrap(),
+            &mut needed,
+        )?
+    };
+    let session = statistics.AuthenticationId;
+    Ok(User {
+        sid: text.unwrap_or_default(),
+        logon_session: (u64::from(session.HighPart as u32) << 32) | u64::from(session.LowPart),
+    })
+}
+
+/// `\\.\pipe\{prefix}-{SID}-{logon session}`
+pub fn name(prefix: &str) -> windows::core::Result<String> {
+    let user = user()?;
+    Ok(format!(r"\\.\pipe\{prefix}-{}-{:x}", user.sid, user.logon_session))
+}
+
+/// A security descriptor that gives the current user, and nobody else, access to a pipe
+pub struct Security {
+    descriptor: PSECURITY_DESCRIPTOR,
+}
+
+impl Security {
+    pub fn new() -> windows::core::Result<Security> {
+        let user = user()?;
+        let mut descriptor = PSECURITY_DESCRIPTOR::default();
+        // Protected, so nothing is inherited, with one entry allowing the user everything
+        let sddl = HSTRING::from(format!("D:P(A;;GA;;;{})", user.sid));
+        unsafe { win32auth::ConvertStringSecurityDescriptorToSecurityDescriptorW(&sddl, win32auth::SDDL_REVISION_1, &mut descriptor, None)? };
+        Ok(Security { descriptor })
+    }
+    /// For `CreateNamedPipeW`, which only looks at them while it's making the pipe
+    pub fn attributes(&self) -> SECURITY_ATTRIBUTES {
+        SECURITY_ATTRIBUTES {
+            nLength: TryInto::<u32>::try_into(size_of::<SECURITY_ATTRIBUTES>()).unwrap(),
+            lpSecurityDescriptor: self.descriptor.0,
+            bInheritHandle: BOOL::from(false),
+        }
+    }
+}
+
+impl Drop for Security {
+    fn drop(&mut self) {
+        unsafe { LocalFree(HLOCAL(self.descriptor.0)) };
+    }
+}
@@ -0,0 +1,102 @@
+//! Column widths the user has dragged or auto-fitted, remembered across
+//! runs in `%LOCALAPPDATA%\stapler\widths.tsv`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// What a column starts out as before anything is remembered
+pub const DEFAULT_COLUMN_WIDTH: i32 = 297;
+/// Narrower than this and there's no room for even a short name
+pub const MIN_COLUMN_WIDTH: i32 = 80;
+
+pub struct ColumnWidths {
+    /// Used for folders without their own width, and for everything when `per_folder` is off
+    default: i32,
+    /// Whether each folder gets its own width, rather than all sharing `default`
+    per_folder: bool,
+    /// By `SIGDN_DESKTOPABSOLUTEPARSING`
+    by_folder: HashMap<String, i32>,
+}
+
+impl Default for ColumnWidths {
+    fn default() -> ColumnWidths {
+        ColumnWidths {
+            default: DEFAULT_COLUMN_WIDTH,
+            per_folder: true,
+            by_folder: HashMap::new(),
+        }
+    }
+}
+
+impl ColumnWidths {
+    /// Reads the saved widths, falling back to the defaults if there aren't any
+    pub fn load() -> ColumnWidths {
+        let mut widths = ColumnWidths::default();
+        let text = match path().and_then(|path| Ok(fs::read_to_string(path)?)) {
+            Ok(text) => text,
+            Err(e) => {
+                log::debug!("no saved column widths: {e:#}");
+                return widths;
+            }
+        };
+        for line in text.lines() {
+            let mut fields = line.splitn(3, '\t');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some("default"), Some(width), None) => {
+                    if let Ok(width) = width.parse() {
+                        widths.default = width;
+                    }
+                }
+                (Some("per_folder"), Some(per_folder), None) => widths.per_folder = per_folder == "1",
+                (Some("folder"), Some(width), Some(folder)) => {
+                    if let Ok(width) = width.parse() {
+                        widths.by_folder.insert(folder.to_owned(), width);
+                    }
+                }
+                _ => log::warn!("skipping bad line in the column widths: {line:?}"),
+            }
+        }
+        widths
+    }
+    pub fn per_folder(&self) -> bool {
+        self.per_folder
+    }
+    pub fn set_per_folder(&mut self, per_folder: bool) {
+        self.per_folder = per_folder;
+        self.save();
+    }
+    /// The width for a column showing `folder`, or showing no folder at all if `None`
+    pub fn width_for(&self, folder: Option<&str>) -> i32 {
+        folder
+            .filter(|_| self.per_folder)
+            .and_then(|folder| self.by_folder.get(folder))
+            .copied()
+            .unwrap_or(self.default)
+    }
+    /// Keeps the width the user gave a column showing `folder`. Columns showing no
+    /// folder, like a selection or search, have nowhere of their own to keep it,
+    /// so they set the width everything else starts out with.
+    pub fn remember(&mut self, folder: Option<&str>, width: i32) {
+        match folder.filter(|_| self.per_folder) {
+            Some(folder) => {
+                self.by_folder.insert(folder.to_owned(), width);
+            }
+            None => self.default = width,
+        }
+        self.save();
+    }
+    fn save(&self) {
+        let mut text = format!("default\t{}\r\nper_folder\t{}\r\n", self.default, if self.per_folder { 1 } else { 0 });
+        for (folder, width) in &self.by_folder {
+            text.push_str(&format!("folder\t{width}\t{folder}\r\n"));
+        }
+        if let Err(e) = path().and_then(|path| Ok(fs::write(path, text)?)) {
+            log::warn!("could not save the column widths: {e:#}");
+        }
+    }
+}
+
+fn path() -> anyhow::Result<PathBuf> {
+    Ok(crate::app_data_dir()?.join("widths.tsv"))
+}